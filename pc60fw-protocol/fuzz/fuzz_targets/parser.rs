@@ -0,0 +1,38 @@
+//! Feeds arbitrary bytes through the parser in arbitrary chunks, and decodes whatever frames come
+//! out with every profile. Besides not panicking, the parser must never get stuck: once enough
+//! bytes have followed the garbage to finish any frame it might have started, a valid frame has
+//! to come out intact.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pc60fw_protocol::{Command, Message, Parser, Profile};
+
+/// Longer than any frame can be, as the length field is a single byte.
+const FLUSH: [u8; 260] = [0; 260];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&options, bytes)) = data.split_first() else {
+        return;
+    };
+    let chunk_size = usize::from(options & 0x3f) + 1;
+    let mut parser = Parser::new().skip_checksum(options & 0x40 != 0);
+
+    let mut frames = 0;
+    for chunk in bytes.chunks(chunk_size).chain([&FLUSH[..]]) {
+        parser.push(chunk);
+        while let Some(frame) = parser.next_frame() {
+            frames += 1;
+            Message::from_frame(&frame);
+            for profile in [Profile::PC_60FW, Profile::PC_68B, Profile::PC_66B, Profile::AP_20] {
+                profile.decode(&frame);
+            }
+        }
+    }
+    assert_eq!(parser.stats().frames, frames);
+
+    let expected = Command::KEEP_ALIVE.to_frame();
+    parser.push(&expected.encode());
+    assert_eq!(parser.next_frame(), Some(expected));
+    assert_eq!(parser.next_frame(), None);
+});
@@ -0,0 +1,191 @@
+//! Decoding of the protocol spoken by Contec's CMS50D+ and CMS50E oximeters, whose BLE modules
+//! pass through the same packets as their USB cables.
+//!
+//! Older firmware streams 5-byte packets on its own, with the top bit set only on the first byte
+//! so the stream can be synchronized. Newer firmware stays quiet until it gets
+//! [`start_command`], then sends 9-byte packets: a type byte, a byte holding the top bits of the
+//! data bytes that follow, and 7 data bytes with their top bit set. The first five data bytes of
+//! its live packets are laid out like the old packets. Both send 60 packets a second, each with a
+//! single waveform sample.
+
+use crate::{Message, ParserStats, ProbeStatus, Reading, WaveformSample};
+
+/// Assigned number of the serial service of the BLE modules.
+pub const SERVICE: u16 = 0xffe0;
+/// Assigned number of the characteristic packets arrive on and commands are written to.
+pub const CHARACTERISTIC: u16 = 0xffe1;
+
+/// Commands start with this, followed by the command byte and padding.
+const COMMAND_PREFIX: [u8; 2] = [0x7d, 0x81];
+const COMMAND_START_LIVE: u8 = 0xa1;
+/// Newer firmware stops sending unless it gets this every few seconds.
+const COMMAND_KEEP_ALIVE: u8 = 0xaf;
+/// Type of the newer firmware's live data packets.
+const TYPE_LIVE: u8 = 0x01;
+const LEGACY_LEN: usize = 5;
+const PACKET_LEN: usize = 9;
+/// Packets arrive at 60 Hz, but a reading a second is plenty.
+const PACKETS_PER_READING: u32 = 60;
+
+/// Values the device sends while it has no measurement.
+const NO_SPO2: u8 = 0x7f;
+const NO_PULSE_RATE: u8 = 0xff;
+
+fn command(command: u8) -> Vec<u8> {
+    let mut bytes = COMMAND_PREFIX.to_vec();
+    bytes.push(command);
+    bytes.resize(PACKET_LEN, 0x80);
+    bytes
+}
+
+/// Makes newer firmware start sending live data.
+///
+/// ```
+/// assert_eq!(
+///     pc60fw_protocol::cms50::start_command(),
+///     [0x7d, 0x81, 0xa1, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80]
+/// );
+/// ```
+pub fn start_command() -> Vec<u8> {
+    command(COMMAND_START_LIVE)
+}
+
+/// Keeps newer firmware sending, to be written every few seconds.
+pub fn keep_alive_command() -> Vec<u8> {
+    command(COMMAND_KEEP_ALIVE)
+}
+
+/// Incremental parser for both kinds of packet, which turns them into [`Message`]s: a
+/// [`Message::Waveform`] for every packet, and a [`Message::Parameters`] for every 60th.
+///
+/// ```
+/// use pc60fw_protocol::cms50::Parser;
+/// use pc60fw_protocol::Message;
+///
+/// let mut parser = Parser::new();
+/// // A stray byte, then signal strength 5, pleth 40, pulse rate 61, SpO2 97.
+/// parser.push(&[0x12, 0x85, 40, 0x03, 61, 97]);
+/// match parser.next_message() {
+///     Some(Message::Parameters(reading)) => assert_eq!((reading.spo2, reading.heart_rate), (97, 61)),
+///     other => panic!("unexpected {:?}", other),
+/// }
+/// assert!(matches!(parser.next_message(), Some(Message::Waveform(_))));
+/// assert_eq!(parser.next_message(), None);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Parser {
+    buffer: Vec<u8>,
+    /// Messages from a packet that haven't been handed out yet.
+    pending: Vec<Message>,
+    packets: u32,
+    stats: ParserStats,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds received bytes to the end of the buffer.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn stats(&self) -> ParserStats {
+        self.stats
+    }
+
+    /// Removes and returns the next message, or `None` if more bytes are needed.
+    pub fn next_message(&mut self) -> Option<Message> {
+        while self.pending.is_empty() {
+            let fields = self.next_packet()?;
+            if self.packets.is_multiple_of(PACKETS_PER_READING) {
+                self.pending.push(Message::Parameters(reading(&fields)));
+            }
+            self.packets = self.packets.wrapping_add(1);
+            self.pending.push(Message::Waveform(vec![WaveformSample {
+                pleth: fields[1] & 0x7f,
+                pulse_beat: fields[0] & 0x40 != 0,
+            }]));
+            self.pending.reverse();
+        }
+        self.pending.pop()
+    }
+
+    /// Removes the next live packet and returns its first five fields, laid out like an old
+    /// packet without the sync bit.
+    fn next_packet(&mut self) -> Option<[u8; LEGACY_LEN]> {
+        loop {
+            let &first = self.buffer.first()?;
+            if first & 0x80 != 0 {
+                // An old packet, unless it's the middle of a new one.
+                if self.buffer.len() < LEGACY_LEN {
+                    return None;
+                }
+                if self.buffer[1..LEGACY_LEN].iter().any(|b| b & 0x80 != 0) {
+                    self.discard(1);
+                    continue;
+                }
+                let mut fields = [0; LEGACY_LEN];
+                fields.copy_from_slice(&self.buffer[..LEGACY_LEN]);
+                fields[0] &= 0x7f;
+                self.buffer.drain(..LEGACY_LEN);
+                self.stats.frames += 1;
+                return Some(fields);
+            }
+
+            // A new packet is a type byte followed by bytes with the top bit set.
+            let run = self.buffer[1..].iter().take_while(|b| *b & 0x80 != 0).count();
+            if run < PACKET_LEN - 1 && 1 + run == self.buffer.len() {
+                return None;
+            }
+            if first != TYPE_LIVE || run < PACKET_LEN - 1 {
+                // Only the type byte, in case what follows is an old packet.
+                self.discard(1);
+                continue;
+            }
+            let high_bits = self.buffer[1];
+            let mut fields = [0; LEGACY_LEN];
+            for (i, field) in fields.iter_mut().enumerate() {
+                *field = (self.buffer[2 + i] & 0x7f) | ((high_bits >> i) & 1) << 7;
+            }
+            self.buffer.drain(..PACKET_LEN);
+            self.stats.frames += 1;
+            return Some(fields);
+        }
+    }
+
+    fn discard(&mut self, count: usize) {
+        self.buffer.drain(..count);
+        self.stats.discarded_bytes += count as u64;
+    }
+}
+
+fn reading(fields: &[u8; LEGACY_LEN]) -> Reading {
+    let finger_out = fields[0] & 0x10 != 0;
+    let probe_error = fields[2] & 0x10 != 0;
+    let searching = fields[2] & 0x20 != 0;
+    // Old packets have the pulse rate's top bit in the third byte, new ones in the fourth.
+    let pulse_rate = ((fields[2] & 0x40) << 1) | fields[3];
+    let spo2 = fields[4] & 0x7f;
+    let (spo2, heart_rate) = if spo2 == NO_SPO2 || pulse_rate == NO_PULSE_RATE || finger_out {
+        (0, 0)
+    } else {
+        (spo2, pulse_rate)
+    };
+    let probe_status = if finger_out || probe_error {
+        ProbeStatus::NoFinger
+    } else if searching || (spo2 == 0 && heart_rate == 0) {
+        ProbeStatus::Searching
+    } else {
+        ProbeStatus::Stable
+    };
+    Reading {
+        spo2,
+        heart_rate,
+        perfusion_index: 0,
+        probe_status,
+        pulse_searching: searching,
+        signal_strength: (fields[0] & 0x0f).min(Reading::MAX_SIGNAL_STRENGTH),
+    }
+}
@@ -0,0 +1,37 @@
+use crate::message::TOKEN_DATA;
+use crate::Frame;
+
+/// Frame type of the command that turns the once-per-second parameter frames on or off.
+const TYPE_PARAMETER_OUTPUT: u8 = 0x84;
+/// Frame type of the command that turns the waveform frames on or off.
+const TYPE_WAVEFORM_OUTPUT: u8 = 0x85;
+
+/// A command the host can write to the device's Nordic UART TX characteristic.
+///
+/// These follow the host commands of Creative Medical's serial oximeter protocol, which the
+/// PC-60FW's frames are a variant of. Not every firmware needs them: many devices stream as soon
+/// as notifications are enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Start or stop sending parameter frames.
+    ParameterOutput(bool),
+    /// Start or stop sending waveform frames.
+    WaveformOutput(bool),
+}
+
+impl Command {
+    /// The commands that start a continuous transfer of everything the device measures.
+    pub const START: [Command; 2] = [Command::ParameterOutput(true), Command::WaveformOutput(true)];
+
+    /// Sent periodically during long sessions. Enabling output that's already on is harmless, and
+    /// reminds firmware that stops streaming when the host goes quiet that someone's listening.
+    pub const KEEP_ALIVE: Command = Command::ParameterOutput(true);
+
+    pub fn to_frame(self) -> Frame {
+        let (kind, enable) = match self {
+            Command::ParameterOutput(enable) => (TYPE_PARAMETER_OUTPUT, enable),
+            Command::WaveformOutput(enable) => (TYPE_WAVEFORM_OUTPUT, enable),
+        };
+        Frame::new(TOKEN_DATA, vec![kind, u8::from(enable)])
+    }
+}
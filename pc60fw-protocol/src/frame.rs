@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::Message;
+
+/// Every frame starts with these two bytes.
+pub const HEADER: [u8; 2] = [0xaa, 0x55];
+
+/// Computes the checksum the device appends to its frames: CRC-8/MAXIM (polynomial 0x31,
+/// reflected) over every byte of the frame before the checksum, header included.
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| {
+        let mut crc = crc ^ byte;
+        for _ in 0..8 {
+            crc = if crc & 0x01 != 0 { (crc >> 1) ^ 0x8c } else { crc >> 1 };
+        }
+        crc
+    })
+}
+
+/// A single frame sent by, or to, the device.
+///
+/// On the wire, a frame looks like this:
+///
+/// ```text
+/// aa 55 <token> <length> <payload...> <checksum>
+/// ```
+///
+/// where `length` counts the payload plus the checksum byte. The first payload byte is the
+/// frame type within the token's group, e.g. token `0x0f` type `0x01` is a parameter frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub token: u8,
+    pub payload: Vec<u8>,
+    pub checksum: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// The bytes don't start with the header.
+    BadHeader,
+    /// Fewer bytes were given than the frame's length field calls for.
+    Truncated,
+    /// The length field is too small to hold the checksum.
+    BadLength,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::BadHeader => write!(f, "frame doesn't start with the header"),
+            FrameError::Truncated => write!(f, "frame is truncated"),
+            FrameError::BadLength => write!(f, "frame length field is invalid"),
+        }
+    }
+}
+
+impl Error for FrameError {}
+
+impl Frame {
+    /// A frame with the given contents and a correct checksum, e.g. to send to the device.
+    ///
+    /// ```
+    /// use pc60fw_protocol::Frame;
+    ///
+    /// let frame = Frame::new(0x0f, vec![0x03, 0x02]);
+    /// assert_eq!(frame.encode(), [0xaa, 0x55, 0x0f, 0x03, 0x03, 0x02, 0x43]);
+    /// assert_eq!(Frame::parse(&frame.encode()), Ok(frame));
+    /// ```
+    pub fn new(token: u8, payload: Vec<u8>) -> Frame {
+        let mut frame = Frame { token, payload, checksum: 0 };
+        let bytes = frame.encode();
+        frame.checksum = checksum(&bytes[..bytes.len() - 1]);
+        frame
+    }
+
+    /// Parses a frame that starts at the beginning of `bytes`. Any bytes after the end of the
+    /// frame are ignored.
+    pub fn parse(bytes: &[u8]) -> Result<Frame, FrameError> {
+        Frame::parse_with_header(bytes, HEADER)
+    }
+
+    /// Like [`Frame::parse`], for devices that start their frames with something other than
+    /// [`HEADER`].
+    pub fn parse_with_header(bytes: &[u8], header: [u8; 2]) -> Result<Frame, FrameError> {
+        if bytes.iter().zip(header.iter()).any(|(a, b)| a != b) {
+            return Err(FrameError::BadHeader);
+        }
+        if bytes.len() < 4 {
+            return Err(FrameError::Truncated);
+        }
+        let length = bytes[3] as usize;
+        if length == 0 {
+            return Err(FrameError::BadLength);
+        }
+        if bytes.len() < 4 + length {
+            return Err(FrameError::Truncated);
+        }
+        Ok(Frame {
+            token: bytes[2],
+            payload: bytes[4..3 + length].to_vec(),
+            checksum: bytes[3 + length],
+        })
+    }
+
+    /// The frame's bytes on the wire.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&HEADER);
+        bytes.push(self.token);
+        bytes.push((self.payload.len() + 1) as u8);
+        bytes.extend_from_slice(&self.payload);
+        bytes.push(self.checksum);
+        bytes
+    }
+
+    /// Whether the checksum byte matches the rest of the frame.
+    pub fn is_checksum_valid(&self) -> bool {
+        let bytes = self.encode();
+        checksum(&bytes[..bytes.len() - 1]) == self.checksum
+    }
+
+    /// Number of bytes the frame takes up on the wire.
+    pub fn encoded_len(&self) -> usize {
+        HEADER.len() + 2 + self.payload.len() + 1
+    }
+
+    /// The frame type within the token's group, if the frame has a payload.
+    pub fn frame_type(&self) -> Option<u8> {
+        self.payload.first().copied()
+    }
+
+    /// Interprets the contents of the frame.
+    pub fn decode(&self) -> Message {
+        Message::from_frame(self)
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// One piece of identification the device sends after a connection is made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InfoField {
+    SoftwareVersion(String),
+    HardwareVersion(String),
+    SerialNumber(String),
+    Model(String),
+}
+
+/// Everything the device has told us about itself so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub software_version: Option<String>,
+    pub hardware_version: Option<String>,
+    pub serial_number: Option<String>,
+    pub model: Option<String>,
+}
+
+impl DeviceInfo {
+    pub fn update(&mut self, field: InfoField) {
+        match field {
+            InfoField::SoftwareVersion(v) => self.software_version = Some(v),
+            InfoField::HardwareVersion(v) => self.hardware_version = Some(v),
+            InfoField::SerialNumber(v) => self.serial_number = Some(v),
+            InfoField::Model(v) => self.model = Some(v),
+        }
+    }
+
+    /// Whether every field has been received.
+    pub fn is_complete(&self) -> bool {
+        self.software_version.is_some()
+            && self.hardware_version.is_some()
+            && self.serial_number.is_some()
+            && self.model.is_some()
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = "unknown".to_string();
+        writeln!(f, "Model: {}", self.model.as_ref().unwrap_or(&unknown))?;
+        writeln!(f, "Serial number: {}", self.serial_number.as_ref().unwrap_or(&unknown))?;
+        writeln!(f, "Software version: {}", self.software_version.as_ref().unwrap_or(&unknown))?;
+        write!(f, "Hardware version: {}", self.hardware_version.as_ref().unwrap_or(&unknown))
+    }
+}
@@ -0,0 +1,28 @@
+//! Decoding of the protocol spoken by the PC-60FW pulse oximeter.
+//!
+//! This crate has no dependencies and does no I/O, so it can be reused with any BLE stack. Push
+//! the bytes received from the Nordic UART RX characteristic into a [`Parser`], and decode the
+//! [`Frame`]s it hands back into [`Message`]s. [`Command`]s go the other way, to the TX
+//! characteristic. Its siblings from Creative Medical, the PC-68B, PC-66B and AP-20, speak the
+//! same protocol with different frame layouts, which their [`Profile`]s decode.
+//!
+//! Oximeters that offer the standard Bluetooth Pulse Oximeter Service instead can be decoded with
+//! the [`plx`] module, into the same [`Reading`]s. So can Viatom's ring oximeters, with the
+//! [`viatom`] module, and Contec's CMS50D+ and CMS50E, with the [`cms50`] module.
+
+pub mod cms50;
+mod command;
+mod frame;
+mod info;
+mod message;
+mod parser;
+pub mod plx;
+mod profile;
+pub mod viatom;
+
+pub use command::Command;
+pub use frame::{checksum, Frame, FrameError, HEADER};
+pub use info::{DeviceInfo, InfoField};
+pub use message::{BatteryLevel, Message, ProbeStatus, Reading, WaveformSample};
+pub use parser::{Parser, ParserStats};
+pub use profile::Profile;
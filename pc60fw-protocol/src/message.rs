@@ -0,0 +1,149 @@
+use std::fmt;
+
+use crate::{Frame, InfoField, Profile};
+
+/// Token for frames carrying measurement data.
+pub(crate) const TOKEN_DATA: u8 = 0x0f;
+
+/// Token for frames identifying the device.
+const TOKEN_INFO: u8 = 0xf0;
+/// Frame types of the identification frames, each carrying an ASCII string.
+const TYPE_SOFTWARE_VERSION: u8 = 0x01;
+const TYPE_HARDWARE_VERSION: u8 = 0x02;
+const TYPE_SERIAL_NUMBER: u8 = 0x03;
+const TYPE_MODEL: u8 = 0x04;
+
+/// The decoded contents of a [`Frame`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// The current measurement, sent about once a second.
+    Parameters(Reading),
+    /// A few plethysmograph samples, sent many times a second.
+    Waveform(Vec<WaveformSample>),
+    /// Battery charge, sent every few seconds.
+    Battery(BatteryLevel),
+    /// Identification of the device, sent once after connecting.
+    Info(InfoField),
+    /// A frame this crate doesn't know how to decode.
+    Unknown(Frame),
+}
+
+/// A measurement from a parameter frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reading {
+    /// Oxygen saturation, in percent.
+    pub spo2: u8,
+    /// Pulse rate, in beats per minute, up to 255 even on devices that could send more.
+    pub heart_rate: u8,
+    /// Perfusion index, in tenths of a percent.
+    pub perfusion_index: u8,
+    /// Whether there's a finger in the probe and the device has locked on to a pulse.
+    pub probe_status: ProbeStatus,
+    /// Set while the device is searching for a pulse, e.g. after the finger moved.
+    pub pulse_searching: bool,
+    /// Strength of the optical signal, from 0 to [`Reading::MAX_SIGNAL_STRENGTH`].
+    pub signal_strength: u8,
+}
+
+/// State of the finger probe, from the parameter frame's status byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeStatus {
+    /// No finger is inserted.
+    NoFinger,
+    /// A finger is inserted but the device hasn't found a pulse yet.
+    Searching,
+    /// The device has a pulse and the values are meaningful.
+    Stable,
+}
+
+impl fmt::Display for ProbeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProbeStatus::NoFinger => "no-finger",
+            ProbeStatus::Searching => "searching",
+            ProbeStatus::Stable => "stable",
+        })
+    }
+}
+
+impl Reading {
+    /// The device sends all zeroes while it has no measurement, e.g. before a finger is inserted.
+    pub fn is_null(&self) -> bool {
+        self.spo2 == 0 && self.heart_rate == 0
+    }
+
+    pub const MAX_SIGNAL_STRENGTH: u8 = 8;
+    /// Readings with a signal strength below this are too noisy to trust.
+    pub const MIN_GOOD_SIGNAL_STRENGTH: u8 = 2;
+
+    /// Whether the reading comes from a stable, strong enough signal to be trusted.
+    pub fn is_good_quality(&self) -> bool {
+        self.probe_status == ProbeStatus::Stable
+            && !self.pulse_searching
+            && self.signal_strength >= Self::MIN_GOOD_SIGNAL_STRENGTH
+    }
+
+    /// Perfusion index, in percent.
+    pub fn perfusion_index_percent(&self) -> f32 {
+        self.perfusion_index as f32 / 10.0
+    }
+}
+
+/// A single point of the plethysmograph waveform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaveformSample {
+    /// Pleth amplitude, 0-127. This is what the device draws as its pulse curve and bar graph.
+    pub pleth: u8,
+    /// Set on the sample where the device detected a heart beat.
+    pub pulse_beat: bool,
+}
+
+impl WaveformSample {
+    pub(crate) fn from_byte(byte: u8) -> Self {
+        WaveformSample {
+            pleth: byte & 0x7f,
+            pulse_beat: byte & 0x80 != 0,
+        }
+    }
+}
+
+/// Battery charge as shown on the device's screen, from 0 (about to die) to 3 bars (full).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BatteryLevel(pub u8);
+
+impl BatteryLevel {
+    pub const MAX: BatteryLevel = BatteryLevel(3);
+
+    /// The device is on its last bar and will turn off soon.
+    pub fn is_low(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Rough charge in percent.
+    pub fn percent(&self) -> u8 {
+        (self.0.min(Self::MAX.0) as u16 * 100 / Self::MAX.0 as u16) as u8
+    }
+}
+
+impl Message {
+    /// Interprets a frame sent by a PC-60FW. Its siblings are decoded with [`Profile::decode`].
+    pub fn from_frame(frame: &Frame) -> Message {
+        Profile::PC_60FW.decode(frame)
+    }
+}
+
+/// Decodes the identification frames every model sends.
+pub(crate) fn decode_info(frame: &Frame) -> Option<Message> {
+    match (frame.token, frame.payload.as_slice()) {
+        (TOKEN_INFO, [kind @ TYPE_SOFTWARE_VERSION..=TYPE_MODEL, text @ ..]) => {
+            let text = String::from_utf8_lossy(text).trim_end_matches('\0').trim().to_string();
+            Some(Message::Info(match *kind {
+                TYPE_SOFTWARE_VERSION => InfoField::SoftwareVersion(text),
+                TYPE_HARDWARE_VERSION => InfoField::HardwareVersion(text),
+                TYPE_SERIAL_NUMBER => InfoField::SerialNumber(text),
+                _ => InfoField::Model(text),
+            }))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{checksum, Frame, FrameError, HEADER};
+
+/// Incremental, sans-IO frame parser.
+///
+/// BLE notifications don't necessarily line up with frame boundaries, so bytes are pushed in
+/// whatever chunks they arrive in, and complete frames are pulled out with
+/// [`Parser::next_frame`]. A frame may be split across several chunks, and a chunk may hold
+/// several frames. Bytes that can't be the start of a frame are skipped, and frames with a bad
+/// checksum are dropped.
+///
+/// When a frame turns out to be corrupt, e.g. because the rest of it was lost and the bytes that
+/// followed belong to the next frame, only its header is dropped and the search for a header
+/// starts over from there, so the frames hiding behind it aren't lost as well.
+///
+/// ```
+/// use pc60fw_protocol::Parser;
+///
+/// let mut parser = Parser::new();
+/// parser.push(&[0x00, 0xaa, 0x55, 0x0f, 0x03]);
+/// assert!(parser.next_frame().is_none());
+/// parser.push(&[0x03, 0x02, 0x43, 0xaa, 0x55, 0x0f, 0x03, 0x03, 0x02, 0x43]);
+/// assert_eq!(parser.next_frame().unwrap().payload, [0x03, 0x02]);
+/// assert_eq!(parser.next_frame().unwrap().payload, [0x03, 0x02]);
+/// assert!(parser.next_frame().is_none());
+/// assert_eq!(parser.stats().frames, 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Parser {
+    buffer: Vec<u8>,
+    stats: ParserStats,
+    skip_checksum: bool,
+    header: [u8; 2],
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser {
+            buffer: Vec::new(),
+            stats: ParserStats::default(),
+            skip_checksum: false,
+            header: HEADER,
+        }
+    }
+}
+
+/// Counters of what the parser has seen, to make flaky links visible.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParserStats {
+    /// Frames successfully parsed.
+    pub frames: u64,
+    /// Frames dropped because of a bad checksum.
+    pub checksum_errors: u64,
+    /// Bytes skipped because they weren't part of a frame.
+    pub discarded_bytes: u64,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds received bytes to the end of the buffer.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Accept frames regardless of their checksum, for firmware that computes it differently.
+    pub fn skip_checksum(mut self, skip: bool) -> Self {
+        self.skip_checksum = skip;
+        self
+    }
+
+    /// Look for frames starting with `header` instead of [`HEADER`], for rebrands that changed it.
+    pub fn header(mut self, header: [u8; 2]) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn stats(&self) -> ParserStats {
+        self.stats
+    }
+
+    /// Removes and returns the next complete frame, or `None` if more bytes are needed.
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        loop {
+            match self.buffer.windows(2).position(|w| w == self.header) {
+                Some(start) => self.discard(start),
+                None => {
+                    // Keep a trailing first header byte, the rest of the header may be on its way.
+                    let keep = usize::from(self.buffer.last() == Some(&self.header[0]));
+                    self.discard(self.buffer.len() - keep);
+                    return None;
+                }
+            }
+
+            match Frame::parse_with_header(&self.buffer, self.header) {
+                // Checked against the bytes received, as the checksum covers the header too.
+                Ok(frame) if self.skip_checksum || checksum(&self.buffer[..frame.encoded_len() - 1]) == frame.checksum => {
+                    self.buffer.drain(..frame.encoded_len());
+                    self.stats.frames += 1;
+                    return Some(frame);
+                }
+                Ok(_) => {
+                    self.stats.checksum_errors += 1;
+                    self.discard(1);
+                }
+                Err(FrameError::Truncated) => return None,
+                // Not actually a frame, look for the next header.
+                Err(FrameError::BadHeader | FrameError::BadLength) => self.discard(1),
+            }
+        }
+    }
+
+    fn discard(&mut self, count: usize) {
+        self.buffer.drain(..count);
+        self.stats.discarded_bytes += count as u64;
+    }
+}
@@ -0,0 +1,186 @@
+//! Decoding of the Bluetooth SIG Pulse Oximeter Service (PLX), which standards-compliant
+//! oximeters offer instead of, or as well as, a vendor protocol.
+//!
+//! Measurements are turned into the same [`Reading`]s as the PC-60FW's parameter frames. PLX has
+//! no notion of signal strength, so [`Reading::signal_strength`] is the maximum unless the device
+//! flags a weak or questionable signal, in which case it's 1.
+
+use crate::{ProbeStatus, Reading};
+
+/// Assigned number of the Pulse Oximeter Service.
+pub const SERVICE: u16 = 0x1822;
+/// Assigned number of the PLX Spot-check Measurement characteristic, which is indicated.
+pub const SPOT_CHECK_MEASUREMENT: u16 = 0x2a5e;
+/// Assigned number of the PLX Continuous Measurement characteristic, which is notified.
+pub const CONTINUOUS_MEASUREMENT: u16 = 0x2a5f;
+
+/// Continuous Measurement flags saying which optional fields follow the normal SpO2/PR pair.
+const CONTINUOUS_FAST: u8 = 0x01;
+const CONTINUOUS_SLOW: u8 = 0x02;
+const CONTINUOUS_MEASUREMENT_STATUS: u8 = 0x04;
+const CONTINUOUS_SENSOR_STATUS: u8 = 0x08;
+const CONTINUOUS_PULSE_AMPLITUDE: u8 = 0x10;
+
+/// Spot-check Measurement flags saying which optional fields follow the SpO2/PR pair.
+const SPOT_CHECK_TIMESTAMP: u8 = 0x01;
+const SPOT_CHECK_MEASUREMENT_STATUS: u8 = 0x02;
+const SPOT_CHECK_SENSOR_STATUS: u8 = 0x04;
+const SPOT_CHECK_PULSE_AMPLITUDE: u8 = 0x08;
+
+/// Measurement Status bits.
+const MEASUREMENT_UNAVAILABLE: u16 = 1 << 13;
+const QUESTIONABLE_MEASUREMENT: u16 = 1 << 14;
+const INVALID_MEASUREMENT: u16 = 1 << 15;
+
+/// Device and Sensor Status bits.
+const INADEQUATE_SIGNAL: u32 = 1 << 3;
+const POOR_SIGNAL: u32 = 1 << 4;
+const SIGNAL_ANALYSIS_ONGOING: u32 = 1 << 9;
+const SENSOR_UNCONNECTED_TO_USER: u32 = 1 << 11;
+const SENSOR_DISCONNECTED: u32 = 1 << 15;
+
+/// Decodes an IEEE 11073-20601 16-bit SFLOAT. Returns `None` for NaN, infinities, and the other
+/// special values, which devices send when they have no measurement.
+///
+/// ```
+/// use pc60fw_protocol::plx::sfloat;
+///
+/// assert_eq!(sfloat(0x0061), Some(97.0));
+/// assert_eq!(sfloat(0xf02a), Some(4.2));
+/// assert_eq!(sfloat(0x07ff), None);
+/// ```
+pub fn sfloat(raw: u16) -> Option<f32> {
+    let mantissa = raw & 0x0fff;
+    if (0x07fe..=0x0802).contains(&mantissa) {
+        return None;
+    }
+    // Both parts are two's complement, the exponent in the top nibble.
+    let mantissa = ((mantissa << 4) as i16 >> 4) as f32;
+    let exponent = (raw as i16) >> 12;
+    // Rounded, as most powers of ten can't be represented exactly.
+    let value = mantissa * 10f32.powi(exponent.into());
+    let scale = 10f32.powi((-exponent).max(0).into());
+    Some((value * scale).round() / scale)
+}
+
+/// Reads little-endian fields off the front of a measurement.
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(field)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<u32> {
+        self.take(3).map(|b| u32::from_le_bytes([b[0], b[1], b[2], 0]))
+    }
+}
+
+/// The fields both measurement characteristics share, once pulled out of their layouts.
+struct Measurement {
+    spo2: Option<f32>,
+    pulse_rate: Option<f32>,
+    measurement_status: u16,
+    sensor_status: u32,
+    pulse_amplitude: Option<f32>,
+}
+
+impl Measurement {
+    fn to_reading(&self) -> Reading {
+        let unavailable = self.measurement_status & (MEASUREMENT_UNAVAILABLE | INVALID_MEASUREMENT) != 0;
+        let (spo2, heart_rate) = match (self.spo2, self.pulse_rate) {
+            (Some(spo2), Some(pulse_rate)) if !unavailable => {
+                (spo2.round().clamp(0.0, 100.0) as u8, pulse_rate.round().clamp(0.0, 255.0) as u8)
+            }
+            _ => (0, 0),
+        };
+        let pulse_searching = self.sensor_status & SIGNAL_ANALYSIS_ONGOING != 0;
+        let probe_status = if self.sensor_status & (SENSOR_DISCONNECTED | SENSOR_UNCONNECTED_TO_USER) != 0 {
+            ProbeStatus::NoFinger
+        } else if pulse_searching || (spo2 == 0 && heart_rate == 0) {
+            ProbeStatus::Searching
+        } else {
+            ProbeStatus::Stable
+        };
+        let weak = self.sensor_status & (INADEQUATE_SIGNAL | POOR_SIGNAL) != 0
+            || self.measurement_status & QUESTIONABLE_MEASUREMENT != 0;
+        Reading {
+            spo2,
+            heart_rate,
+            perfusion_index: self
+                .pulse_amplitude
+                .map_or(0, |pai| (pai * 10.0).round().clamp(0.0, 255.0) as u8),
+            probe_status,
+            pulse_searching,
+            signal_strength: if weak { 1 } else { Reading::MAX_SIGNAL_STRENGTH },
+        }
+    }
+}
+
+/// Decodes a PLX Continuous Measurement, or returns `None` if it's too short.
+///
+/// ```
+/// use pc60fw_protocol::plx::continuous_measurement;
+/// use pc60fw_protocol::ProbeStatus;
+///
+/// // SpO2 97%, pulse rate 61, pulse amplitude index 4.2%.
+/// let reading = continuous_measurement(&[0x10, 0x61, 0x00, 0x3d, 0x00, 0x2a, 0xf0]).unwrap();
+/// assert_eq!((reading.spo2, reading.heart_rate, reading.perfusion_index), (97, 61, 42));
+/// assert_eq!(reading.probe_status, ProbeStatus::Stable);
+/// ```
+pub fn continuous_measurement(bytes: &[u8]) -> Option<Reading> {
+    let (&flags, rest) = bytes.split_first()?;
+    let mut fields = Fields(rest);
+    let spo2 = fields.u16()?;
+    let pulse_rate = fields.u16()?;
+    // The fast and slow averages aren't needed, the normal ones are what the device displays.
+    if flags & CONTINUOUS_FAST != 0 {
+        fields.take(4)?;
+    }
+    if flags & CONTINUOUS_SLOW != 0 {
+        fields.take(4)?;
+    }
+    let measurement_status = if flags & CONTINUOUS_MEASUREMENT_STATUS != 0 { fields.u16()? } else { 0 };
+    let sensor_status = if flags & CONTINUOUS_SENSOR_STATUS != 0 { fields.u24()? } else { 0 };
+    let pulse_amplitude = if flags & CONTINUOUS_PULSE_AMPLITUDE != 0 { sfloat(fields.u16()?) } else { None };
+    let measurement = Measurement {
+        spo2: sfloat(spo2),
+        pulse_rate: sfloat(pulse_rate),
+        measurement_status,
+        sensor_status,
+        pulse_amplitude,
+    };
+    Some(measurement.to_reading())
+}
+
+/// Decodes a PLX Spot-check Measurement, or returns `None` if it's too short. Its timestamp is
+/// ignored, as readings are timed by when they arrive.
+pub fn spot_check_measurement(bytes: &[u8]) -> Option<Reading> {
+    let (&flags, rest) = bytes.split_first()?;
+    let mut fields = Fields(rest);
+    let spo2 = fields.u16()?;
+    let pulse_rate = fields.u16()?;
+    if flags & SPOT_CHECK_TIMESTAMP != 0 {
+        fields.take(7)?;
+    }
+    let measurement_status = if flags & SPOT_CHECK_MEASUREMENT_STATUS != 0 { fields.u16()? } else { 0 };
+    let sensor_status = if flags & SPOT_CHECK_SENSOR_STATUS != 0 { fields.u24()? } else { 0 };
+    let pulse_amplitude = if flags & SPOT_CHECK_PULSE_AMPLITUDE != 0 { sfloat(fields.u16()?) } else { None };
+    let measurement = Measurement {
+        spo2: sfloat(spo2),
+        pulse_rate: sfloat(pulse_rate),
+        measurement_status,
+        sensor_status,
+        pulse_amplitude,
+    };
+    Some(measurement.to_reading())
+}
@@ -0,0 +1,134 @@
+use crate::message::{decode_info, TOKEN_DATA};
+use crate::{BatteryLevel, Frame, Message, ProbeStatus, Reading, WaveformSample, HEADER};
+
+/// Parameter frame status byte bit set when the probe reports no finger inserted.
+const STATUS_PROBE_OFF: u8 = 0x02;
+/// Parameter frame status byte bit set while the device is looking for a pulse.
+const STATUS_PULSE_SEARCHING: u8 = 0x04;
+/// Parameter frame status byte bits holding the signal strength.
+const STATUS_SIGNAL_STRENGTH_MASK: u8 = 0xf0;
+
+/// Where one of Creative Medical's oximeters puts things in its frames.
+///
+/// The PC-60FW and its siblings share the framing and the identification frames, but not the
+/// frame types of their data frames or the layout of their parameter frames. Offsets are into the
+/// frame's payload, so the frame type is at 0. Only the PC-60FW's profile has been checked
+/// against a real device; the others follow Creative's serial protocol documents.
+///
+/// ```
+/// use pc60fw_protocol::{BatteryLevel, Frame, Message, Profile};
+///
+/// let frame = Frame::new(0x0f, vec![0x03, 0x02]);
+/// assert_eq!(Profile::PC_60FW.decode(&frame), Message::Battery(BatteryLevel(2)));
+/// // The PC-66B doesn't report its battery, so the same frame means nothing to it.
+/// assert!(matches!(Profile::PC_66B.decode(&frame), Message::Unknown(_)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    /// The bytes every frame starts with, to be given to [`crate::Parser::header`].
+    pub header: [u8; 2],
+    /// Token of the frames carrying measurements.
+    pub data_token: u8,
+    /// Frame type of the once-per-second SpO2/pulse rate frame.
+    pub parameters_type: u8,
+    /// Frame type of the plethysmograph waveform frame.
+    pub waveform_type: u8,
+    /// Frame type of the battery status frame, for models that send one.
+    pub battery_type: Option<u8>,
+    pub spo2_offset: usize,
+    pub pulse_rate_offset: usize,
+    /// Offset of the pulse rate's high byte, for models that send one. Pulse rates past 255 are
+    /// still read as 255, as that's as far as [`Reading::heart_rate`] goes.
+    pub pulse_rate_high_offset: Option<usize>,
+    /// Offset of the perfusion index, in tenths of a percent, for models that measure it.
+    pub perfusion_index_offset: Option<usize>,
+    /// Offset of the status byte with the probe state and signal strength.
+    pub status_offset: Option<usize>,
+}
+
+impl Profile {
+    /// The PC-60FW fingertip oximeter and its rebrands.
+    pub const PC_60FW: Profile = Profile {
+        header: HEADER,
+        data_token: TOKEN_DATA,
+        parameters_type: 0x01,
+        waveform_type: 0x02,
+        battery_type: Some(0x03),
+        spo2_offset: 1,
+        pulse_rate_offset: 2,
+        pulse_rate_high_offset: None,
+        perfusion_index_offset: Some(4),
+        status_offset: Some(5),
+    };
+
+    /// The PC-68B handheld oximeter, which sends the pulse rate as two bytes. Rates above 255,
+    /// which only its neonatal probes could see, are read as 255.
+    pub const PC_68B: Profile = Profile {
+        pulse_rate_high_offset: Some(3),
+        ..Profile::PC_60FW
+    };
+
+    /// The PC-66B fingertip oximeter, which measures neither perfusion index nor its battery.
+    pub const PC_66B: Profile = Profile {
+        battery_type: None,
+        perfusion_index_offset: None,
+        status_offset: Some(4),
+        ..Profile::PC_60FW
+    };
+
+    /// The AP-20 wrist oximeter. Its frame type 0x03 carries nasal airflow, which isn't decoded,
+    /// so its battery frame comes after it.
+    pub const AP_20: Profile = Profile {
+        battery_type: Some(0x04),
+        ..Profile::PC_60FW
+    };
+
+    /// Interprets the contents of a frame sent by a device with this profile.
+    pub fn decode(&self, frame: &Frame) -> Message {
+        let decoded = match frame.frame_type() {
+            Some(kind) if frame.token == self.data_token => self.decode_data(kind, &frame.payload),
+            _ => decode_info(frame),
+        };
+        decoded.unwrap_or_else(|| Message::Unknown(frame.clone()))
+    }
+
+    fn decode_data(&self, kind: u8, payload: &[u8]) -> Option<Message> {
+        if kind == self.parameters_type {
+            self.reading(payload).map(Message::Parameters)
+        } else if kind == self.waveform_type {
+            Some(Message::Waveform(payload[1..].iter().copied().map(WaveformSample::from_byte).collect()))
+        } else if Some(kind) == self.battery_type {
+            payload.get(1).map(|&level| Message::Battery(BatteryLevel(level)))
+        } else {
+            None
+        }
+    }
+
+    fn reading(&self, payload: &[u8]) -> Option<Reading> {
+        let field = |offset: Option<usize>| offset.and_then(|offset| payload.get(offset)).copied();
+        let spo2 = *payload.get(self.spo2_offset)?;
+        let pulse_rate_low = *payload.get(self.pulse_rate_offset)?;
+        let pulse_rate = u16::from_le_bytes([pulse_rate_low, field(self.pulse_rate_high_offset).unwrap_or(0)]);
+        let heart_rate = pulse_rate.min(u16::from(u8::MAX)) as u8;
+        let is_null = spo2 == 0 && heart_rate == 0;
+        let status = field(self.status_offset).unwrap_or(0);
+        Some(Reading {
+            spo2,
+            heart_rate,
+            perfusion_index: field(self.perfusion_index_offset).unwrap_or(0),
+            probe_status: probe_status(status, is_null),
+            pulse_searching: status & STATUS_PULSE_SEARCHING != 0,
+            signal_strength: ((status & STATUS_SIGNAL_STRENGTH_MASK) >> 4).min(Reading::MAX_SIGNAL_STRENGTH),
+        })
+    }
+}
+
+fn probe_status(status: u8, is_null: bool) -> ProbeStatus {
+    if status & STATUS_PROBE_OFF != 0 {
+        ProbeStatus::NoFinger
+    } else if status & STATUS_PULSE_SEARCHING != 0 || is_null {
+        ProbeStatus::Searching
+    } else {
+        ProbeStatus::Stable
+    }
+}
@@ -0,0 +1,109 @@
+//! Decodes every notification stream in `tests/frames` and checks that what comes out matches
+//! what the file says should, so changes to the parser or profiles can't quietly change what
+//! gets decoded.
+//!
+//! Each file is a list of lines:
+//!
+//! ```text
+//! # A comment
+//! @profile pc-68b          the profile to decode with, PC-60FW if not given
+//! < aa550f03030243         a notification, in hex
+//! > Battery(BatteryLevel(2))   the next message decoded, as printed with {:?}
+//! @frames 11               how many frames the parser should have counted at the end
+//! @checksum-errors 1       and how many it should have dropped
+//! ```
+//!
+//! The hex column of a `--record-raw` capture can be pasted in as notifications.
+
+use pc60fw_protocol::{Parser, Profile};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+fn profile_named(name: &str) -> Profile {
+    match name {
+        "pc-60fw" => Profile::PC_60FW,
+        "pc-68b" => Profile::PC_68B,
+        "pc-66b" => Profile::PC_66B,
+        "ap-20" => Profile::AP_20,
+        _ => panic!("unknown profile {:?}", name),
+    }
+}
+
+fn hex(text: &str) -> Vec<u8> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    assert!(digits.len().is_multiple_of(2), "odd number of hex digits in {:?}", text);
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16).expect("invalid hex"))
+        .collect()
+}
+
+/// Checks one file, panicking with the line that didn't match.
+fn check(path: &Path) {
+    let text = fs::read_to_string(path).unwrap();
+    let mut profile = Profile::PC_60FW;
+    let mut parser = None;
+    let mut decoded = VecDeque::new();
+    let mut expected_frames = None;
+    let mut expected_checksum_errors = None;
+    for (index, line) in text.lines().enumerate() {
+        let at = format!("{}:{}", path.display(), index + 1);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (kind, rest) = line.split_at(1);
+        let rest = rest.trim();
+        match kind {
+            "@" => {
+                let (directive, value) = rest.split_once(' ').unwrap_or_else(|| panic!("{}: missing value", at));
+                match directive {
+                    "profile" => {
+                        assert!(parser.is_none(), "{}: profile given after the first notification", at);
+                        profile = profile_named(value);
+                    }
+                    "frames" => expected_frames = Some(value.parse().unwrap()),
+                    "checksum-errors" => expected_checksum_errors = Some(value.parse().unwrap()),
+                    _ => panic!("{}: unknown directive {:?}", at, directive),
+                }
+            }
+            "<" => {
+                let parser = parser.get_or_insert_with(|| Parser::new().header(profile.header));
+                parser.push(&hex(rest));
+                while let Some(frame) = parser.next_frame() {
+                    decoded.push_back(profile.decode(&frame));
+                }
+            }
+            ">" => match decoded.pop_front() {
+                Some(message) => assert_eq!(format!("{:?}", message), rest, "{}", at),
+                None => panic!("{}: expected {}, but nothing more was decoded", at, rest),
+            },
+            _ => panic!("{}: can't make sense of {:?}", at, line),
+        }
+    }
+    assert!(decoded.is_empty(), "{}: decoded more than expected: {:?}", path.display(), decoded);
+
+    let stats = parser.map(|parser| parser.stats()).unwrap_or_default();
+    if let Some(frames) = expected_frames {
+        assert_eq!(stats.frames, frames, "{}: frames", path.display());
+    }
+    if let Some(checksum_errors) = expected_checksum_errors {
+        assert_eq!(stats.checksum_errors, checksum_errors, "{}: checksum errors", path.display());
+    }
+}
+
+#[test]
+fn golden_frames() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/frames");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "hex"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no .hex files in {}", dir.display());
+    for path in paths {
+        check(&path);
+    }
+}
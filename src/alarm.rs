@@ -0,0 +1,240 @@
+//! Alarms on readings that stay out of range, e.g. for someone watching over a sleeping patient.
+//!
+//! An alarm goes off once its condition has held for `--alarm-duration`, so a single odd reading
+//! doesn't wake anyone, and clears once the value is back past the threshold by
+//! `--alarm-hysteresis`, so a value hovering around the threshold doesn't keep setting it off.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::time::Duration;
+
+use crate::cli::Args;
+use crate::output::{Event, EventRecord, Record};
+
+/// What alarms are logged under, so they can be shown when nothing else is.
+pub const LOG_TARGET: &str = "ble_spo2::alarm";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Alarm {
+    #[serde(rename = "spo2-below")]
+    Spo2Below,
+    HeartRateAbove,
+    HeartRateBelow,
+}
+
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Alarm::Spo2Below => "SpO2 below",
+            Alarm::HeartRateAbove => "heart rate above",
+            Alarm::HeartRateBelow => "heart rate below",
+        })
+    }
+}
+
+impl Alarm {
+    fn value(self, record: &Record) -> Option<u8> {
+        match self {
+            Alarm::Spo2Below => record.spo2,
+            Alarm::HeartRateAbove | Alarm::HeartRateBelow => record.heartrate,
+        }
+    }
+
+    fn breached(self, value: u8, threshold: u8) -> bool {
+        match self {
+            Alarm::Spo2Below | Alarm::HeartRateBelow => value < threshold,
+            Alarm::HeartRateAbove => value > threshold,
+        }
+    }
+
+    fn cleared(self, value: u8, threshold: u8, hysteresis: u8) -> bool {
+        match self {
+            Alarm::Spo2Below | Alarm::HeartRateBelow => value >= threshold.saturating_add(hysteresis),
+            Alarm::HeartRateAbove => value <= threshold.saturating_sub(hysteresis),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    /// Breached since this time, but not for long enough yet.
+    Pending(DateTime<Utc>),
+    Active,
+}
+
+#[derive(Debug)]
+struct Condition {
+    alarm: Alarm,
+    threshold: u8,
+    state: State,
+}
+
+/// The alarms set up on the command line, for one device.
+#[derive(Debug)]
+pub struct Alarms {
+    conditions: Vec<Condition>,
+    duration: Duration,
+    hysteresis: u8,
+}
+
+impl Alarms {
+    pub fn new(args: &Args) -> Self {
+        let conditions = [
+            (Alarm::Spo2Below, args.alarm_spo2_below),
+            (Alarm::HeartRateAbove, args.alarm_hr_above),
+            (Alarm::HeartRateBelow, args.alarm_hr_below),
+        ]
+        .into_iter()
+        .filter_map(|(alarm, threshold)| Some(Condition { alarm, threshold: threshold?, state: State::Normal }))
+        .collect();
+        Alarms {
+            conditions,
+            duration: args.alarm_duration,
+            hysteresis: args.alarm_hysteresis,
+        }
+    }
+
+    /// Takes the next reading, and returns the alarms it set off or cleared.
+    pub fn reading(&mut self, record: &Record) -> Vec<EventRecord> {
+        let mut events = Vec::new();
+        for condition in &mut self.conditions {
+            let Condition { alarm, threshold, state } = condition;
+            let Some(value) = alarm.value(record) else {
+                // Without a measurement there's no telling, so only an alarm that's already
+                // going off carries on.
+                if *state != State::Active {
+                    *state = State::Normal;
+                }
+                continue;
+            };
+            let event = match *state {
+                State::Normal | State::Pending(_) if !alarm.breached(value, *threshold) => {
+                    *state = State::Normal;
+                    None
+                }
+                State::Normal | State::Pending(_) => {
+                    let since = match *state {
+                        State::Pending(since) => since,
+                        _ => record.time,
+                    };
+                    if (record.time - since).to_std().unwrap_or_default() >= self.duration {
+                        *state = State::Active;
+                        Some(Event::Alarm { alarm: *alarm, threshold: *threshold, value })
+                    } else {
+                        *state = State::Pending(since);
+                        None
+                    }
+                }
+                State::Active if alarm.cleared(value, *threshold, self.hysteresis) => {
+                    *state = State::Normal;
+                    Some(Event::AlarmCleared { alarm: *alarm, threshold: *threshold, value })
+                }
+                State::Active => None,
+            };
+            if let Some(event) = event {
+                events.push(EventRecord { time: record.time, device: record.device.clone(), event });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use pc60fw_protocol::ProbeStatus;
+
+    use crate::output::{Extra, Quality};
+
+    fn alarms(flags: &[&str]) -> Alarms {
+        let args = Args::try_parse_from(["ble-spo2"].iter().chain(flags)).unwrap();
+        Alarms::new(&args)
+    }
+
+    fn record(second: i64, spo2: Option<u8>, heartrate: Option<u8>) -> Record {
+        Record {
+            time: DateTime::from_timestamp(second, 0).unwrap(),
+            spo2,
+            heartrate,
+            pi: None,
+            battery: None,
+            status: ProbeStatus::Stable,
+            signal: 6,
+            quality: Quality::Good,
+            device: "test".to_string(),
+            rssi: None,
+            extra: Extra::default(),
+        }
+    }
+
+    /// Feeds one SpO2 reading a second from `start`, and returns the seconds at which events
+    /// happened, with their kind.
+    fn feed(alarms: &mut Alarms, start: i64, spo2: &[Option<u8>]) -> Vec<(i64, &'static str)> {
+        let mut events = Vec::new();
+        for (second, &spo2) in (start..).zip(spo2) {
+            for event in alarms.reading(&record(second, spo2, Some(60))) {
+                events.push((second, event.event.kind()));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn fires_once_breached_for_the_duration() {
+        let mut alarms = alarms(&["--alarm-spo2-below", "90", "--alarm-duration", "10s"]);
+        let events = feed(&mut alarms, 0, &[Some(89); 15]);
+        assert_eq!(events, [(10, "alarm")]);
+    }
+
+    #[test]
+    fn single_spike_does_not_fire() {
+        let mut alarms = alarms(&["--alarm-spo2-below", "90", "--alarm-duration", "10s"]);
+        let mut spo2 = vec![Some(97); 15];
+        spo2[3] = Some(80);
+        assert_eq!(feed(&mut alarms, 0, &spo2), []);
+        // A reading back in range starts the wait over.
+        let mut spo2 = vec![Some(85); 15];
+        spo2[2] = Some(97);
+        assert_eq!(feed(&mut alarms, 100, &spo2), [(113, "alarm")]);
+    }
+
+    #[test]
+    fn clears_only_past_the_hysteresis() {
+        let mut alarms = alarms(&["--alarm-spo2-below", "90", "--alarm-duration", "0s", "--alarm-hysteresis", "2"]);
+        let spo2 = [Some(88), Some(90), Some(91), Some(89), Some(92), Some(91)];
+        assert_eq!(feed(&mut alarms, 0, &spo2), [(0, "alarm"), (4, "alarm-cleared")]);
+    }
+
+    #[test]
+    fn missing_value_while_pending_starts_over() {
+        let mut alarms = alarms(&["--alarm-spo2-below", "90", "--alarm-duration", "5s"]);
+        let mut spo2 = [Some(85); 10];
+        spo2[3] = None;
+        assert_eq!(feed(&mut alarms, 0, &spo2), [(9, "alarm")]);
+    }
+
+    #[test]
+    fn missing_value_while_active_keeps_it_going() {
+        let mut alarms = alarms(&["--alarm-spo2-below", "90", "--alarm-duration", "0s"]);
+        let spo2 = [Some(85), None, None, Some(86), Some(95)];
+        assert_eq!(feed(&mut alarms, 0, &spo2), [(0, "alarm"), (4, "alarm-cleared")]);
+    }
+
+    #[test]
+    fn thresholds_near_the_limits_saturate() {
+        // 254 + 5 and 1 - 5 would overflow, so the alarms clear at 255 and 0.
+        for (flag, threshold, cleared) in [("--alarm-hr-below", "254", 255), ("--alarm-hr-above", "1", 0)] {
+            let mut alarms = alarms(&[flag, threshold, "--alarm-duration", "0s", "--alarm-hysteresis", "5"]);
+            let kinds: Vec<_> = [(0, 100), (1, cleared)]
+                .into_iter()
+                .flat_map(|(second, heartrate)| alarms.reading(&record(second, Some(97), Some(heartrate))))
+                .map(|event| event.event.kind())
+                .collect();
+            assert_eq!(kinds, ["alarm", "alarm-cleared"], "{flag} {threshold}");
+        }
+    }
+}
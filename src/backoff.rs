@@ -0,0 +1,51 @@
+//! Delays between reconnection attempts.
+
+use std::time::Duration;
+
+/// Exponential backoff with jitter: each failed attempt doubles the delay up to `max`, and the
+/// actual delay is picked at random from the upper half, so several readers sharing an adapter
+/// don't retry in lockstep.
+#[derive(Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    max_retries: Option<u32>,
+    failures: u32,
+}
+
+impl Backoff {
+    /// `max_retries` is how many failures in a row to tolerate, or `None` to keep trying forever.
+    pub fn new(initial: Duration, max: Duration, max_retries: Option<u32>) -> Self {
+        Backoff {
+            initial,
+            max,
+            max_retries,
+            failures: 0,
+        }
+    }
+
+    /// Starts over after a successful connection.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Records a failure and returns how long to wait before the next attempt, or `None` once
+    /// the retries are used up.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.failures += 1;
+        if self.max_retries.is_some_and(|max_retries| self.failures > max_retries) {
+            return None;
+        }
+        let exponent = (self.failures - 1).min(31);
+        let delay = self.initial.saturating_mul(1 << exponent).min(self.max);
+        Some(delay.mul_f64(rand::random_range(0.5..=1.0)))
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn max_retries(&self) -> Option<u32> {
+        self.max_retries
+    }
+}
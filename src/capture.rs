@@ -0,0 +1,90 @@
+//! Raw captures of every notification a device sends, for debugging the protocol and building
+//! test data. Each notification is a line with the time it arrived, the device, the
+//! characteristic, and the bytes in hex:
+//!
+//! ```text
+//! 2024-05-01T21:13:45.123456Z AA:BB:CC:DD:EE:FF 6e400003-b5a3-f393-e0a9-e50e24dcca9e aa550f0801613d...
+//! ```
+
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::rotating_file::RotatingFile;
+
+/// Appends notifications to a capture file, whose name may be a strftime pattern. They're written
+/// out every `flush_interval`, as there are dozens a second while the waveform is streaming.
+#[derive(Debug)]
+pub struct CaptureWriter {
+    file: RotatingFile,
+    flush_interval: Duration,
+}
+
+impl CaptureWriter {
+    pub fn new(pattern: &str, flush_interval: Duration) -> Result<Self, String> {
+        Ok(CaptureWriter {
+            file: RotatingFile::new(pattern)?,
+            flush_interval,
+        })
+    }
+
+    pub fn write(&mut self, time: DateTime<Utc>, device: &str, characteristic: Uuid, value: &[u8]) -> io::Result<()> {
+        let hex: String = value.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let line = format!(
+            "{} {} {} {}\n",
+            time.to_rfc3339_opts(SecondsFormat::Micros, true),
+            device,
+            characteristic,
+            hex
+        );
+        let (file, _) = self.file.file_for(time.with_timezone(&Local))?;
+        file.write_all(line.as_bytes())?;
+        self.file.flush_every(self.flush_interval)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// A notification read back from a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub time: DateTime<Utc>,
+    pub device: String,
+    pub characteristic: Uuid,
+    pub value: Vec<u8>,
+}
+
+impl FromStr for Notification {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Empty notifications leave the last field out.
+        let (time, device, characteristic, hex) = match fields[..] {
+            [time, device, characteristic] => (time, device, characteristic, ""),
+            [time, device, characteristic, hex] => (time, device, characteristic, hex),
+            _ => return Err(format!("Expected 4 fields, got {}", fields.len())),
+        };
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return Err(format!("Invalid hex {:?}", hex));
+        }
+        let value = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Invalid hex {:?}: {}", hex, e))?;
+        Ok(Notification {
+            time: DateTime::parse_from_rfc3339(time)
+                .map_err(|e| format!("Invalid time {:?}: {}", time, e))?
+                .with_timezone(&Utc),
+            device: device.to_string(),
+            characteristic: Uuid::parse_str(characteristic)
+                .map_err(|e| format!("Invalid characteristic {:?}: {}", characteristic, e))?,
+            value,
+        })
+    }
+}
@@ -0,0 +1,130 @@
+//! Loading of settings from a TOML configuration file.
+//!
+//! Every key in the file corresponds to a long command-line option, so anything that can be
+//! passed on the command line can also be put in the config file:
+//!
+//! ```toml
+//! address = "AA:BB:CC:DD:EE:FF"
+//! output = "/var/lib/pc60fw/readings.csv"
+//!
+//! # Tables are flattened with dashes, so this is `--name-filter Wellue --name-regex ...`.
+//! [name]
+//! filter = ["OxySmart", "Wellue"]
+//! regex = ["^PC-60F_SN"]
+//! ```
+//!
+//! Options given on the command line take precedence over the config file. The `profiles` table
+//! is the exception, it declares device profiles for `--device-profile` (see
+//! [`crate::device_profile`]).
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches};
+use std::env;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::cli::Args;
+use crate::device_profile::DeviceProfile;
+
+/// `$XDG_CONFIG_HOME/pc60fw`, falling back to `~/.config/pc60fw`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("pc60fw"))
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Turns a config value into the command-line arguments it stands for.
+fn push_args(argv: &mut Vec<OsString>, flag: &str, value: &toml::Value) -> Result<(), Box<dyn Error>> {
+    match value {
+        toml::Value::Boolean(true) => argv.push(flag.into()),
+        toml::Value::Boolean(false) => {}
+        toml::Value::String(s) => argv.extend([flag.into(), s.into()]),
+        toml::Value::Integer(i) => argv.extend([flag.into(), i.to_string().into()]),
+        toml::Value::Float(f) => argv.extend([flag.into(), f.to_string().into()]),
+        toml::Value::Datetime(d) => argv.extend([flag.into(), d.to_string().into()]),
+        toml::Value::Array(values) => {
+            for value in values {
+                push_args(argv, flag, value)?;
+            }
+        }
+        toml::Value::Table(_) => return Err(format!("Unexpected table for {:?}", flag).into()),
+    }
+    Ok(())
+}
+
+/// Flattens nested tables into `(option-name, value)` pairs.
+fn flatten(prefix: &str, table: toml::Table, out: &mut Vec<(String, toml::Value)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() { key } else { format!("{}-{}", prefix, key) };
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, out),
+            value => out.push((key, value)),
+        }
+    }
+}
+
+/// Parses the command line, filling in anything not given there from the config file.
+pub fn load_args() -> Result<Args, Box<dyn Error>> {
+    let command = Args::command();
+    let cli_matches = command.clone().get_matches();
+
+    let path = match cli_matches.get_one::<PathBuf>("config") {
+        Some(path) => Some(path.clone()),
+        None => default_path().filter(|path| path.exists()),
+    };
+
+    let mut argv: Vec<OsString> = env::args_os().collect();
+    let mut profiles = BTreeMap::new();
+    if let Some(path) = path {
+        debug!("Loading config from {:?}", path);
+        let mut table: toml::Table = fs::read_to_string(&path)
+            .map_err(|e| format!("Couldn't read config {:?}: {}", path, e))?
+            .parse()
+            .map_err(|e| format!("Couldn't parse config {:?}: {}", path, e))?;
+        if let Some(value) = table.remove("profiles") {
+            profiles = value
+                .try_into::<BTreeMap<String, DeviceProfile>>()
+                .map_err(|e| format!("Invalid profiles in config {:?}: {}", path, e))?;
+        }
+        let mut entries = Vec::new();
+        flatten("", table, &mut entries);
+
+        // Config options go right after the program name, so they never end up attached to a
+        // subcommand.
+        let mut config_argv = Vec::new();
+        for (key, value) in entries {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key.as_str()))
+                .ok_or_else(|| format!("Unknown option {:?} in config {:?}", key, path))?;
+            if cli_matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            // Counted flags, like `verbose = 2`, stand for giving them that many times.
+            if let (ArgAction::Count, toml::Value::Integer(count)) = (arg.get_action(), &value) {
+                config_argv.extend((0..*count).map(|_| OsString::from(format!("--{}", key))));
+                continue;
+            }
+            push_args(&mut config_argv, &format!("--{}", key), &value)?;
+        }
+        argv.splice(1..1, config_argv);
+    }
+
+    let matches = command.try_get_matches_from(argv).unwrap_or_else(|e| e.exit());
+    let mut args = Args::from_arg_matches(&matches)?;
+    if let Some(name) = &args.device_profile {
+        let profile = profiles
+            .get(name)
+            .ok_or_else(|| format!("No profile {:?} in the config, there's {:?}", name, profiles.keys().collect::<Vec<_>>()))?;
+        profile.apply(&mut args, &cli_matches)?;
+    }
+    Ok(args)
+}
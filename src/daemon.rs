@@ -0,0 +1,99 @@
+//! Running in the background without systemd: `--daemonize` detaches from the terminal and writes
+//! a pidfile, which `stop` uses to find the reader again.
+
+use daemonize::{Daemonize, Stdio};
+use std::env;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cli::Args;
+
+/// `--pidfile`, or ble-spo2.pid in the runtime directory, which is cleared on logout and reboot.
+pub fn pidfile(args: &Args) -> PathBuf {
+    args.pidfile.clone().unwrap_or_else(|| {
+        env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join("ble-spo2.pid")
+    })
+}
+
+/// Forks into the background and returns in the daemon. Has to happen before the async runtime
+/// starts, as only the thread that forks carries on.
+pub fn daemonize(args: &Args) -> Result<(), Box<dyn Error>> {
+    let pidfile = pidfile(args);
+    // The daemon would find out too, but by then the terminal has been given back.
+    if let Some(pid) = running(&pidfile)? {
+        return Err(format!("Already running as PID {}, according to {:?}", pid, pidfile).into());
+    }
+    Daemonize::new()
+        .pid_file(&pidfile)
+        // Relative paths in the other options should still work.
+        .working_directory(env::current_dir()?)
+        .stdout(redirect(args.daemon_stdout.as_deref())?)
+        .stderr(redirect(args.daemon_stderr.as_deref())?)
+        .start()?;
+    Ok(())
+}
+
+/// Appends to `path`, or discards everything without one.
+fn redirect(path: Option<&Path>) -> Result<Stdio, Box<dyn Error>> {
+    let Some(path) = path else {
+        return Ok(Stdio::devnull());
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Couldn't open {:?}: {}", path, e))?;
+    Ok(file.into())
+}
+
+/// Asks the daemon to stop, and waits up to `timeout` for it to finish writing and exit.
+pub fn stop(args: &Args, timeout: Duration) -> Result<(), Box<dyn Error>> {
+    let pidfile = pidfile(args);
+    let Some(pid) = running(&pidfile)? else {
+        return Err(format!("Not running, according to {:?}", pidfile).into());
+    };
+    // SAFETY: kill only sends a signal.
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        return Err(format!("Couldn't stop PID {}: {}", pid, io::Error::last_os_error()).into());
+    }
+    let deadline = Instant::now() + timeout;
+    while alive(pid) {
+        if Instant::now() >= deadline {
+            return Err(format!("PID {} is still running after {:?}", pid, timeout).into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    // The daemon leaves it behind, as it can't know whether it was the last one to have it.
+    if let Err(e) = fs::remove_file(&pidfile) {
+        debug!("Couldn't remove {:?}: {}", pidfile, e);
+    }
+    eprintln!("Stopped PID {}", pid);
+    Ok(())
+}
+
+/// The process the pidfile names, if there's one and it's still running.
+fn running(pidfile: &Path) -> Result<Option<libc::pid_t>, Box<dyn Error>> {
+    let contents = match fs::read_to_string(pidfile) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Couldn't read {:?}: {}", pidfile, e).into()),
+    };
+    // Empty while a daemon is still starting up.
+    let Ok(pid) = contents.trim().parse() else {
+        return Ok(None);
+    };
+    Ok(Some(pid).filter(|&pid| pid > 0 && alive(pid)))
+}
+
+fn alive(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists.
+    let found = unsafe { libc::kill(pid, 0) } == 0;
+    found || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
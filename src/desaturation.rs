@@ -0,0 +1,237 @@
+//! Detection of oxygen desaturations, the dips in SpO2 that the oxygen desaturation index (ODI)
+//! counts. Sleep apnea shows up as many of them an hour.
+//!
+//! A desaturation is SpO2 staying at least 3 (or 4) points below the baseline for at least
+//! [`MIN_DURATION`]. The baseline is worked out from the readings before the dip, and is held
+//! while SpO2 is down, so a long dip doesn't drag its own baseline down with it.
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::output::{Event, EventRecord, Quality, Record};
+
+/// Drops counted, in SpO2 points below the baseline.
+pub const THRESHOLDS: [u8; 2] = [3, 4];
+/// Shorter dips are most likely motion or a lost signal.
+const MIN_DURATION: Duration = Duration::from_secs(10);
+/// Readings needed before there's a baseline to compare with.
+const MIN_BASELINE_READINGS: usize = 10;
+
+/// How the baseline is worked out from the readings in its window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Baseline {
+    /// The average SpO2.
+    Mean,
+    /// The highest SpO2, which counts more, shallower dips when SpO2 wanders.
+    Peak,
+}
+
+/// A dip that might turn out to be a desaturation, once it's lasted long enough.
+#[derive(Debug, Clone, Copy)]
+struct Dip {
+    start: DateTime<Utc>,
+    /// Of the last reading that was still down.
+    last: DateTime<Utc>,
+    baseline: f32,
+    nadir: u8,
+}
+
+impl Dip {
+    /// The desaturation this dip was, if it had lasted long enough by `end`.
+    fn desaturation(&self, threshold: u8, end: DateTime<Utc>, device: &str) -> Option<EventRecord> {
+        let duration = (end - self.start).to_std().unwrap_or_default();
+        (duration >= MIN_DURATION).then(|| EventRecord {
+            time: self.start,
+            device: device.to_string(),
+            event: Event::Desaturation {
+                threshold,
+                baseline: (self.baseline * 10.0).round() / 10.0,
+                nadir: self.nadir,
+                duration: duration.as_secs_f32().round(),
+            },
+        })
+    }
+}
+
+/// Finds desaturations in one device's readings.
+#[derive(Debug)]
+pub struct Detector {
+    baseline: Baseline,
+    window: Duration,
+    /// Readings the baseline is worked out from, oldest first.
+    history: VecDeque<(DateTime<Utc>, u8)>,
+    /// The dip in progress for each of [`THRESHOLDS`].
+    dips: [Option<Dip>; THRESHOLDS.len()],
+}
+
+impl Detector {
+    pub fn new(baseline: Baseline, window: Duration) -> Self {
+        Detector {
+            baseline,
+            window,
+            history: VecDeque::new(),
+            dips: [None; THRESHOLDS.len()],
+        }
+    }
+
+    fn baseline(&self) -> Option<f32> {
+        if self.history.len() < MIN_BASELINE_READINGS {
+            return None;
+        }
+        let values = self.history.iter().map(|&(_, spo2)| spo2);
+        Some(match self.baseline {
+            Baseline::Mean => values.map(f32::from).sum::<f32>() / self.history.len() as f32,
+            Baseline::Peak => f32::from(values.max()?),
+        })
+    }
+
+    /// Takes the next reading, and returns the desaturations it ended.
+    pub fn reading(&mut self, record: &Record) -> Vec<EventRecord> {
+        let mut events = Vec::new();
+        let Some(spo2) = record.spo2 else {
+            // Without a finger there's no telling what SpO2 did, so a dip in progress is dropped.
+            self.dips = [None; THRESHOLDS.len()];
+            return events;
+        };
+        // Motion artifacts look like dips.
+        if record.quality == Quality::Low {
+            return events;
+        }
+
+        let baseline = self.baseline();
+        for (dip, threshold) in self.dips.iter_mut().zip(THRESHOLDS) {
+            let below = |baseline: f32| baseline - f32::from(spo2) >= f32::from(threshold);
+            match dip {
+                Some(current) if below(current.baseline) => {
+                    current.nadir = current.nadir.min(spo2);
+                    current.last = record.time;
+                }
+                Some(current) => {
+                    events.extend(current.desaturation(threshold, record.time, &record.device));
+                    *dip = None;
+                }
+                None => {
+                    if let Some(baseline) = baseline.filter(|&baseline| below(baseline)) {
+                        *dip = Some(Dip { start: record.time, last: record.time, baseline, nadir: spo2 });
+                    }
+                }
+            }
+        }
+
+        // The smallest drop starts first and ends last, so while it's going on the baseline holds.
+        if self.dips[0].is_none() {
+            self.history.push_back((record.time, spo2));
+            while let Some(&(time, _)) = self.history.front() {
+                match (record.time - time).to_std() {
+                    Ok(age) if age > self.window => self.history.pop_front(),
+                    _ => break,
+                };
+            }
+        }
+        events
+    }
+
+    /// Ends the dips still going on, as the readings have stopped, and returns those that had
+    /// already lasted long enough up to their last reading.
+    pub fn close(&mut self, device: &str) -> Vec<EventRecord> {
+        let dips = std::mem::replace(&mut self.dips, [None; THRESHOLDS.len()]);
+        dips.into_iter()
+            .zip(THRESHOLDS)
+            .filter_map(|(dip, threshold)| dip.and_then(|dip| dip.desaturation(threshold, dip.last, device)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pc60fw_protocol::ProbeStatus;
+
+    use crate::output::Extra;
+
+    fn record(second: i64, spo2: u8) -> Record {
+        Record {
+            time: DateTime::from_timestamp(second, 0).unwrap(),
+            spo2: Some(spo2),
+            heartrate: Some(60),
+            pi: None,
+            battery: None,
+            status: ProbeStatus::Stable,
+            signal: 6,
+            quality: Quality::Good,
+            device: "test".to_string(),
+            rssi: None,
+            extra: Extra::default(),
+        }
+    }
+
+    /// Feeds 10 s of SpO2 at 97 for a baseline, then a reading a second of `dip` from t = 10 s
+    /// on, and returns the desaturations found by then, with the time they started.
+    fn feed(detector: &mut Detector, dip: &[u8]) -> Vec<(i64, Event)> {
+        let spo2 = [97; MIN_BASELINE_READINGS].iter().chain(dip);
+        (0..)
+            .zip(spo2)
+            .flat_map(|(second, &spo2)| detector.reading(&record(second, spo2)))
+            .map(|event| (event.time.timestamp(), event.event))
+            .collect()
+    }
+
+    fn desaturation(threshold: u8, baseline: f32, nadir: u8, duration: f32) -> Event {
+        Event::Desaturation { threshold, baseline, nadir, duration }
+    }
+
+    fn detector() -> Detector {
+        Detector::new(Baseline::Mean, Duration::from_secs(60))
+    }
+
+    #[test]
+    fn counts_drops_of_3_and_4_points() {
+        let mut dip = vec![93; 15];
+        dip[5] = 92;
+        dip.push(97);
+        let events = feed(&mut detector(), &dip);
+        assert_eq!(events, [(10, desaturation(3, 97.0, 92, 15.0)), (10, desaturation(4, 97.0, 92, 15.0))]);
+
+        let mut dip = vec![94; 15];
+        dip.push(97);
+        let events = feed(&mut detector(), &dip);
+        assert_eq!(events, [(10, desaturation(3, 97.0, 94, 15.0))]);
+    }
+
+    #[test]
+    fn ignores_dips_shorter_than_the_minimum() {
+        let too_short = MIN_DURATION.as_secs() as usize - 1;
+        let mut dip = vec![90; too_short];
+        dip.push(97);
+        assert_eq!(feed(&mut detector(), &dip), []);
+
+        let mut dip = vec![90; too_short + 1];
+        dip.push(97);
+        assert_eq!(feed(&mut detector(), &dip).len(), THRESHOLDS.len());
+    }
+
+    #[test]
+    fn holds_the_baseline_during_a_dip() {
+        // The dip outlasts the baseline window, and would become its own baseline otherwise.
+        let mut detector = Detector::new(Baseline::Mean, Duration::from_secs(20));
+        let mut dip = vec![92; 60];
+        dip.push(97);
+        let events = feed(&mut detector, &dip);
+        assert_eq!(events, [(10, desaturation(3, 97.0, 92, 60.0)), (10, desaturation(4, 97.0, 92, 60.0))]);
+    }
+
+    #[test]
+    fn reports_dips_still_going_on_when_closed() {
+        let mut long = detector();
+        assert_eq!(feed(&mut long, &[93; 16]), []);
+        let events: Vec<_> = long.close("test").into_iter().map(|event| event.event).collect();
+        assert_eq!(events, [desaturation(3, 97.0, 93, 15.0), desaturation(4, 97.0, 93, 15.0)]);
+        assert!(long.close("test").is_empty());
+
+        let mut short = detector();
+        feed(&mut short, &[93; 5]);
+        assert!(short.close("test").is_empty());
+    }
+}
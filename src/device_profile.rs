@@ -0,0 +1,85 @@
+//! Device profiles declared in the config file, for rebrands of the PC-60FW that moved things
+//! around, so they can be read without waiting for a new release:
+//!
+//! ```toml
+//! device-profile = "acme"
+//!
+//! [profiles.acme]
+//! name-filter = "ACME-OX"
+//! service = "0000ffe0-0000-1000-8000-00805f9b34fb"
+//! rx-characteristic = "0000ffe4-0000-1000-8000-00805f9b34fb"
+//! tx-characteristic = "0000ffe9-0000-1000-8000-00805f9b34fb"
+//! header = [0xa5, 0x5a]
+//! status-offset = 6
+//! ```
+//!
+//! Anything a profile leaves out is taken from `--model`, and options given on the command line
+//! still take precedence.
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use pc60fw_protocol::Profile;
+use serde::Deserialize;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::cli::Args;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DeviceProfile {
+    name_filter: Option<String>,
+    service: Option<String>,
+    rx_characteristic: Option<String>,
+    tx_characteristic: Option<String>,
+    header: Option<[u8; 2]>,
+    data_token: Option<u8>,
+    parameters_type: Option<u8>,
+    waveform_type: Option<u8>,
+    battery_type: Option<u8>,
+    spo2_offset: Option<usize>,
+    pulse_rate_offset: Option<usize>,
+    pulse_rate_high_offset: Option<usize>,
+    perfusion_index_offset: Option<usize>,
+    status_offset: Option<usize>,
+}
+
+fn parse_uuid(uuid: &Option<String>) -> Result<Option<Uuid>, Box<dyn Error>> {
+    uuid.as_deref()
+        .map(|uuid| Uuid::parse_str(uuid).map_err(|e| format!("Invalid UUID {:?}: {}", uuid, e).into()))
+        .transpose()
+}
+
+impl DeviceProfile {
+    /// Fills in `args` from the profile, except for options given on the command line.
+    pub fn apply(&self, args: &mut Args, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+        let from_command_line = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        if let Some(name_filter) = self.name_filter.clone().filter(|_| !from_command_line("name_filters")) {
+            args.name_filters = vec![name_filter];
+        }
+        if let Some(service) = parse_uuid(&self.service)?.filter(|_| !from_command_line("scan_services")) {
+            args.scan_services = vec![service];
+        }
+        if !from_command_line("rx_characteristic") {
+            args.rx_characteristic = parse_uuid(&self.rx_characteristic)?.or(args.rx_characteristic);
+        }
+        if !from_command_line("tx_characteristic") {
+            args.tx_characteristic = parse_uuid(&self.tx_characteristic)?.or(args.tx_characteristic);
+        }
+
+        let base = args.model.profile();
+        args.profile = Some(Profile {
+            header: self.header.unwrap_or(base.header),
+            data_token: self.data_token.unwrap_or(base.data_token),
+            parameters_type: self.parameters_type.unwrap_or(base.parameters_type),
+            waveform_type: self.waveform_type.unwrap_or(base.waveform_type),
+            battery_type: self.battery_type.or(base.battery_type),
+            spo2_offset: self.spo2_offset.unwrap_or(base.spo2_offset),
+            pulse_rate_offset: self.pulse_rate_offset.unwrap_or(base.pulse_rate_offset),
+            pulse_rate_high_offset: self.pulse_rate_high_offset.or(base.pulse_rate_high_offset),
+            perfusion_index_offset: self.perfusion_index_offset.or(base.perfusion_index_offset),
+            status_offset: self.status_offset.or(base.status_offset),
+        });
+        Ok(())
+    }
+}
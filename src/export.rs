@@ -0,0 +1,125 @@
+//! Conversion of recorded readings into formats other software imports.
+
+use chrono::{DateTime, Local, Utc};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// The parts of a recorded reading that exports need.
+struct Sample {
+    time: DateTime<Utc>,
+    spo2: Option<u8>,
+    heartrate: Option<u8>,
+}
+
+/// Parses RFC 3339 times, and epoch times in seconds or milliseconds.
+fn parse_time(time: &str) -> Result<DateTime<Utc>, Box<dyn Error>> {
+    if let Ok(epoch) = time.parse::<i64>() {
+        // Seconds would only get this large thousands of years from now.
+        let parsed = if epoch.abs() >= 100_000_000_000 {
+            DateTime::from_timestamp_millis(epoch)
+        } else {
+            DateTime::from_timestamp(epoch, 0)
+        };
+        return parsed.ok_or_else(|| format!("Bad time {:?}", time).into());
+    }
+    Ok(DateTime::parse_from_rfc3339(time)
+        .map_err(|e| format!("Bad time {:?}: {}", time, e))?
+        .with_timezone(&Utc))
+}
+
+/// Reads readings written with `--output`, in either CSV or JSON Lines format.
+fn read_samples(path: &Path) -> Result<Vec<Sample>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Couldn't read {:?}: {}", path, e))?;
+    let mut samples = Vec::new();
+    let mut csv_columns: Option<Vec<&str>> = None;
+    for line in contents.lines().filter(|line| !line.is_empty()) {
+        if line.starts_with('{') {
+            let value: serde_json::Value = serde_json::from_str(line)?;
+            let field = |name| value[name].as_u64().and_then(|v| u8::try_from(v).ok());
+            let time = match &value["time"] {
+                serde_json::Value::String(time) => time.clone(),
+                serde_json::Value::Number(time) => time.to_string(),
+                _ => return Err("Reading without a time".into()),
+            };
+            samples.push(Sample {
+                time: parse_time(&time)?,
+                spo2: field("spo2"),
+                heartrate: field("heartrate"),
+            });
+        } else if line.starts_with("time,") {
+            // Files get a new header whenever they're appended to from a fresh start.
+            csv_columns = Some(line.split(',').collect());
+        } else {
+            let columns = csv_columns.as_ref().ok_or("CSV file without a header")?;
+            let values: Vec<&str> = line.split(',').collect();
+            let field = |name| {
+                columns
+                    .iter()
+                    .position(|column| *column == name)
+                    .and_then(|i| values.get(i))
+                    .and_then(|value| value.parse().ok())
+            };
+            samples.push(Sample {
+                time: parse_time(values[0])?,
+                spo2: field("spo2"),
+                heartrate: field("heartrate"),
+            });
+        }
+    }
+    Ok(samples)
+}
+
+/// Keeps the first sample of each `interval` that has a value, so a night of once-a-second
+/// readings doesn't become tens of thousands of health records.
+fn thin<T>(values: impl Iterator<Item = (DateTime<Utc>, T)>, interval: Duration) -> Vec<(DateTime<Utc>, T)> {
+    let interval = chrono::Duration::from_std(interval).unwrap_or(chrono::Duration::MAX);
+    let mut kept: Vec<(DateTime<Utc>, T)> = Vec::new();
+    for (time, value) in values {
+        if kept.last().is_none_or(|(last, _)| time - *last >= interval) {
+            kept.push((time, value));
+        }
+    }
+    kept
+}
+
+/// Writes samples in the format of Apple Health's own `export.xml`, which importer apps and
+/// shortcuts understand.
+fn write_apple_health(out: &mut dyn Write, samples: &[Sample], interval: Duration) -> io::Result<()> {
+    let apple_date = |time: DateTime<Utc>| time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S %z").to_string();
+    let spo2 = thin(samples.iter().filter_map(|s| Some((s.time, s.spo2?))), interval);
+    let heartrate = thin(samples.iter().filter_map(|s| Some((s.time, s.heartrate?))), interval);
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<HealthData locale="en_US">"#)?;
+    let mut record = |kind: &str, unit: &str, time: DateTime<Utc>, value: String| {
+        let date = apple_date(time);
+        writeln!(
+            out,
+            r#" <Record type="{}" sourceName="PC-60FW" unit="{}" creationDate="{}" startDate="{}" endDate="{}" value="{}"/>"#,
+            kind, unit, date, date, date, value
+        )
+    };
+    for (time, value) in spo2 {
+        // Apple Health stores oxygen saturation as a fraction.
+        record("HKQuantityTypeIdentifierOxygenSaturation", "%", time, format!("{:.2}", f32::from(value) / 100.0))?;
+    }
+    for (time, value) in heartrate {
+        record("HKQuantityTypeIdentifierHeartRate", "count/min", time, value.to_string())?;
+    }
+    writeln!(out, "</HealthData>")?;
+    out.flush()
+}
+
+/// Runs the `export --apple-health` subcommand, writing to `output` or stdout.
+pub fn apple_health(input: &Path, output: Option<&Path>, interval: Duration) -> Result<(), Box<dyn Error>> {
+    let samples = read_samples(input)?;
+    info!("Exporting {} readings from {:?}", samples.len(), input);
+    match output {
+        Some(path) => write_apple_health(&mut io::BufWriter::new(fs::File::create(path)?), &samples, interval)?,
+        None => write_apple_health(&mut io::stdout().lock(), &samples, interval)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use btleplug::api::BDAddr;
+use btleplug::platform::PeripheralId;
+use regex::Regex;
+
+/// Decides which advertised peripheral names are worth connecting to.
+///
+/// A name matches if it contains any of the substrings or matches any of the regexes.
+#[derive(Debug, Clone)]
+pub struct NameFilter {
+    substrings: Vec<String>,
+    regexes: Vec<Regex>,
+}
+
+impl NameFilter {
+    pub fn new(substrings: Vec<String>, regexes: Vec<Regex>) -> Self {
+        NameFilter { substrings, regexes }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.substrings.iter().any(|s| name.contains(s.as_str()))
+            || self.regexes.iter().any(|r| r.is_match(name))
+    }
+}
+
+/// Checks whether a peripheral is the one the user asked for by `--address`.
+///
+/// The address is compared against the peripheral's MAC, and, since macOS hides MACs behind
+/// per-host UUIDs, against the platform peripheral ID as well.
+pub fn matches_address(address: &str, mac: &BDAddr, id: &PeripheralId) -> bool {
+    let address = address.to_lowercase();
+    mac.to_string().to_lowercase() == address
+        || format!("{:?}", id).to_lowercase().contains(&address)
+}
+
+/// Checks whether an adapter is the one the user asked for by `--adapter`, either by its index in
+/// the list of adapters or by part of its description, like "hci1" on Linux.
+pub fn matches_adapter(selector: &str, index: usize, info: &str) -> bool {
+    match selector.parse::<usize>() {
+        Ok(selector_index) => selector_index == index,
+        Err(_) => info.to_lowercase().contains(&selector.to_lowercase()),
+    }
+}
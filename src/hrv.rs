@@ -0,0 +1,127 @@
+//! Heart rate variability with `--hrv`, from the beat markers in the plethysmograph waveform.
+//!
+//! The time between beats is counted in waveform samples rather than taken from when
+//! notifications arrive, as those come in bursts. The sample rate this needs is worked out from
+//! how many samples arrived over the first seconds, so it doesn't matter which device sent them.
+//! At 50 samples a second, that's still only accurate to 20 ms, so this is a rough measure next to
+//! an ECG chest strap.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use pc60fw_protocol::WaveformSample;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Beat-to-beat intervals outside this range, in seconds, are from a missed or an extra beat.
+const INTERVALS: std::ops::RangeInclusive<f64> = 0.3..=2.0;
+
+/// An interval that differs from the one before by more than this fraction is taken to be an
+/// artifact, as is usual when cleaning up beat-to-beat intervals.
+const MAX_CHANGE: f64 = 0.2;
+
+/// Waveform frames further apart than this mean some were lost, so beats are counted afresh.
+const MAX_FRAME_GAP: TimeDelta = TimeDelta::seconds(1);
+
+/// How long the waveform has to have been arriving for its sample rate to be known well enough.
+const MIN_RATE_TIME: TimeDelta = TimeDelta::seconds(10);
+
+/// How many intervals there have to be in the window before RMSSD and SDNN mean anything.
+const MIN_INTERVALS: usize = 10;
+
+/// Tracks one device's beats over a sliding window.
+pub struct Hrv {
+    window: TimeDelta,
+    /// When the first waveform frame arrived, and how many samples arrived before the last one.
+    started: Option<DateTime<Utc>>,
+    counted: u64,
+    /// When the last waveform frame arrived, and how many samples there have been in all.
+    last_frame: Option<DateTime<Utc>>,
+    samples: u64,
+    /// Whether the last sample was marked as a beat, as some devices mark a few in a row.
+    in_beat: bool,
+    /// The number of the sample and the time of each beat in the window.
+    beats: VecDeque<(u64, DateTime<Utc>)>,
+}
+
+impl Hrv {
+    pub fn new(window: Duration) -> Self {
+        Hrv {
+            window: TimeDelta::from_std(window).unwrap_or(TimeDelta::MAX),
+            started: None,
+            counted: 0,
+            last_frame: None,
+            samples: 0,
+            in_beat: false,
+            beats: VecDeque::new(),
+        }
+    }
+
+    /// Takes the samples from a waveform frame that arrived at `time`.
+    pub fn waveform(&mut self, time: DateTime<Utc>, samples: &[WaveformSample]) {
+        if self.last_frame.is_some_and(|last_frame| time - last_frame > MAX_FRAME_GAP) {
+            self.started = None;
+            self.samples = 0;
+            self.in_beat = false;
+            self.beats.clear();
+        }
+        self.started.get_or_insert(time);
+        self.last_frame = Some(time);
+        self.counted = self.samples;
+        for sample in samples {
+            if sample.pulse_beat && !self.in_beat {
+                self.beats.push_back((self.samples, time));
+            }
+            self.in_beat = sample.pulse_beat;
+            self.samples += 1;
+        }
+        while self.beats.front().is_some_and(|&(_, beat)| time - beat > self.window) {
+            self.beats.pop_front();
+        }
+    }
+
+    /// Samples per second, once the waveform has been arriving for long enough to tell.
+    fn sample_rate(&self) -> Option<f64> {
+        let elapsed = self.last_frame? - self.started?;
+        if elapsed < MIN_RATE_TIME {
+            return None;
+        }
+        Some(self.counted as f64 / (elapsed.num_milliseconds() as f64 / 1000.0))
+    }
+
+    /// The intervals between the beats in the window in milliseconds, leaving out artifacts.
+    fn intervals(&self) -> Vec<f64> {
+        let Some(sample_rate) = self.sample_rate() else {
+            return Vec::new();
+        };
+        let mut intervals = Vec::new();
+        let mut previous: Option<f64> = None;
+        for ((first, _), (second, _)) in self.beats.iter().zip(self.beats.iter().skip(1)) {
+            let interval = (second - first) as f64 / sample_rate;
+            if !INTERVALS.contains(&interval) {
+                continue;
+            }
+            if previous.is_none_or(|previous| (interval - previous).abs() <= MAX_CHANGE * previous) {
+                intervals.push(interval * 1000.0);
+            }
+            previous = Some(interval);
+        }
+        intervals
+    }
+
+    /// RMSSD and SDNN over the window, in milliseconds, once there are enough beats in it.
+    pub fn metrics(&self) -> (Option<f32>, Option<f32>) {
+        let intervals = self.intervals();
+        if intervals.len() < MIN_INTERVALS {
+            return (None, None);
+        }
+        let differences: Vec<f64> = intervals.windows(2).map(|pair| (pair[1] - pair[0]).powi(2)).collect();
+        let rmssd = (differences.iter().sum::<f64>() / differences.len() as f64).sqrt();
+        let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        let variance = intervals.iter().map(|interval| (interval - mean).powi(2)).sum::<f64>() / (intervals.len() - 1) as f64;
+        (Some(round(rmssd)), Some(round(variance.sqrt())))
+    }
+}
+
+/// To a tenth of a millisecond, which is more than precise enough.
+fn round(value: f64) -> f32 {
+    ((value * 10.0).round() / 10.0) as f32
+}
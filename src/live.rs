@@ -0,0 +1,76 @@
+//! Fan-out of readings and pleth samples to live HTTP clients.
+
+use chrono::Utc;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+use crate::output::{Record, WaveformRecord};
+
+/// How many recent readings are kept for clients that reconnect and want to catch up.
+const RECENT_READINGS: usize = 300;
+
+/// A reading with a sequence number, so clients can tell what they've missed.
+#[derive(Debug, Clone)]
+pub struct LiveReading {
+    pub id: u64,
+    pub record: Record,
+}
+
+pub struct LiveFeed {
+    /// When the feed started, in milliseconds since the Unix epoch, to tell its ids apart from
+    /// those of an earlier run, which start from 1 as well.
+    pub epoch: i64,
+    sender: broadcast::Sender<LiveReading>,
+    waveform: broadcast::Sender<WaveformRecord>,
+    recent: Mutex<(u64, VecDeque<LiveReading>)>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        LiveFeed {
+            epoch: Utc::now().timestamp_millis(),
+            sender: broadcast::channel(64).0,
+            // Samples come about 50 times as often as readings.
+            waveform: broadcast::channel(512).0,
+            recent: Mutex::new((0, VecDeque::with_capacity(RECENT_READINGS))),
+        }
+    }
+}
+
+impl LiveFeed {
+    pub fn publish(&self, record: &Record) {
+        let mut recent = self.recent.lock().unwrap();
+        let (next_id, readings) = &mut *recent;
+        *next_id += 1;
+        let reading = LiveReading {
+            id: *next_id,
+            record: record.clone(),
+        };
+        if readings.len() == RECENT_READINGS {
+            readings.pop_front();
+        }
+        readings.push_back(reading.clone());
+        // Sending only fails when nobody is listening, which is fine.
+        let _ = self.sender.send(reading);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveReading> {
+        self.sender.subscribe()
+    }
+
+    /// Pleth samples aren't buffered, as they're only of use while they're current.
+    pub fn publish_waveform(&self, record: &WaveformRecord) {
+        let _ = self.waveform.send(record.clone());
+    }
+
+    pub fn subscribe_waveform(&self) -> broadcast::Receiver<WaveformRecord> {
+        self.waveform.subscribe()
+    }
+
+    /// The buffered readings that came after `id`.
+    pub fn since(&self, id: u64) -> Vec<LiveReading> {
+        let recent = self.recent.lock().unwrap();
+        recent.1.iter().filter(|r| r.id > id).cloned().collect()
+    }
+}
@@ -0,0 +1,255 @@
+//! Where log messages go: straight to the journal when running under systemd, so readings and
+//! events can be looked up by field, to the Event Log when running as a Windows service, and to
+//! stderr otherwise, as text or as JSON with the spans each message happened in. `--log-file`
+//! keeps them in a file as well, which is rotated by date and size.
+
+use chrono::Local;
+use clap::ValueEnum;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing_subscriber::filter::{filter_fn, FilterExt};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Filter, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer, Registry};
+
+use crate::alarm;
+use crate::cli::Args;
+use crate::rotating_file::check_pattern;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, with the message's fields and the spans it happened in.
+    Json,
+}
+
+pub fn init(args: &Args) -> Result<(), Box<dyn Error>> {
+    let file = match &args.log_file {
+        Some(pattern) => Some(file_layer(pattern, args)?),
+        None => None,
+    };
+    #[cfg(target_os = "linux")]
+    if connected_to_journal() {
+        // Looked at after the fact rather than watched, so the reader's own messages down to
+        // info are kept. Fields are kept under their own names, like DEVICE and SPO2.
+        let journal = tracing_journald::layer()?.with_field_prefix(None);
+        tracing_subscriber::registry()
+            .with(file)
+            .with(journal.with_filter(filter(args, "ble_spo2=info")))
+            .try_init()?;
+        return Ok(());
+    }
+    // The dashboard has the terminal, and anything printed over it would garble the screen.
+    // Alarms are shown on it anyway, and the rest can go to --log-file.
+    let stderr = (!args.tui()).then(|| {
+        let stderr = fmt::layer().with_writer(io::stderr).with_ansi(io::stderr().is_terminal());
+        let stderr = match args.log_format {
+            LogFormat::Text => stderr.boxed(),
+            LogFormat::Json => stderr.json().with_current_span(true).with_span_list(true).boxed(),
+        };
+        stderr.with_filter(stderr_filter(args))
+    });
+    tracing_subscriber::registry().with(file).with(stderr).try_init()?;
+    Ok(())
+}
+
+/// Writes to `--log-file` in `--log-format`, down to info like the journal.
+fn file_layer(pattern: &str, args: &Args) -> Result<Box<dyn Layer<Registry> + Send + Sync>, Box<dyn Error>> {
+    check_pattern(pattern)?;
+    let file = LogFile {
+        pattern: pattern.to_string(),
+        max_size: args.log_max_size,
+        keep: args.log_keep,
+        current: None,
+    };
+    let layer = fmt::layer().with_writer(Mutex::new(file)).with_ansi(false);
+    let layer = match args.log_format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().with_current_span(true).with_span_list(true).boxed(),
+    };
+    Ok(layer.with_filter(filter(args, "ble_spo2=info")).boxed())
+}
+
+/// Filters messages by `-v`, or by `RUST_LOG` without it, or by `default` without either. Spans
+/// are always kept, so the messages that do get through still say which attempt and session they
+/// belong to.
+fn filter<S>(args: &Args, default: &str) -> impl Filter<S> {
+    let messages = match args.verbose {
+        0 => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default)),
+        1 => EnvFilter::new("warn,ble_spo2=info"),
+        2 => EnvFilter::new("warn,ble_spo2=debug"),
+        _ => EnvFilter::new("debug,ble_spo2=trace"),
+    };
+    filter_fn(|metadata| metadata.is_span()).or(messages)
+}
+
+/// Errors and alarms by default, and only alarms with `--quiet`.
+fn stderr_filter<S>(args: &Args) -> Box<dyn Filter<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let alarms = format!("{}=warn", alarm::LOG_TARGET);
+    if args.quiet {
+        return Box::new(EnvFilter::new(alarms));
+    }
+    Box::new(filter(args, &format!("error,{}", alarms)))
+}
+
+/// The log file, named by expanding a strftime pattern at the time of each message, and rolled
+/// over to FILE.1, FILE.2, ... when it would grow past `max_size`.
+///
+/// Unlike [`RotatingFile`](crate::rotating_file::RotatingFile), nothing is buffered, so the last
+/// messages before a crash aren't lost, and nothing is logged from in here, which would deadlock.
+struct LogFile {
+    pattern: String,
+    max_size: Option<u64>,
+    /// How many rolled over files to keep.
+    keep: usize,
+    /// The file being written to and its size.
+    current: Option<(PathBuf, File, u64)>,
+}
+
+impl LogFile {
+    /// Returns the file that `length` more bytes should be written to.
+    fn file_for(&mut self, length: u64) -> io::Result<&mut (PathBuf, File, u64)> {
+        let path = PathBuf::from(Local::now().format(&self.pattern).to_string());
+        let full = |size: u64| self.max_size.is_some_and(|max_size| size > 0 && size + length > max_size);
+        let reopen = match &self.current {
+            Some((current, _, size)) => *current != path || full(*size),
+            None => true,
+        };
+        if reopen {
+            self.current = None;
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            // The first message after a restart may find the file full already.
+            let size = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+            if full(size) {
+                roll(&path, self.keep)?;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let size = file.metadata()?.len();
+            self.current = Some((path, file, size));
+        }
+        Ok(self.current.as_mut().unwrap())
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (_, file, size) = self.file_for(buf.len() as u64)?;
+        file.write_all(buf)?;
+        *size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some((_, file, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Renames `path` to `path.1`, after moving `path.1` to `path.2` and so on, dropping the oldest
+/// beyond `keep`.
+fn roll(path: &Path, keep: usize) -> io::Result<()> {
+    let numbered = |n: usize| {
+        let mut name = OsString::from(path);
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    };
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    for n in (1..keep).rev() {
+        match fs::rename(numbered(n), numbered(n + 1)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::rename(path, numbered(1))
+}
+
+/// Whether stderr goes to the journal, as it does for a systemd service, which sets
+/// `JOURNAL_STREAM` to its device and inode.
+#[cfg(target_os = "linux")]
+fn connected_to_journal() -> bool {
+    use std::fs::File;
+    use std::os::fd::AsFd;
+    use std::os::unix::fs::MetadataExt;
+
+    let Some(stream) = std::env::var_os("JOURNAL_STREAM") else {
+        return false;
+    };
+    let stderr = io::stderr().as_fd().try_clone_to_owned().map(File::from);
+    stderr
+        .and_then(|stderr| stderr.metadata())
+        .is_ok_and(|metadata| stream == format!("{}:{}", metadata.dev(), metadata.ino()).as_str())
+}
+
+/// Logs to the Windows Event Log as `source`, which is registered first if it can be.
+///
+/// The Event Log is a `log` logger, so without a tracing subscriber, messages are passed on to it
+/// as `log` records.
+#[cfg(windows)]
+pub fn init_event_log(source: &str) -> Result<(), Box<dyn Error>> {
+    // Needs to be an administrator, which a service usually is.
+    let registered = eventlog::register(source);
+    filtered::install(eventlog::EventLog::new(source, log::Level::Trace)?)?;
+    if let Err(e) = registered {
+        warn!("Couldn't register {} as an Event Log source: {}", source, e);
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod filtered {
+    use env_logger::filter::{Builder, Filter};
+    use log::{LevelFilter, Log, Metadata, Record};
+    use std::env;
+    use std::error::Error;
+
+    /// The Event Log takes everything, so this filters by `RUST_LOG` like on stderr.
+    struct Filtered<L> {
+        filter: Filter,
+        logger: L,
+    }
+
+    impl<L: Log> Log for Filtered<L> {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            self.filter.enabled(metadata)
+        }
+
+        fn log(&self, record: &Record) {
+            if self.filter.matches(record) {
+                self.logger.log(record);
+            }
+        }
+
+        fn flush(&self) {
+            self.logger.flush();
+        }
+    }
+
+    /// Without `RUST_LOG`, the reader's own messages down to info are kept, as these logs are
+    /// looked at after the fact rather than watched.
+    pub fn install(logger: impl Log + 'static) -> Result<(), Box<dyn Error>> {
+        let mut builder = Builder::new();
+        match env::var("RUST_LOG") {
+            Ok(filters) => builder.parse(&filters),
+            Err(_) => builder.filter_module("ble_spo2", LevelFilter::Info),
+        };
+        let filter = builder.build();
+        log::set_max_level(filter.filter());
+        log::set_boxed_logger(Box::new(Filtered { filter, logger }))?;
+        Ok(())
+    }
+}
@@ -1,138 +1,218 @@
 // See the "macOS permissions note" in README.md before running this on macOS
 // Big Sur or later.
 
-use btleplug::api::{Central, CharPropFlags, Manager as _, Peripheral as _, ScanFilter, CentralEvent, ValueNotification};
-use btleplug::platform::{Adapter, Manager, Peripheral};
+use btleplug::platform::Manager;
 use std::error::Error;
-use std::time::Duration;
-use tokio::{time};
-use uuid::Uuid;
-use chrono;
-use futures::StreamExt;
+use std::future::Future;
+use std::sync::Arc;
+
+mod alarm;
+mod backoff;
+mod capture;
+mod cli;
+mod config;
+#[cfg(unix)]
+mod daemon;
+mod desaturation;
+mod device_profile;
+mod export;
+mod filter;
+mod hrv;
+mod live;
+mod logging;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod outlier;
+mod output;
+mod pairing;
+mod protocol;
+mod receiver;
+mod respiration;
+mod rotating_file;
+mod server;
+#[cfg(windows)]
+mod service;
+mod sink;
+mod snoop;
+mod source;
+mod stats;
+mod summary;
+mod systemd;
+mod template;
+#[cfg(feature = "tui")]
+mod tui;
+
+use capture::CaptureWriter;
+use cli::{Args, Command};
+use live::LiveFeed;
+use stats::Stats;
 
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
-/// Only devices whose name contains this string will be tried.
-const PERIPHERAL_NAME_MATCH_FILTER: &str = "OxySmart";
-/// UUID of the characteristic for which we should subscribe to notifications to receive new bytes
-const NUS_CHARACTERISTIC_RX_UUID: Uuid = Uuid::from_u128(0x6e400003_b5a3_f393_e0a9_e50e24dcca9e);
+fn main() -> Result<(), Box<dyn Error>> {
+    #[cfg(windows)]
+    if std::env::args_os().any(|arg| arg == "--service") {
+        return service::run();
+    }
+    let args = config::load_args()?;
+    logging::init(&args)?;
+    #[cfg(unix)]
+    {
+        if let Some(Command::Stop { timeout }) = args.command {
+            return daemon::stop(&args, timeout);
+        }
+        if args.daemonize {
+            daemon::daemonize(&args)?;
+        }
+    }
+    tokio::runtime::Runtime::new()?.block_on(run(args, std::future::pending()))
+}
 
-async fn find_device(manager: &Manager) -> Result<(Adapter, Peripheral, btleplug::api::Characteristic), Box<dyn Error>> {
-    let adapter_list = manager.adapters().await?;
-    if adapter_list.is_empty() {
-        error!("No Bluetooth adapters found");
-        return Err("No adapters found".into());
+/// Reads from the device until it's done, or the dashboard is quit, Ctrl-C is pressed, SIGTERM
+/// arrives, `--duration` is up, `--one-shot` has its reading or `stop` completes. Either way,
+/// devices are disconnected, sinks get to write out what they're holding and the summary is
+/// printed before returning.
+async fn run(args: Args, stop: impl Future<Output = ()>) -> Result<(), Box<dyn Error>> {
+    if let Some(Command::Export { input, output, apple_health: true, interval }) = &args.command {
+        return export::apple_health(input, output.as_deref(), *interval);
+    }
+    if let Some(Command::Decode { input, handle }) = &args.command {
+        return snoop::decode(&args, input, *handle);
+    }
+    if let Some(Command::Info { timeout }) = args.command {
+        return source::ble::print_device_info(&Manager::new().await?, &args, timeout).await;
     }
 
-    for adapter in adapter_list.iter() {
-        info!("Starting scan...");
-        adapter
-            .start_scan(ScanFilter::default())
-            .await
-            .expect("Can't scan BLE adapter for connected devices...");
-        time::sleep(Duration::from_secs(2)).await;
-        let peripherals = adapter.peripherals().await?;
+    // Counted from the start, so the time it takes to find the device is included.
+    let deadline = args.duration.map(|duration| tokio::time::Instant::now() + duration);
+    let mut sinks = sink::from_args(&args).await?;
+    let measured = match args.one_shot {
+        true => {
+            let (sink, measured) = sink::OneShotSink::new(sink::row_sink(&args)?, args.one_shot_samples.into());
+            sinks.push(sink);
+            Some(measured)
+        }
+        false => None,
+    };
+    let stats = Arc::new(Stats::default());
+    if let Some(address) = args.listen {
+        let live = Arc::new(LiveFeed::default());
+        server::spawn(address, stats.clone(), live.clone()).await?;
+        sinks.push(sink::LiveSink::new(live));
+    }
+    #[cfg(feature = "otlp")]
+    let _otlp = match &args.otlp {
+        Some(endpoint) => Some(otlp::start(endpoint, args.otlp_protocol, args.otlp_interval, stats.clone())?),
+        None => None,
+    };
 
-        if peripherals.is_empty() {
-            error!("->>> BLE peripheral devices were not found, sorry. Exiting...");
-            return Err("No BLE peripheral devices found".into());
+    #[cfg(feature = "tui")]
+    let dashboard = args.tui.then(|| {
+        let state = tui::SharedState::default();
+        sinks.push(tui::DashboardSink::new(state.clone()));
+        state
+    });
+    // Quitting the dashboard ends the program, and without one there's nothing to quit.
+    let quit = async {
+        #[cfg(feature = "tui")]
+        if let Some(state) = dashboard {
+            return tui::run(state).await;
         }
+        std::future::pending::<std::io::Result<()>>().await
+    };
 
-        // All peripheral devices in range.
-        for peripheral in peripherals.iter() {
-            let properties = peripheral.properties().await?.unwrap();
-            let is_connected = peripheral.is_connected().await?;
-            let local_name = properties
-                .local_name
-                .unwrap_or(String::from(properties.address.to_string()));
-            // Check if it's the peripheral we want.
-            if !local_name.contains(PERIPHERAL_NAME_MATCH_FILTER) {
-                continue;
+    let capture = args
+        .record_raw
+        .as_deref()
+        .map(|pattern| CaptureWriter::new(pattern, args.flush_interval))
+        .transpose()?;
+    let source = source::from_args(&args, &stats).await?;
+    let shutdown = async {
+        tokio::select! {
+            result = quit => result.map_err(Into::into),
+            _ = tokio::signal::ctrl_c() => {
+                info!("Interrupted, stopping...");
+                Ok(())
             }
-
-            info!("Found matching peripheral {:?}...", &local_name);
-            if !is_connected {
-                // Connect if we aren't already connected.
-                if let Err(err) = peripheral.connect().await {
-                    error!("Error connecting to peripheral, skipping: {}", err.to_string());
-                    continue;
-                }
+            () = terminated() => {
+                info!("Terminated, stopping...");
+                Ok(())
             }
-            let is_connected = peripheral.is_connected().await?;
-            info!("Now connected ({:?}) to peripheral {:?}.", is_connected, &local_name);
-            if !is_connected {
-                error!("Couldn't connect to peripheral, skipping {:?}.", &local_name);
-                continue;
+            () = settled(measured.clone()) => {
+                info!("Got a steady reading, stopping...");
+                Ok(())
             }
-
-            debug!("Discover peripheral {:?} services...", local_name);
-            peripheral.discover_services().await?;
-            let characteristics = peripheral.characteristics();
-            let characteristic_rx = characteristics.iter().find(|c| {
-                c.uuid == NUS_CHARACTERISTIC_RX_UUID &&
-                    c.properties.contains(CharPropFlags::NOTIFY)
-            });
-            if characteristic_rx.is_none() {
-                error!("Couldn't find characteristic, skipping {:?}.", &local_name);
-                continue;
+            () = until(deadline) => {
+                info!("Time is up, stopping...");
+                Ok(())
+            }
+            () = stop => {
+                info!("Stopping...");
+                Ok(())
             }
-            return Ok((adapter.to_owned(), peripheral.to_owned(), characteristic_rx.unwrap().to_owned()));
         }
+    };
+    let sinks = tokio::sync::Mutex::new(sinks);
+    let result = source::run(source, &args, &sinks, &stats, capture, shutdown).await;
+    sinks.into_inner().close().await;
+    if !args.quiet && !args.one_shot {
+        print_summary(&stats);
     }
-    Err("No matching peripheral found".into())
+    result?;
+    if measured.is_some_and(|measured| !*measured.borrow()) {
+        return Err("Stopped before the reading settled".into());
+    }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    pretty_env_logger::init();
-    let manager = Manager::new().await?;
-    println!("time,spo2,heartrate");
+/// Resolves once `--one-shot` has written its reading, or never without it.
+async fn settled(measured: Option<tokio::sync::watch::Receiver<bool>>) {
+    let Some(mut measured) = measured else {
+        return std::future::pending().await;
+    };
+    while !*measured.borrow() {
+        if measured.changed().await.is_err() {
+            return std::future::pending().await;
+        }
+    }
+}
 
-    loop {
-        match find_device(&manager).await {
-            Ok((adaptor, peripheral, characteristic_rx)) => {
-                peripheral.subscribe(&characteristic_rx).await?;
-                let mut notification_stream = peripheral.notifications().await?;
-                let mut disconnect_stream = adaptor.events().await?;
-                // Process while the BLE connection is not broken or stopped.
+/// Resolves at `deadline`, or never without one.
+async fn until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
 
+/// Resolves on SIGTERM, which is how `stop` and service managers ask the reader to quit.
+#[cfg(unix)]
+async fn terminated() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(e) => {
+            warn!("Couldn't listen for SIGTERM: {}", e);
+            std::future::pending().await
+        }
+    }
+}
 
-                loop {
-                    tokio::select! {
-                        msg = notification_stream.next() => {
-                            match msg {
-                                Some(ValueNotification { uuid: _, value }) => {
-                                    trace!("Got raw data: {:?}", value);
-                                    if value.len() >= 7 && value[..5] == vec! {0xaa, 0x55, 0x0f, 0x08, 0x01} {
-                                        let time_iso8601 = chrono::offset::Utc::now().to_rfc3339();
-                                        let (spo2, hr) = (value[5], value[6]);
-                                        if spo2 == 0 && hr == 0 {
-                                            debug!("Suppressing null data");
-                                            continue;
-                                        }
-                                        println!("{},{},{}", time_iso8601, spo2, hr);
-                                    }
-                                },
-                                _ => break
-                            }
-                        },
-                        msg = disconnect_stream.next() => {
-                            match msg {
-                                Some(CentralEvent::DeviceDisconnected(periph_id)) if periph_id == peripheral.id() => {
-                                    info!("Disconnected from peripheral, exiting...");
-                                    break;
-                                },
-                                _ => {}
-                            }
-                        },
-                    }
-                }
+#[cfg(not(unix))]
+async fn terminated() {
+    std::future::pending().await
+}
 
-                info!("Disconnecting from peripheral...");
-                peripheral.disconnect().await?;
-            }
-            Err(e) => { error!("Failed to connect: {}", e); }
-        };
+/// Prints what was recorded to stderr, so it doesn't end up among readings written to stdout.
+fn print_summary(stats: &Stats) {
+    let summaries = stats.summaries();
+    for (device, summary) in &summaries {
+        if summaries.len() > 1 {
+            eprintln!("\n{}:", device);
+        }
+        eprintln!("{}", summary);
     }
 }
@@ -0,0 +1,87 @@
+//! Export of the readings and reader stats as OpenTelemetry metrics.
+
+use clap::ValueEnum;
+use opentelemetry::metrics::MeterProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{MetricExporter, WithExportConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::Resource;
+use std::error::Error;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::output::Record;
+use crate::stats::Stats;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Protocol {
+    /// OTLP over gRPC, usually on port 4317.
+    Grpc,
+    /// OTLP over HTTP with protobuf bodies, usually on port 4318.
+    Http,
+}
+
+/// Starts exporting metrics to the OTLP collector at `endpoint` every `interval`. Exporting stops
+/// when the returned provider is dropped.
+pub fn start(endpoint: &str, protocol: Protocol, interval: Duration, stats: Arc<Stats>) -> Result<SdkMeterProvider, Box<dyn Error>> {
+    let exporter = match protocol {
+        Protocol::Grpc => MetricExporter::builder().with_tonic().with_endpoint(endpoint).build()?,
+        Protocol::Http => MetricExporter::builder().with_http().with_endpoint(endpoint).build()?,
+    };
+    let reader = PeriodicReader::builder(exporter).with_interval(interval).build();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(reader)
+        .with_resource(Resource::builder().with_service_name(env!("CARGO_PKG_NAME")).build())
+        .build();
+    let meter = provider.meter("pc60fw");
+
+    // Gauges only report a value while there's one to report, with one for each device.
+    let gauge = |name: &'static str, unit: &'static str, description: &'static str, value: fn(&Record) -> Option<f64>| {
+        let stats = stats.clone();
+        meter
+            .f64_observable_gauge(name)
+            .with_unit(unit)
+            .with_description(description)
+            .with_callback(move |observer| {
+                for (device, record) in stats.latest_by_device() {
+                    if let Some(value) = value(&record) {
+                        observer.observe(value, &[KeyValue::new("device", device)]);
+                    }
+                }
+            })
+            .build();
+    };
+    gauge("pc60fw.spo2", "%", "Latest oxygen saturation.", |r| r.spo2.map(f64::from));
+    gauge("pc60fw.heart_rate", "{beat}/min", "Latest pulse rate.", |r| r.heartrate.map(f64::from));
+    gauge("pc60fw.perfusion_index", "%", "Latest perfusion index.", |r| {
+        r.pi.map(|pi| (f64::from(pi) * 10.0).round() / 10.0)
+    });
+    gauge("pc60fw.battery", "{bar}", "Battery charge as shown on the device, 0-3.", |r| r.battery.map(f64::from));
+    gauge("pc60fw.signal_strength", "1", "Optical signal strength, 0-8.", |r| Some(f64::from(r.signal)));
+    gauge("pc60fw.rssi", "dBm", "Bluetooth signal strength of the device.", |r| r.rssi.map(f64::from));
+
+    let counter = |name: &'static str, description: &'static str, value: fn(&Stats) -> u64| {
+        let stats = stats.clone();
+        meter
+            .u64_observable_counter(name)
+            .with_description(description)
+            .with_callback(move |observer| observer.observe(value(&stats), &[]))
+            .build();
+    };
+    counter("pc60fw.frames", "Frames parsed successfully.", |s| s.frames.load(Ordering::Relaxed));
+    counter("pc60fw.checksum_errors", "Frames dropped because of a bad checksum.", |s| {
+        s.checksum_errors.load(Ordering::Relaxed)
+    });
+    counter("pc60fw.connections", "Successful connections to the device.", |s| s.connections.load(Ordering::Relaxed));
+
+    let connected = stats.clone();
+    meter
+        .u64_observable_gauge("pc60fw.connected")
+        .with_description("Number of devices currently connected.")
+        .with_callback(move |observer| observer.observe(connected.connected.load(Ordering::Relaxed), &[]))
+        .build();
+
+    info!("Exporting OTLP metrics to {}", endpoint);
+    Ok(provider)
+}
@@ -0,0 +1,186 @@
+//! Suppressing single-reading glitches with `--outlier-filter`, like SpO2 jumping to 127 or the
+//! heart rate spiking while the finger moves, before they set off alarms or end up in the
+//! outputs.
+
+use clap::ValueEnum;
+use std::collections::VecDeque;
+
+use crate::output::Record;
+
+/// Hampel filters replace values more than this many scaled median absolute deviations from the
+/// median.
+const HAMPEL_THRESHOLD: f32 = 3.0;
+
+/// Scales the median absolute deviation to the standard deviation, for normally distributed
+/// values.
+const MAD_SCALE: f32 = 1.4826;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutlierFilter {
+    /// Replace every value with the median of the last --outlier-window ones. Evens out the
+    /// readings a little, and follows real changes half a window late.
+    Median,
+    /// Only replace values that are far off the median of the last --outlier-window ones, by
+    /// more than 3 times their spread. Real changes are left alone.
+    Hampel,
+}
+
+/// The recent values of one measurement.
+struct Series {
+    values: VecDeque<u8>,
+    window: usize,
+    /// Values above this can't be right, and are always replaced.
+    max: u8,
+    /// Values no further than this from the median are never replaced, as with whole numbers that
+    /// barely change, the spread is often zero.
+    min_deviation: u8,
+}
+
+impl Series {
+    fn new(window: usize, max: u8, min_deviation: u8) -> Self {
+        Series {
+            values: VecDeque::with_capacity(window),
+            window,
+            max,
+            min_deviation,
+        }
+    }
+
+    fn filter(&mut self, kind: OutlierFilter, value: Option<u8>) -> Option<u8> {
+        let Some(value) = value else {
+            // The finger came out, so whatever comes next is a new measurement.
+            self.values.clear();
+            return None;
+        };
+        if value > self.max {
+            return median(self.values.iter().copied());
+        }
+        if self.values.len() == self.window {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+        let median = median(self.values.iter().copied())?;
+        match kind {
+            OutlierFilter::Median => Some(median),
+            OutlierFilter::Hampel => {
+                let mad = median_of_deviations(&self.values, median);
+                let threshold = (HAMPEL_THRESHOLD * MAD_SCALE * f32::from(mad)).max(f32::from(self.min_deviation));
+                let outlier = f32::from(value.abs_diff(median)) > threshold;
+                Some(if outlier { median } else { value })
+            }
+        }
+    }
+}
+
+/// The middle value, or the upper of the two middle ones.
+fn median(values: impl Iterator<Item = u8>) -> Option<u8> {
+    let mut values: Vec<u8> = values.collect();
+    values.sort_unstable();
+    values.get(values.len() / 2).copied()
+}
+
+fn median_of_deviations(values: &VecDeque<u8>, median_value: u8) -> u8 {
+    median(values.iter().map(|value| value.abs_diff(median_value))).unwrap_or(0)
+}
+
+/// Filters one device's readings.
+pub struct Outliers {
+    kind: OutlierFilter,
+    spo2: Series,
+    heartrate: Series,
+}
+
+impl Outliers {
+    pub fn new(kind: OutlierFilter, window: usize) -> Self {
+        Outliers {
+            kind,
+            spo2: Series::new(window, 100, 2),
+            heartrate: Series::new(window, 250, 5),
+        }
+    }
+
+    /// Replaces the SpO2 and heart rate of `record` with their filtered values.
+    pub fn filter(&mut self, record: &mut Record) {
+        record.spo2 = self.spo2.filter(self.kind, record.spo2);
+        record.heartrate = self.heartrate.filter(self.kind, record.heartrate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use pc60fw_protocol::ProbeStatus;
+
+    use crate::output::{Extra, Quality};
+
+    fn record(spo2: Option<u8>, heartrate: Option<u8>) -> Record {
+        Record {
+            time: DateTime::from_timestamp(0, 0).unwrap(),
+            spo2,
+            heartrate,
+            pi: None,
+            battery: None,
+            status: ProbeStatus::Stable,
+            signal: 6,
+            quality: Quality::Good,
+            device: "test".to_string(),
+            rssi: None,
+            extra: Extra::default(),
+        }
+    }
+
+    /// Filters the SpO2 values in turn, with the heart rate steady.
+    fn spo2(outliers: &mut Outliers, values: &[Option<u8>]) -> Vec<Option<u8>> {
+        values
+            .iter()
+            .map(|&spo2| {
+                let mut record = record(spo2, Some(60));
+                outliers.filter(&mut record);
+                record.spo2
+            })
+            .collect()
+    }
+
+    #[test]
+    fn median_replaces_every_value() {
+        let mut outliers = Outliers::new(OutlierFilter::Median, 5);
+        let values = [97, 98, 96, 97, 80, 97].map(Some);
+        assert_eq!(spo2(&mut outliers, &values), [97, 98, 97, 97, 97, 97].map(Some));
+    }
+
+    #[test]
+    fn hampel_replaces_only_values_far_off() {
+        let mut outliers = Outliers::new(OutlierFilter::Hampel, 5);
+        let values = [97, 97, 97, 97, 80, 95, 97].map(Some);
+        assert_eq!(spo2(&mut outliers, &values), [97, 97, 97, 97, 97, 95, 97].map(Some));
+    }
+
+    #[test]
+    fn hampel_follows_a_real_change() {
+        let mut outliers = Outliers::new(OutlierFilter::Hampel, 5);
+        let values = [97, 97, 97, 97, 97, 90, 90, 90, 90].map(Some);
+        assert_eq!(spo2(&mut outliers, &values), [97, 97, 97, 97, 97, 97, 97, 90, 90].map(Some));
+    }
+
+    #[test]
+    fn replaces_values_above_the_maximum() {
+        for kind in [OutlierFilter::Median, OutlierFilter::Hampel] {
+            let mut outliers = Outliers::new(kind, 5);
+            // A value that can't be right isn't kept either, so it doesn't pull the median up.
+            let values = [Some(127), Some(96), Some(127), Some(127), Some(96)];
+            assert_eq!(spo2(&mut outliers, &values), [None, Some(96), Some(96), Some(96), Some(96)], "{kind:?}");
+
+            let mut record = record(Some(96), Some(255));
+            outliers.filter(&mut record);
+            assert_eq!(record.heartrate, Some(60), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn starts_over_when_the_finger_comes_out() {
+        let mut outliers = Outliers::new(OutlierFilter::Median, 5);
+        let values = [Some(97), Some(97), None, Some(90)];
+        assert_eq!(spo2(&mut outliers, &values), [Some(97), Some(97), None, Some(90)]);
+    }
+}
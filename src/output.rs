@@ -0,0 +1,693 @@
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use pc60fw_protocol::{BatteryLevel, ProbeStatus, Reading, WaveformSample};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::alarm::Alarm;
+use crate::rotating_file::{check_pattern, RotatingFile};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Comma-separated values with a header row.
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+    /// CSV that OSCAR imports as pulse oximetry data, to merge with CPAP sessions.
+    Oscar,
+}
+
+/// Something that can be written as a line of output.
+pub trait Row: Serialize {
+    const CSV_HEADER: &'static str;
+
+    fn time(&self) -> DateTime<Utc>;
+
+    /// `time` is the row's time, already formatted.
+    fn to_csv(&self, time: &str) -> String;
+
+    /// The header for a file that starts with this row, for rows with columns that are only
+    /// there when asked for.
+    fn csv_header(&self) -> Cow<'static, str> {
+        Cow::Borrowed(Self::CSV_HEADER)
+    }
+
+    const OSCAR_HEADER: &'static str;
+
+    fn to_oscar(&self) -> String;
+}
+
+/// One measurement, along with the device state at the time it was taken. Can be read back from
+/// its JSON, for the spool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    #[serde(serialize_with = "rfc3339", deserialize_with = "from_rfc3339")]
+    pub time: DateTime<Utc>,
+    /// Empty while there's no measurement, rather than a misleading zero.
+    pub spo2: Option<u8>,
+    pub heartrate: Option<u8>,
+    pub pi: Option<f32>,
+    pub battery: Option<u8>,
+    #[serde(serialize_with = "display", deserialize_with = "probe_status")]
+    pub status: ProbeStatus,
+    pub signal: u8,
+    pub quality: Quality,
+    /// Address of the device the reading came from, to tell devices apart when there are several.
+    pub device: String,
+    /// Bluetooth signal strength in dBm, as last checked, if the platform reports it.
+    pub rssi: Option<i16>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Columns that are only written when asked for, after the usual ones. Each is `None` while it
+/// isn't asked for, and `Some(None)` while it is but there's no value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Extra {
+    /// SpO2 as the device sent it, before `--outlier-filter`, with `--keep-raw`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spo2_raw: Option<Option<u8>>,
+    /// Heart rate as the device sent it, before `--outlier-filter`, with `--keep-raw`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartrate_raw: Option<Option<u8>>,
+    /// Root mean square of successive differences between heart beats in ms, with `--hrv`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rmssd: Option<Option<f32>>,
+    /// Standard deviation of the time between heart beats in ms, with `--hrv`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sdnn: Option<Option<f32>>,
+    /// Breaths a minute estimated from the waveform, with `--respiratory-rate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resp_rate: Option<Option<f32>>,
+}
+
+impl Extra {
+    /// Names and values of the columns that were asked for, in order.
+    pub fn columns(&self) -> Vec<(&'static str, Option<String>)> {
+        fn column<T: Display>(name: &'static str, value: Option<Option<T>>) -> Option<(&'static str, Option<String>)> {
+            Some((name, value?.map(|value| value.to_string())))
+        }
+        [
+            column("spo2_raw", self.spo2_raw),
+            column("heartrate_raw", self.heartrate_raw),
+            column("rmssd", self.rmssd),
+            column("sdnn", self.sdnn),
+            column("resp_rate", self.resp_rate),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// `header` followed by the names of the columns that were asked for.
+    fn csv_header(&self, header: &'static str) -> Cow<'static, str> {
+        let columns = self.columns();
+        if columns.is_empty() {
+            return Cow::Borrowed(header);
+        }
+        let names: Vec<_> = columns.iter().map(|(name, _)| *name).collect();
+        Cow::Owned(format!("{},{}", header, names.join(",")))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    Good,
+    Low,
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Quality::Good => "good",
+            Quality::Low => "low",
+        })
+    }
+}
+
+impl Record {
+    pub fn new(
+        time: DateTime<Utc>,
+        device: &str,
+        reading: &Reading,
+        battery: Option<BatteryLevel>,
+        rssi: Option<i16>,
+    ) -> Self {
+        let (spo2, heartrate, pi) = if reading.is_null() {
+            (None, None, None)
+        } else {
+            (Some(reading.spo2), Some(reading.heart_rate), Some(reading.perfusion_index_percent()))
+        };
+        Record {
+            time,
+            spo2,
+            heartrate,
+            pi,
+            battery: battery.map(|b| b.0),
+            status: reading.probe_status,
+            signal: reading.signal_strength,
+            quality: if reading.is_good_quality() { Quality::Good } else { Quality::Low },
+            device: device.to_string(),
+            rssi,
+            extra: Extra::default(),
+        }
+    }
+}
+
+impl Row for Record {
+    const CSV_HEADER: &'static str = "time,spo2,heartrate,pi,battery,status,signal,quality,device,rssi";
+
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn to_csv(&self, time: &str) -> String {
+        let mut csv = format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            time,
+            csv_field(self.spo2),
+            csv_field(self.heartrate),
+            csv_field(self.pi.map(|pi| format!("{:.1}", pi))),
+            csv_field(self.battery),
+            self.status,
+            self.signal,
+            self.quality,
+            self.device,
+            csv_field(self.rssi),
+        );
+        for (_, value) in self.extra.columns() {
+            csv.push(',');
+            csv.push_str(&value.unwrap_or_default());
+        }
+        csv
+    }
+
+    fn csv_header(&self) -> Cow<'static, str> {
+        self.extra.csv_header(Self::CSV_HEADER)
+    }
+
+    const OSCAR_HEADER: &'static str = "Timestamp,Pulse,SpO2";
+
+    /// OSCAR has no notion of a missing value, so those are written as 0, which it treats as no
+    /// data.
+    fn to_oscar(&self) -> String {
+        format!(
+            "{},{},{}",
+            oscar_timestamp(self.time),
+            self.heartrate.unwrap_or(0),
+            self.spo2.unwrap_or(0),
+        )
+    }
+}
+
+/// Marks where a device's readings stop because it went away, so charts show a break rather than
+/// a line across the gap. Written like a reading with only the time and device, and a status of
+/// `disconnected`, with the same extra columns as the readings, left empty.
+#[derive(Debug, Clone, Serialize)]
+pub struct Gap {
+    #[serde(serialize_with = "rfc3339")]
+    pub time: DateTime<Utc>,
+    spo2: Option<u8>,
+    heartrate: Option<u8>,
+    pi: Option<f32>,
+    battery: Option<u8>,
+    status: &'static str,
+    signal: Option<u8>,
+    quality: Option<Quality>,
+    pub device: String,
+    rssi: Option<i16>,
+    #[serde(flatten)]
+    extra: Extra,
+}
+
+impl Gap {
+    /// `extra` has the columns the readings have, without values.
+    pub fn new(time: DateTime<Utc>, device: &str, extra: Extra) -> Self {
+        Gap {
+            time,
+            spo2: None,
+            heartrate: None,
+            pi: None,
+            battery: None,
+            status: "disconnected",
+            signal: None,
+            quality: None,
+            device: device.to_string(),
+            rssi: None,
+            extra,
+        }
+    }
+}
+
+impl Row for Gap {
+    const CSV_HEADER: &'static str = Record::CSV_HEADER;
+
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn to_csv(&self, time: &str) -> String {
+        let mut csv = format!("{},,,,,{},,,{},", time, self.status, self.device);
+        csv.push_str(&",".repeat(self.extra.columns().len()));
+        csv
+    }
+
+    fn csv_header(&self) -> Cow<'static, str> {
+        self.extra.csv_header(Self::CSV_HEADER)
+    }
+
+    const OSCAR_HEADER: &'static str = Record::OSCAR_HEADER;
+
+    fn to_oscar(&self) -> String {
+        format!("{},0,0", oscar_timestamp(self.time))
+    }
+}
+
+/// Readings over an interval starting at `time`, for `--average`: the mean, lowest and highest
+/// SpO2 and heart rate of those that had them, and how many there were in all.
+#[derive(Debug, Clone, Serialize)]
+pub struct Average {
+    #[serde(serialize_with = "rfc3339")]
+    pub time: DateTime<Utc>,
+    pub spo2: Option<f32>,
+    pub spo2_min: Option<u8>,
+    pub spo2_max: Option<u8>,
+    pub heartrate: Option<f32>,
+    pub heartrate_min: Option<u8>,
+    pub heartrate_max: Option<u8>,
+    pub readings: usize,
+    pub device: String,
+}
+
+impl Row for Average {
+    const CSV_HEADER: &'static str =
+        "time,spo2,spo2_min,spo2_max,heartrate,heartrate_min,heartrate_max,readings,device";
+
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn to_csv(&self, time: &str) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            time,
+            csv_field(self.spo2.map(|spo2| format!("{:.1}", spo2))),
+            csv_field(self.spo2_min),
+            csv_field(self.spo2_max),
+            csv_field(self.heartrate.map(|heartrate| format!("{:.1}", heartrate))),
+            csv_field(self.heartrate_min),
+            csv_field(self.heartrate_max),
+            self.readings,
+            self.device,
+        )
+    }
+
+    const OSCAR_HEADER: &'static str = Record::OSCAR_HEADER;
+
+    /// OSCAR only takes whole numbers.
+    fn to_oscar(&self) -> String {
+        format!(
+            "{},{},{}",
+            oscar_timestamp(self.time),
+            self.heartrate.map_or(0, |heartrate| heartrate.round() as u8),
+            self.spo2.map_or(0, |spo2| spo2.round() as u8),
+        )
+    }
+}
+
+/// One plethysmograph sample.
+#[derive(Debug, Clone, Serialize)]
+pub struct WaveformRecord {
+    #[serde(serialize_with = "rfc3339")]
+    pub time: DateTime<Utc>,
+    pub pleth: u8,
+    pub pulse_beat: bool,
+}
+
+impl WaveformRecord {
+    pub fn new(time: DateTime<Utc>, sample: &WaveformSample) -> Self {
+        WaveformRecord {
+            time,
+            pleth: sample.pleth,
+            pulse_beat: sample.pulse_beat,
+        }
+    }
+}
+
+impl Row for WaveformRecord {
+    const CSV_HEADER: &'static str = "time,pleth,pulse_beat";
+
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn to_csv(&self, time: &str) -> String {
+        format!("{},{},{}", time, self.pleth, u8::from(self.pulse_beat))
+    }
+
+    const OSCAR_HEADER: &'static str = "Timestamp,Pleth";
+
+    fn to_oscar(&self) -> String {
+        format!("{},{}", oscar_timestamp(self.time), self.pleth)
+    }
+}
+
+/// Something that happened to a device, as opposed to a measurement.
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRecord {
+    #[serde(serialize_with = "rfc3339")]
+    pub time: DateTime<Utc>,
+    pub device: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// SpO2 stayed at least `threshold` points below `baseline` for `duration` seconds, starting
+    /// at the record's time.
+    Desaturation { threshold: u8, baseline: f32, nadir: u8, duration: f32 },
+    /// A reading stayed past an alarm's threshold for long enough.
+    Alarm { alarm: Alarm, threshold: u8, value: u8 },
+    /// A reading came back from an alarm's threshold.
+    AlarmCleared { alarm: Alarm, threshold: u8, value: u8 },
+    /// The device connected, and readings are about to arrive.
+    Connected,
+    /// The device went away.
+    Disconnected,
+    /// The device will be connected to again `delay` seconds from the record's time, after the
+    /// connection was lost and `failures` attempts in a row have failed since.
+    Reconnecting { failures: u32, delay: f32 },
+    /// The finger was taken out of the probe.
+    FingerRemoved,
+    /// A finger was put in the probe.
+    FingerInserted,
+    /// The device's battery dropped to `level` bars, which it shows as low.
+    BatteryLow { level: u8 },
+}
+
+impl Event {
+    /// What the event is, as written in the `event` column.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Desaturation { .. } => "desaturation",
+            Event::Alarm { .. } => "alarm",
+            Event::AlarmCleared { .. } => "alarm-cleared",
+            Event::Connected => "connected",
+            Event::Disconnected => "disconnected",
+            Event::Reconnecting { .. } => "reconnecting",
+            Event::FingerRemoved => "finger-removed",
+            Event::FingerInserted => "finger-inserted",
+            Event::BatteryLow { .. } => "battery-low",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Desaturation { threshold, baseline, nadir, duration } => write!(
+                f,
+                "SpO2 dropped at least {}% from {:.1}% to {}% for {}s",
+                threshold, baseline, nadir, duration
+            ),
+            Event::Alarm { alarm, threshold, value } => {
+                write!(f, "Alarm: {} {} ({})", alarm, threshold, value)
+            }
+            Event::AlarmCleared { alarm, threshold, value } => {
+                write!(f, "Alarm cleared: {} {} ({})", alarm, threshold, value)
+            }
+            Event::Connected => f.write_str("Connected"),
+            Event::Disconnected => f.write_str("Disconnected"),
+            Event::Reconnecting { failures, delay } => {
+                write!(f, "Reconnecting in {:.1}s (failed attempts: {})", delay, failures)
+            }
+            Event::FingerRemoved => f.write_str("Finger removed"),
+            Event::FingerInserted => f.write_str("Finger inserted"),
+            Event::BatteryLow { level } => write!(f, "Battery low ({}/3)", level),
+        }
+    }
+}
+
+impl Row for EventRecord {
+    const CSV_HEADER: &'static str = "time,device,event,details";
+
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn to_csv(&self, time: &str) -> String {
+        format!("{},{},{},\"{}\"", time, self.device, self.event.kind(), self.event)
+    }
+
+    const OSCAR_HEADER: &'static str = "Timestamp,Event,Details";
+
+    /// OSCAR doesn't import events, but they're kept in the same shape for whoever reads them.
+    fn to_oscar(&self) -> String {
+        format!("{},{},\"{}\"", oscar_timestamp(self.time), self.event.kind(), self.event)
+    }
+}
+
+fn csv_field<T: Display>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// OSCAR expects local time without an offset or fractional seconds.
+fn oscar_timestamp(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+fn rfc3339<S: Serializer>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&time.to_rfc3339())
+}
+
+fn display<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+fn from_rfc3339<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let time = String::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&time)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(serde::de::Error::custom)
+}
+
+/// The reverse of [`ProbeStatus`]'s `Display`.
+fn probe_status<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ProbeStatus, D::Error> {
+    let status = String::deserialize(deserializer)?;
+    [ProbeStatus::NoFinger, ProbeStatus::Searching, ProbeStatus::Stable]
+        .into_iter()
+        .find(|known| known.to_string() == status)
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown probe status {:?}", status)))
+}
+
+/// Which time zone timestamps in the output are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Zone {
+    #[default]
+    Utc,
+    Local,
+    Named(Tz),
+}
+
+/// How timestamps in the output are written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    #[default]
+    Rfc3339,
+    /// Whole seconds since the Unix epoch.
+    EpochSeconds,
+    /// Milliseconds since the Unix epoch.
+    EpochMillis,
+    Strftime(String),
+}
+
+impl TimeFormat {
+    /// "rfc3339", "epoch", "epoch-ms", or anything else as a strftime pattern.
+    pub fn parse(format: &str) -> Result<Self, String> {
+        Ok(match format {
+            "rfc3339" => TimeFormat::Rfc3339,
+            "epoch" => TimeFormat::EpochSeconds,
+            "epoch-ms" => TimeFormat::EpochMillis,
+            pattern => {
+                check_pattern(pattern)?;
+                TimeFormat::Strftime(pattern.to_string())
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timestamps {
+    pub zone: Zone,
+    pub format: TimeFormat,
+}
+
+impl Timestamps {
+    pub fn format(&self, time: DateTime<Utc>) -> String {
+        match &self.format {
+            TimeFormat::Rfc3339 => match self.zone {
+                Zone::Utc => time.to_rfc3339(),
+                Zone::Local => time.with_timezone(&Local).to_rfc3339(),
+                Zone::Named(tz) => time.with_timezone(&tz).to_rfc3339(),
+            },
+            TimeFormat::EpochSeconds => time.timestamp().to_string(),
+            TimeFormat::EpochMillis => time.timestamp_millis().to_string(),
+            TimeFormat::Strftime(pattern) => match self.zone {
+                Zone::Utc => time.format(pattern).to_string(),
+                Zone::Local => time.with_timezone(&Local).format(pattern).to_string(),
+                Zone::Named(tz) => time.with_timezone(&tz).format(pattern).to_string(),
+            },
+        }
+    }
+
+    /// Like [`Timestamps::format`], but epoch times are JSON numbers rather than strings.
+    pub fn to_json(&self, time: DateTime<Utc>) -> serde_json::Value {
+        match self.format {
+            TimeFormat::EpochSeconds => time.timestamp().into(),
+            TimeFormat::EpochMillis => time.timestamp_millis().into(),
+            _ => self.format(time).into(),
+        }
+    }
+}
+
+/// Where rows are written to.
+pub enum Destination {
+    Stdout(io::Stdout),
+    File(RotatingFile),
+}
+
+impl Destination {
+    /// A file if a path (or strftime pattern) is given, otherwise stdout.
+    pub fn new(path: Option<&str>) -> Result<Self, String> {
+        Ok(match path {
+            Some(path) => Destination::File(RotatingFile::new(path)?),
+            None => Destination::Stdout(io::stdout()),
+        })
+    }
+}
+
+/// Writes rows of one kind in the chosen format, flushing after every row so nothing is lost if
+/// the program is killed, unless [`buffered`](Self::buffered).
+pub struct RowWriter<R: Row> {
+    destination: Destination,
+    format: Format,
+    timestamps: Timestamps,
+    wrote_stdout_header: bool,
+    flush_interval: Duration,
+    row: PhantomData<R>,
+}
+
+impl<R: Row> RowWriter<R> {
+    pub fn new(destination: Destination, format: Format, timestamps: Timestamps) -> Self {
+        RowWriter {
+            destination,
+            format,
+            timestamps,
+            wrote_stdout_header: false,
+            flush_interval: Duration::ZERO,
+            row: PhantomData,
+        }
+    }
+
+    /// Only flushes files every `interval`, for rows that come in too often to write one by one.
+    pub fn buffered(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        &self.timestamps
+    }
+
+    /// The output for rows written at `time`, and whether it still needs a header.
+    fn output(&mut self, time: DateTime<Utc>) -> io::Result<(&mut dyn Write, bool)> {
+        Ok(match &mut self.destination {
+            Destination::Stdout(stdout) => {
+                let needs_header = !self.wrote_stdout_header;
+                self.wrote_stdout_header = true;
+                (stdout, needs_header)
+            }
+            Destination::File(file) => {
+                let (file, is_empty) = file.file_for(time.with_timezone(&Local))?;
+                (file, is_empty)
+            }
+        })
+    }
+
+    pub fn write(&mut self, row: &R) -> io::Result<()> {
+        self.write_under(row, row.csv_header())
+    }
+
+    /// Writes a row of another kind that has the same columns, and so the same header.
+    pub fn write_alike<T: Row>(&mut self, row: &T) -> io::Result<()> {
+        self.write_under(row, row.csv_header())
+    }
+
+    fn write_under<T: Row>(&mut self, row: &T, csv_header: Cow<str>) -> io::Result<()> {
+        let format = self.format;
+        let time = self.timestamps.format(row.time());
+        let json_time = self.timestamps.to_json(row.time());
+        let (out, needs_header) = self.output(row.time())?;
+        match format {
+            Format::Csv => {
+                if needs_header {
+                    writeln!(out, "{}", csv_header)?;
+                }
+                writeln!(out, "{}", row.to_csv(&time))?;
+            }
+            Format::Jsonl => write_json(out, row, json_time)?,
+            Format::Oscar => {
+                if needs_header {
+                    writeln!(out, "{}", R::OSCAR_HEADER)?;
+                }
+                writeln!(out, "{}", row.to_oscar())?;
+            }
+        }
+        self.flush_every(self.flush_interval)
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Writes a row of another kind among these ones as JSON, which has a key to tell it apart.
+    pub fn write_json<T: Row>(&mut self, row: &T) -> io::Result<()> {
+        let json_time = self.timestamps.to_json(row.time());
+        let (out, _) = self.output(row.time())?;
+        write_json(out, row, json_time)?;
+        self.flush_every(self.flush_interval)
+    }
+
+    /// Writes a line that was already formatted, without any header.
+    pub fn write_line(&mut self, time: DateTime<Utc>, line: &str) -> io::Result<()> {
+        let (out, _) = self.output(time)?;
+        writeln!(out, "{}", line)?;
+        self.flush_every(self.flush_interval)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flush_every(Duration::ZERO)
+    }
+
+    fn flush_every(&mut self, interval: Duration) -> io::Result<()> {
+        match &mut self.destination {
+            Destination::Stdout(stdout) => stdout.flush(),
+            Destination::File(file) => file.flush_every(interval),
+        }
+    }
+}
+
+fn write_json<T: Row>(out: &mut dyn Write, row: &T, time: serde_json::Value) -> io::Result<()> {
+    let mut value = serde_json::to_value(row)?;
+    value["time"] = time;
+    serde_json::to_writer(&mut *out, &value)?;
+    writeln!(out)
+}
@@ -0,0 +1,105 @@
+//! Bonding with the device before subscribing, as some platforms refuse notifications from
+//! devices that aren't bonded.
+
+use btleplug::api::Peripheral as _;
+use btleplug::platform::Peripheral;
+use std::error::Error;
+
+/// Pairs with the connected `peripheral` unless it's already paired.
+#[cfg(target_os = "linux")]
+pub async fn pair(peripheral: &Peripheral) -> Result<(), Box<dyn Error>> {
+    bluez::pair(&peripheral.address().to_string()).await
+}
+
+/// Elsewhere, the OS pairs on its own when the device asks for it.
+#[cfg(not(target_os = "linux"))]
+pub async fn pair(peripheral: &Peripheral) -> Result<(), Box<dyn Error>> {
+    debug!("Leaving pairing with {} to the OS", peripheral.address());
+    Ok(())
+}
+
+/// btleplug can't pair, so on Linux this talks to BlueZ over D-Bus directly.
+#[cfg(target_os = "linux")]
+mod bluez {
+    use dbus::arg::{prop_cast, Variant};
+    use dbus::channel::{MatchingReceiver, Sender};
+    use dbus::message::MatchRule;
+    use dbus::nonblock::stdintf::org_freedesktop_dbus::{ObjectManager, Properties};
+    use dbus::nonblock::{Proxy, SyncConnection};
+    use dbus::Path;
+    use std::error::Error;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const DEVICE: &str = "org.bluez.Device1";
+    const AGENT_MANAGER: &str = "org.bluez.AgentManager1";
+    const AGENT_PATH: &str = "/pc60fw/agent";
+    /// Generous, as pairing can wait on the device.
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub async fn pair(address: &str) -> Result<(), Box<dyn Error>> {
+        let (resource, connection) = dbus_tokio::connection::new_system_sync()?;
+        let connection_task = tokio::spawn(async {
+            let err = resource.await;
+            debug!("Lost connection to D-Bus: {}", err);
+        });
+        let result = pair_over(&connection, address).await;
+        connection_task.abort();
+        result
+    }
+
+    async fn pair_over(connection: &Arc<SyncConnection>, address: &str) -> Result<(), Box<dyn Error>> {
+        let root = Proxy::new("org.bluez", "/", TIMEOUT, connection.clone());
+        let objects = root.get_managed_objects().await?;
+        let (path, properties) = objects
+            .iter()
+            .filter_map(|(path, interfaces)| Some((path, interfaces.get(DEVICE)?)))
+            .find(|(_, properties)| prop_cast::<String>(properties, "Address").is_some_and(|a| a.eq_ignore_ascii_case(address)))
+            .ok_or_else(|| format!("BlueZ doesn't know about {}", address))?;
+        if prop_cast::<bool>(properties, "Paired") == Some(&true) {
+            debug!("Already paired with {}", address);
+            return Ok(());
+        }
+
+        // BlueZ asks the agent of whoever started pairing to confirm it. The oximeter has no
+        // display or buttons, so this one agrees to everything.
+        let agent = connection.start_receive(
+            MatchRule::new_method_call().with_path(AGENT_PATH),
+            Box::new(|message, connection| {
+                let reply = match message.member().as_deref() {
+                    Some("RequestPinCode") | Some("RequestPasskey") => {
+                        message.error(&"org.bluez.Error.Rejected".into(), c"No way to enter a PIN")
+                    }
+                    _ => message.method_return(),
+                };
+                let _ = connection.send(reply);
+                true
+            }),
+        );
+        let bluez = Proxy::new("org.bluez", "/org/bluez", TIMEOUT, connection.clone());
+        let registered: Result<(), dbus::Error> = bluez
+            .method_call(AGENT_MANAGER, "RegisterAgent", (Path::from(AGENT_PATH), "NoInputNoOutput"))
+            .await;
+        if let Err(err) = &registered {
+            warn!("Couldn't register a pairing agent, pairing may fail: {}", err);
+        }
+
+        info!("Pairing with {}...", address);
+        let device = Proxy::new("org.bluez", path.clone(), TIMEOUT, connection.clone());
+        let paired: Result<(), dbus::Error> = device.method_call(DEVICE, "Pair", ()).await;
+
+        if registered.is_ok() {
+            let _: Result<(), _> = bluez.method_call(AGENT_MANAGER, "UnregisterAgent", (Path::from(AGENT_PATH),)).await;
+        }
+        connection.stop_receive(agent);
+        match paired {
+            Ok(()) => {}
+            Err(err) if err.name() == Some("org.bluez.Error.AlreadyExists") => {}
+            Err(err) => return Err(format!("Pairing failed: {}", err).into()),
+        }
+        // Trusted devices can reconnect without being asked about again.
+        device.set(DEVICE, "Trusted", Variant(true)).await?;
+        info!("Paired with {}", address);
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! The protocols of the oximeters this can read, and where each is spoken over BLE.
+
+use btleplug::api::bleuuid::uuid_from_u16;
+use clap::ValueEnum;
+use pc60fw_protocol::{cms50, plx, viatom, Command, Message, Parser, ParserStats, Profile};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::cli::Args;
+
+/// The Nordic UART service the PC-60FW and its rebrands use.
+const NUS_SERVICE: Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+const NUS_TX: Uuid = Uuid::from_u128(0x6e400002_b5a3_f393_e0a9_e50e24dcca9e);
+const NUS_RX: Uuid = Uuid::from_u128(0x6e400003_b5a3_f393_e0a9_e50e24dcca9e);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Protocol {
+    /// The PC-60FW's own frames, over the Nordic UART service.
+    Pc60fw,
+    /// The standard Bluetooth Pulse Oximeter Service.
+    Plx,
+    /// Viatom's ring and wrist oximeters, like the Wellue O2Ring and Checkme O2.
+    Viatom,
+    /// Contec's CMS50D+ and CMS50E.
+    Cms50,
+    /// Work it out from the services the device has, and if it has several protocols' services,
+    /// from what it sends.
+    Auto,
+}
+
+/// Which of Creative Medical's oximeters is speaking the pc60fw protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Model {
+    #[value(name = "pc-60fw")]
+    Pc60fw,
+    #[value(name = "pc-68b")]
+    Pc68b,
+    #[value(name = "pc-66b")]
+    Pc66b,
+    #[value(name = "ap-20")]
+    Ap20,
+}
+
+impl Model {
+    pub fn profile(self) -> Profile {
+        match self {
+            Model::Pc60fw => Profile::PC_60FW,
+            Model::Pc68b => Profile::PC_68B,
+            Model::Pc66b => Profile::PC_66B,
+            Model::Ap20 => Profile::AP_20,
+        }
+    }
+}
+
+impl Protocol {
+    /// Every protocol `Auto` picks from.
+    pub const KNOWN: [Protocol; 4] = [Protocol::Pc60fw, Protocol::Plx, Protocol::Viatom, Protocol::Cms50];
+
+    /// Whether this protocol's frames have a header and a checksum, so they can't be mistaken for
+    /// another protocol's. PLX measurements are bare values, and CMS50 packets only have a sync
+    /// bit.
+    fn signed(self) -> bool {
+        matches!(self, Protocol::Pc60fw | Protocol::Viatom)
+    }
+
+    /// The services devices speaking this protocol advertise.
+    pub fn services(self) -> Vec<Uuid> {
+        match self {
+            Protocol::Pc60fw => vec![NUS_SERVICE],
+            Protocol::Plx => vec![uuid_from_u16(plx::SERVICE)],
+            Protocol::Viatom => vec![Uuid::from_u128(viatom::SERVICE)],
+            Protocol::Cms50 => vec![uuid_from_u16(cms50::SERVICE)],
+            Protocol::Auto => Protocol::KNOWN.iter().flat_map(|protocol| protocol.services()).collect(),
+        }
+    }
+
+    /// The characteristics readings arrive on, by notification or indication, unless
+    /// `--rx-characteristic` says otherwise.
+    pub fn rx_characteristics(self, args: &Args) -> Vec<Uuid> {
+        if let Some(uuid) = args.rx_characteristic {
+            return vec![uuid];
+        }
+        match self {
+            Protocol::Pc60fw => vec![NUS_RX],
+            Protocol::Plx => vec![
+                uuid_from_u16(plx::CONTINUOUS_MEASUREMENT),
+                uuid_from_u16(plx::SPOT_CHECK_MEASUREMENT),
+            ],
+            Protocol::Viatom => vec![Uuid::from_u128(viatom::NOTIFY_CHARACTERISTIC)],
+            Protocol::Cms50 => vec![uuid_from_u16(cms50::CHARACTERISTIC)],
+            Protocol::Auto => Protocol::KNOWN.iter().flat_map(|protocol| protocol.rx_characteristics(args)).collect(),
+        }
+    }
+
+    /// The characteristic commands are written to, for protocols that have commands, unless
+    /// `--tx-characteristic` says otherwise.
+    pub fn tx_characteristic(self, args: &Args) -> Option<Uuid> {
+        if args.tx_characteristic.is_some() {
+            return args.tx_characteristic;
+        }
+        match self {
+            Protocol::Pc60fw => Some(NUS_TX),
+            Protocol::Plx | Protocol::Auto => None,
+            Protocol::Viatom => Some(Uuid::from_u128(viatom::WRITE_CHARACTERISTIC)),
+            Protocol::Cms50 => Some(uuid_from_u16(cms50::CHARACTERISTIC)),
+        }
+    }
+
+    /// What to write after subscribing to make the device start sending.
+    pub fn start_commands(self, args: &Args) -> Vec<Vec<u8>> {
+        match self {
+            Protocol::Pc60fw if !args.no_start_command => {
+                Command::START.iter().map(|command| command.to_frame().encode()).collect()
+            }
+            // Older firmware ignores this and streams anyway.
+            Protocol::Cms50 if !args.no_start_command => vec![cms50::start_command()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// What to write periodically while connected, and how often.
+    pub fn poll(self, args: &Args) -> Option<(Duration, Vec<u8>)> {
+        match self {
+            // Some firmware stops streaming after a while unless it hears from the host.
+            Protocol::Pc60fw if !args.keep_alive.is_zero() => {
+                Some((args.keep_alive, Command::KEEP_ALIVE.to_frame().encode()))
+            }
+            Protocol::Viatom => Some((Duration::from_secs(1), viatom::real_time_request())),
+            Protocol::Cms50 => Some((Duration::from_secs(5), cms50::keep_alive_command())),
+            _ => None,
+        }
+    }
+
+    pub fn decoder(self, args: &Args) -> Decoder {
+        match self {
+            Protocol::Pc60fw => {
+                let profile = args.profile.unwrap_or_else(|| args.model.profile());
+                Decoder::Pc60fw(Parser::new().skip_checksum(args.skip_checksum).header(profile.header), profile)
+            }
+            Protocol::Plx => Decoder::Plx(ParserStats::default()),
+            Protocol::Viatom => Decoder::Viatom(viatom::Parser::new()),
+            Protocol::Cms50 => Decoder::Cms50(cms50::Parser::new()),
+            Protocol::Auto => Decoder::detect(args, &Protocol::KNOWN),
+        }
+    }
+}
+
+/// Turns notifications into [`Message`]s, whichever protocol the device speaks.
+pub enum Decoder {
+    Pc60fw(Parser, Profile),
+    /// Each PLX notification is a whole measurement, so there's only counting to do.
+    Plx(ParserStats),
+    Viatom(viatom::Parser),
+    Cms50(cms50::Parser),
+    /// Tries every candidate until one makes sense of what the device sends, then becomes it.
+    Detect(Vec<Candidate>),
+}
+
+pub struct Candidate {
+    protocol: Protocol,
+    rx: Vec<Uuid>,
+    decoder: Decoder,
+}
+
+impl Decoder {
+    /// A decoder that works out which of `protocols` the device speaks.
+    ///
+    /// With `--rx-characteristic`, every candidate listens to the same characteristic, and the
+    /// first to make anything of what arrives would win. Only protocols with a signature can tell
+    /// their frames from the others' there, so the rest have to be asked for with `--protocol`.
+    pub fn detect(args: &Args, protocols: &[Protocol]) -> Decoder {
+        let (protocols, unsigned): (Vec<Protocol>, Vec<Protocol>) =
+            protocols.iter().partition(|protocol| args.rx_characteristic.is_none() || protocol.signed());
+        if !unsigned.is_empty() {
+            info!("Not detecting {:?} on --rx-characteristic; pass --protocol to use one of them", unsigned);
+        }
+        let candidates = protocols
+            .iter()
+            .map(|&protocol| Candidate {
+                protocol,
+                rx: protocol.rx_characteristics(args),
+                decoder: protocol.decoder(args),
+            })
+            .collect();
+        Decoder::Detect(candidates)
+    }
+
+    /// The protocol being decoded, once it's known.
+    pub fn protocol(&self) -> Option<Protocol> {
+        match self {
+            Decoder::Pc60fw(..) => Some(Protocol::Pc60fw),
+            Decoder::Plx(_) => Some(Protocol::Plx),
+            Decoder::Viatom(_) => Some(Protocol::Viatom),
+            Decoder::Cms50(_) => Some(Protocol::Cms50),
+            Decoder::Detect(_) => None,
+        }
+    }
+
+    /// Decodes a notification from `characteristic`.
+    pub fn decode(&mut self, characteristic: Uuid, value: &[u8]) -> Vec<Message> {
+        match self {
+            Decoder::Pc60fw(parser, profile) => {
+                parser.push(value);
+                std::iter::from_fn(|| parser.next_frame()).map(|frame| profile.decode(&frame)).collect()
+            }
+            Decoder::Plx(stats) => {
+                let reading = if characteristic == uuid_from_u16(plx::CONTINUOUS_MEASUREMENT) {
+                    plx::continuous_measurement(value)
+                } else {
+                    plx::spot_check_measurement(value)
+                };
+                match reading {
+                    Some(reading) => {
+                        stats.frames += 1;
+                        vec![Message::Parameters(reading)]
+                    }
+                    None => {
+                        stats.discarded_bytes += value.len() as u64;
+                        Vec::new()
+                    }
+                }
+            }
+            Decoder::Viatom(parser) => {
+                parser.push(value);
+                std::iter::from_fn(|| parser.next_message()).collect()
+            }
+            Decoder::Cms50(parser) => {
+                parser.push(value);
+                std::iter::from_fn(|| parser.next_message()).collect()
+            }
+            Decoder::Detect(candidates) => {
+                for index in 0..candidates.len() {
+                    let candidate = &mut candidates[index];
+                    if !candidate.rx.contains(&characteristic) {
+                        continue;
+                    }
+                    let messages = candidate.decoder.decode(characteristic, value);
+                    // Garbage can look like a frame of a type nobody knows, but not like a reading.
+                    if messages.iter().any(|message| !matches!(message, Message::Unknown(_))) {
+                        info!("Detected the {:?} protocol", candidate.protocol);
+                        *self = candidates.swap_remove(index).decoder;
+                        return messages;
+                    }
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn stats(&self) -> ParserStats {
+        match self {
+            Decoder::Pc60fw(parser, _) => parser.stats(),
+            Decoder::Plx(stats) => *stats,
+            Decoder::Viatom(parser) => parser.stats(),
+            Decoder::Cms50(parser) => parser.stats(),
+            Decoder::Detect(candidates) => {
+                let mut stats = ParserStats::default();
+                for candidate in candidates {
+                    let candidate = candidate.decoder.stats();
+                    stats.frames += candidate.frames;
+                    stats.checksum_errors += candidate.checksum_errors;
+                    stats.discarded_bytes += candidate.discarded_bytes;
+                }
+                stats
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser as _;
+    use pc60fw_protocol::Frame;
+
+    /// A PLX continuous measurement of SpO2 97% and 60 bpm.
+    const PLX_MEASUREMENT: [u8; 5] = [0x00, 97, 0x00, 60, 0x00];
+
+    fn decoder(flags: &[&str]) -> Decoder {
+        let args = Args::try_parse_from(["ble-spo2", "--protocol", "auto"].iter().chain(flags)).unwrap();
+        args.protocol.decoder(&args)
+    }
+
+    #[test]
+    fn detects_the_protocol_from_its_characteristic() {
+        let mut decoder = decoder(&[]);
+        let messages = decoder.decode(uuid_from_u16(plx::CONTINUOUS_MEASUREMENT), &PLX_MEASUREMENT);
+        assert!(matches!(messages[..], [Message::Parameters(_)]));
+        assert_eq!(decoder.protocol(), Some(Protocol::Plx));
+    }
+
+    #[test]
+    fn needs_a_signature_on_an_overridden_characteristic() {
+        let rx = Uuid::from_u128(0x0000ffe4_0000_1000_8000_00805f9b34fb);
+        let mut decoder = decoder(&["--rx-characteristic", &rx.to_string()]);
+        assert!(decoder.decode(rx, &PLX_MEASUREMENT).is_empty());
+        assert_eq!(decoder.protocol(), None);
+
+        let frame = Frame::new(0x0f, vec![0x01, 97, 60, 0, 30, 6 << 4]).encode();
+        let messages = decoder.decode(rx, &frame);
+        assert!(matches!(messages[..], [Message::Parameters(_)]));
+        assert_eq!(decoder.protocol(), Some(Protocol::Pc60fw));
+    }
+}
@@ -0,0 +1,192 @@
+use chrono::{DateTime, Utc};
+use pc60fw_protocol::{BatteryLevel, DeviceInfo, Message, ParserStats, ProbeStatus};
+use std::sync::atomic::Ordering;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::alarm::{self, Alarms};
+use crate::cli::Args;
+use crate::desaturation::Detector;
+use crate::hrv::Hrv;
+use crate::outlier::Outliers;
+use crate::output::{Event, EventRecord, Record, WaveformRecord};
+use crate::protocol::Decoder;
+use crate::respiration::Respiration;
+use crate::sink::Sinks;
+use crate::stats::Stats;
+
+/// Turns the notifications of one device into readings for the sinks, wherever the notifications
+/// come from.
+pub struct Receiver {
+    address: String,
+    decoder: Decoder,
+    battery: Option<BatteryLevel>,
+    /// Of the last reading, to tell when a finger goes in or out.
+    probe_status: Option<ProbeStatus>,
+    device_info: DeviceInfo,
+    desaturations: Detector,
+    alarms: Alarms,
+    outliers: Option<Outliers>,
+    /// Whether to keep the values from before `outliers` in the records.
+    keep_raw: bool,
+    hrv: Option<Hrv>,
+    respiration: Option<Respiration>,
+    /// Signal strength of the device, recorded with each reading.
+    pub rssi: Option<i16>,
+}
+
+impl Receiver {
+    pub fn new(address: String, decoder: Decoder, args: &Args, stats: &Stats) -> Self {
+        let device_info = DeviceInfo::default();
+        stats.set_device_info(&device_info);
+        Receiver {
+            address,
+            decoder,
+            battery: None,
+            probe_status: None,
+            device_info,
+            desaturations: Detector::new(args.desaturation_baseline, args.desaturation_baseline_window),
+            alarms: Alarms::new(args),
+            outliers: args.outlier_filter.map(|kind| Outliers::new(kind, args.outlier_window.into())),
+            keep_raw: args.keep_raw,
+            hrv: args.hrv.then(|| Hrv::new(args.hrv_window)),
+            respiration: args.respiratory_rate.then(|| Respiration::new(args.respiratory_rate_window)),
+            rssi: None,
+        }
+    }
+
+    pub fn parser_stats(&self) -> ParserStats {
+        self.decoder.stats()
+    }
+
+    /// Decodes a notification that arrived at `time` and passes on what's in it.
+    pub async fn receive(
+        &mut self,
+        time: DateTime<Utc>,
+        characteristic: Uuid,
+        value: &[u8],
+        sinks: &Mutex<Sinks>,
+        stats: &Stats,
+    ) {
+        let ParserStats { frames, checksum_errors, .. } = self.decoder.stats();
+        let messages = self.decoder.decode(characteristic, value);
+        for message in messages {
+            match message {
+                Message::Parameters(reading) => {
+                    let mut record = Record::new(time, &self.address, &reading, self.battery, self.rssi);
+                    if self.keep_raw {
+                        record.extra.spo2_raw = Some(record.spo2);
+                        record.extra.heartrate_raw = Some(record.heartrate);
+                    }
+                    if let Some(outliers) = &mut self.outliers {
+                        outliers.filter(&mut record);
+                    }
+                    if let Some(hrv) = &self.hrv {
+                        let (rmssd, sdnn) = hrv.metrics();
+                        record.extra.rmssd = Some(rmssd);
+                        record.extra.sdnn = Some(sdnn);
+                    }
+                    if let Some(respiration) = &self.respiration {
+                        record.extra.resp_rate = Some(respiration.rate());
+                    }
+                    debug!(
+                        device = record.device.as_str(), spo2 = record.spo2, heartrate = record.heartrate,
+                        "Reading from {}: SpO2 {}, heart rate {}",
+                        record.device,
+                        record.spo2.map_or("--".to_string(), |spo2| format!("{}%", spo2)),
+                        record.heartrate.map_or("--".to_string(), |heartrate| format!("{} bpm", heartrate))
+                    );
+                    stats.reading(&record);
+                    let mut events: Vec<EventRecord> = self.finger_event(&record).into_iter().collect();
+                    events.extend(self.desaturations.reading(&record));
+                    events.extend(self.alarms.reading(&record));
+                    let mut sinks = sinks.lock().await;
+                    sinks.reading(&record).await;
+                    for event in events {
+                        let kind = event.event.kind();
+                        match event.event {
+                            Event::Alarm { .. } => warn!(
+                                target: alarm::LOG_TARGET,
+                                device = event.device.as_str(), event = kind, spo2 = record.spo2, heartrate = record.heartrate,
+                                "{} from {}", event.event, event.device
+                            ),
+                            _ => info!(
+                                device = event.device.as_str(), event = kind, spo2 = record.spo2, heartrate = record.heartrate,
+                                "{} from {} at {}", event.event, event.device, event.time
+                            ),
+                        }
+                        stats.event(&event);
+                        sinks.event(&event).await;
+                    }
+                }
+                Message::Waveform(samples) => {
+                    if let Some(hrv) = &mut self.hrv {
+                        hrv.waveform(time, &samples);
+                    }
+                    if let Some(respiration) = &mut self.respiration {
+                        respiration.waveform(time, &samples);
+                    }
+                    let mut sinks = sinks.lock().await;
+                    for sample in &samples {
+                        sinks.waveform(&WaveformRecord::new(time, sample)).await;
+                    }
+                }
+                Message::Battery(level) => {
+                    if level.is_low() && self.battery != Some(level) {
+                        warn!(device = self.address.as_str(), event = "battery-low", "Device battery is low");
+                        let event = EventRecord {
+                            time,
+                            device: self.address.clone(),
+                            event: Event::BatteryLow { level: level.0 },
+                        };
+                        stats.event(&event);
+                        sinks.lock().await.event(&event).await;
+                    }
+                    self.battery = Some(level);
+                }
+                Message::Info(field) => {
+                    debug!("Got device info: {:?}", field);
+                    let was_complete = self.device_info.is_complete();
+                    self.device_info.update(field);
+                    stats.set_device_info(&self.device_info);
+                    if !was_complete && self.device_info.is_complete() {
+                        info!("Connected to device:\n{}", self.device_info);
+                    }
+                }
+                Message::Unknown(frame) => trace!("Ignoring unknown frame: {:?}", frame),
+            }
+        }
+        let new_stats = self.decoder.stats();
+        if new_stats.checksum_errors > checksum_errors {
+            debug!("Dropped frame with bad checksum from {:?}", value);
+        }
+        // Saturating, as a decoder that was detecting the protocol only keeps one candidate's counts.
+        stats.frames.fetch_add(new_stats.frames.saturating_sub(frames), Ordering::Relaxed);
+        stats.checksum_errors.fetch_add(new_stats.checksum_errors.saturating_sub(checksum_errors), Ordering::Relaxed);
+    }
+
+    /// Passes on what was still going on once the readings stop, like a desaturation that hadn't
+    /// ended yet, so it still counts.
+    pub async fn close(&mut self, sinks: &Mutex<Sinks>, stats: &Stats) {
+        for event in self.desaturations.close(&self.address) {
+            info!(
+                device = event.device.as_str(), event = event.event.kind(),
+                "{} from {} at {}", event.event, event.device, event.time
+            );
+            stats.event(&event);
+            sinks.lock().await.event(&event).await;
+        }
+    }
+
+    /// An event for a finger going into or out of the probe, if it just did.
+    fn finger_event(&mut self, record: &Record) -> Option<EventRecord> {
+        let previous = self.probe_status.replace(record.status)?;
+        let event = match (previous, record.status) {
+            (ProbeStatus::NoFinger, ProbeStatus::NoFinger) => return None,
+            (ProbeStatus::NoFinger, _) => Event::FingerInserted,
+            (_, ProbeStatus::NoFinger) => Event::FingerRemoved,
+            _ => return None,
+        };
+        Some(EventRecord { time: record.time, device: record.device.clone(), event })
+    }
+}
@@ -0,0 +1,118 @@
+//! Experimental respiratory rate estimation with `--respiratory-rate`, from how breathing makes
+//! the baseline of the plethysmograph waveform rise and fall.
+//!
+//! The waveform is averaged over each heart beat, using the device's beat markers, which leaves
+//! the slow swing that breathing adds. After taking out any drift over the window, every time
+//! that swing goes from clearly below its mean to clearly above counts as a breath. This is easily
+//! thrown by movement, and by devices that adjust the waveform's gain as they go.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use pc60fw_protocol::WaveformSample;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Waveform frames further apart than this mean some were lost, so beats are counted afresh.
+const MAX_FRAME_GAP: TimeDelta = TimeDelta::seconds(1);
+
+/// How far past the mean, in standard deviations, the baseline has to swing to count, so noise
+/// around the mean isn't taken for breaths.
+const HYSTERESIS: f64 = 0.5;
+
+/// How many beats there have to be in the window to go by.
+const MIN_BEATS: usize = 20;
+
+/// Rates outside this range, in breaths a minute, are more likely to be noise than breathing.
+const RATES: std::ops::RangeInclusive<f64> = 4.0..=40.0;
+
+/// Tracks one device's waveform baseline over a sliding window.
+pub struct Respiration {
+    window: TimeDelta,
+    last_frame: Option<DateTime<Utc>>,
+    /// Whether the last sample was marked as a beat, as some devices mark a few in a row.
+    in_beat: bool,
+    /// The sum and number of samples since the last beat, once there's been one.
+    beat: Option<(u32, u32)>,
+    /// The mean of the waveform over each beat in the window, and when the beat ended.
+    baseline: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl Respiration {
+    pub fn new(window: Duration) -> Self {
+        Respiration {
+            window: TimeDelta::from_std(window).unwrap_or(TimeDelta::MAX),
+            last_frame: None,
+            in_beat: false,
+            beat: None,
+            baseline: VecDeque::new(),
+        }
+    }
+
+    /// Takes the samples from a waveform frame that arrived at `time`.
+    pub fn waveform(&mut self, time: DateTime<Utc>, samples: &[WaveformSample]) {
+        if self.last_frame.is_some_and(|last_frame| time - last_frame > MAX_FRAME_GAP) {
+            self.in_beat = false;
+            self.beat = None;
+            self.baseline.clear();
+        }
+        self.last_frame = Some(time);
+        for sample in samples {
+            if sample.pulse_beat && !self.in_beat {
+                if let Some((sum, count)) = self.beat.filter(|&(_, count)| count > 0) {
+                    self.baseline.push_back((time, f64::from(sum) / f64::from(count)));
+                }
+                self.beat = Some((0, 0));
+            }
+            self.in_beat = sample.pulse_beat;
+            if let Some((sum, count)) = &mut self.beat {
+                *sum += u32::from(sample.pleth);
+                *count += 1;
+            }
+        }
+        while self.baseline.front().is_some_and(|&(beat, _)| time - beat > self.window) {
+            self.baseline.pop_front();
+        }
+    }
+
+    /// Breaths a minute over the window, once it's mostly full and breathing can be made out.
+    pub fn rate(&self) -> Option<f32> {
+        let (first, _) = *self.baseline.front()?;
+        let (last, _) = *self.baseline.back()?;
+        if self.baseline.len() < MIN_BEATS || last - first < self.window / 2 {
+            return None;
+        }
+        let seconds = |time: DateTime<Utc>| (time - first).num_milliseconds() as f64 / 1000.0;
+        let points: Vec<(f64, f64)> = self.baseline.iter().map(|&(time, value)| (seconds(time), value)).collect();
+        let residuals = detrend(&points);
+        let deviation = (residuals.iter().map(|(_, r)| r * r).sum::<f64>() / residuals.len() as f64).sqrt();
+        if deviation == 0.0 {
+            return None;
+        }
+
+        let mut below = false;
+        let mut breaths = Vec::new();
+        for &(time, residual) in &residuals {
+            if residual < -HYSTERESIS * deviation {
+                below = true;
+            } else if below && residual > HYSTERESIS * deviation {
+                below = false;
+                breaths.push(time);
+            }
+        }
+        if breaths.len() < 3 {
+            return None;
+        }
+        let rate = (breaths.len() - 1) as f64 / (breaths[breaths.len() - 1] - breaths[0]) * 60.0;
+        RATES.contains(&rate).then_some(((rate * 10.0).round() / 10.0) as f32)
+    }
+}
+
+/// Takes the least squares line through `points` out of them.
+fn detrend(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let slope = if variance == 0.0 { 0.0 } else { covariance / variance };
+    points.iter().map(|&(x, y)| (x, y - mean_y - slope * (x - mean_x))).collect()
+}
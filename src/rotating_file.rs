@@ -0,0 +1,72 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// A file whose name is a strftime pattern, e.g. `readings-%Y-%m-%d.csv`.
+///
+/// Whenever the formatted name changes (at midnight, for the example above), the next write goes
+/// to a new file. Files are appended to rather than truncated, so restarting doesn't lose data.
+/// Writes are buffered until [`flush`](Self::flush) or [`flush_every`](Self::flush_every).
+#[derive(Debug)]
+pub struct RotatingFile {
+    pattern: String,
+    current: Option<(PathBuf, BufWriter<File>)>,
+    last_flush: Instant,
+}
+
+/// Makes sure formatting `pattern` won't fail later on.
+pub fn check_pattern(pattern: &str) -> Result<(), String> {
+    if StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("Invalid strftime pattern in {:?}", pattern));
+    }
+    Ok(())
+}
+
+impl RotatingFile {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        check_pattern(pattern)?;
+        Ok(RotatingFile {
+            pattern: pattern.to_string(),
+            current: None,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Returns the file that writes at `time` should go to, and whether it's empty, e.g. because
+    /// it was just created.
+    pub fn file_for(&mut self, time: DateTime<Local>) -> io::Result<(&mut BufWriter<File>, bool)> {
+        let path = PathBuf::from(time.format(&self.pattern).to_string());
+        if self.current.as_ref().map(|(current, _)| current) != Some(&path) {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            info!("Writing to {:?}", path);
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            // Dropping the previous file flushes it.
+            self.current = Some((path, BufWriter::new(file)));
+        }
+        let (_, file) = self.current.as_mut().unwrap();
+        let is_empty = file.buffer().is_empty() && file.get_ref().metadata()?.len() == 0;
+        Ok((file, is_empty))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        match &mut self.current {
+            Some((_, file)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes if it's been `interval` since the last time, so that frequent small writes are
+    /// gathered into fewer larger ones.
+    pub fn flush_every(&mut self, interval: Duration) -> io::Result<()> {
+        if self.last_flush.elapsed() < interval {
+            return Ok(());
+        }
+        self.flush()
+    }
+}
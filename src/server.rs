@@ -0,0 +1,182 @@
+//! HTTP server for scraping, polling and streaming the reader, and a dashboard page to watch it on.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures::stream::{self, Stream, StreamExt};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+use crate::live::{LiveFeed, LiveReading};
+use crate::output::WaveformRecord;
+use crate::stats::Stats;
+
+/// A self-contained page, so it works on a LAN without internet access.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+#[derive(Clone)]
+struct AppState {
+    stats: Arc<Stats>,
+    live: Arc<LiveFeed>,
+}
+
+async fn dashboard() -> Html<&'static str> {
+    Html(DASHBOARD)
+}
+
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.stats.to_prometheus(),
+    )
+}
+
+/// The most recent reading, or 404 if there hasn't been one yet.
+async fn latest(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let record = state.stats.latest().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(json!(record)))
+}
+
+/// Connection state, battery and frame counters, for health checks.
+async fn status(State(state): State<AppState>) -> Json<Value> {
+    let stats = &state.stats;
+    let latest = stats.latest();
+    let device_info = stats.device_info();
+    Json(json!({
+        "connected": stats.connected.load(Ordering::Relaxed) > 0,
+        "connections": stats.connections.load(Ordering::Relaxed),
+        "battery": latest.as_ref().and_then(|r| r.battery),
+        "last_reading": latest.as_ref().map(|r| r.time.to_rfc3339()),
+        "frames": stats.frames.load(Ordering::Relaxed),
+        "checksum_errors": stats.checksum_errors.load(Ordering::Relaxed),
+        "device": {
+            "model": device_info.model,
+            "serial_number": device_info.serial_number,
+            "software_version": device_info.software_version,
+            "hardware_version": device_info.hardware_version,
+        },
+    }))
+}
+
+/// Streams every reading as a JSON text message.
+async fn websocket(State(state): State<AppState>, upgrade: WebSocketUpgrade) -> impl IntoResponse {
+    let receiver = state.live.subscribe();
+    upgrade.on_upgrade(move |socket| stream_readings(socket, receiver))
+}
+
+/// Streams every pleth sample as a JSON text message.
+async fn waveform(State(state): State<AppState>, upgrade: WebSocketUpgrade) -> impl IntoResponse {
+    let receiver = state.live.subscribe_waveform();
+    upgrade.on_upgrade(move |socket| stream_waveform(socket, receiver))
+}
+
+/// Waits for the next message, skipping over any the receiver was too slow to get.
+async fn next_message<T: Clone>(receiver: &mut broadcast::Receiver<T>) -> Option<T> {
+    loop {
+        match receiver.recv().await {
+            Ok(message) => return Some(message),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                debug!("Live client fell behind, skipped {} messages", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Sends `value` as JSON, returning false once the client has gone away.
+async fn send_json(socket: &mut WebSocket, value: &impl serde::Serialize) -> bool {
+    let json = match serde_json::to_string(value) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Couldn't serialize live message: {}", e);
+            return true;
+        }
+    };
+    socket.send(Message::Text(json.into())).await.is_ok()
+}
+
+async fn stream_readings(mut socket: WebSocket, mut receiver: broadcast::Receiver<LiveReading>) {
+    while let Some(reading) = next_message(&mut receiver).await {
+        if !send_json(&mut socket, &reading.record).await {
+            break;
+        }
+    }
+}
+
+async fn stream_waveform(mut socket: WebSocket, mut receiver: broadcast::Receiver<WaveformRecord>) {
+    while let Some(sample) = next_message(&mut receiver).await {
+        if !send_json(&mut socket, &sample).await {
+            break;
+        }
+    }
+}
+
+/// Streams every reading as a server-sent event. Clients that reconnect with a Last-Event-ID
+/// header first get the buffered readings they missed.
+async fn events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // Subscribe before looking at the buffer, so nothing falls in between.
+    let receiver = state.live.subscribe();
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|id| id.to_str().ok())
+        .and_then(|id| id.split_once('-'))
+        .and_then(|(epoch, id)| {
+            // An id from before a restart says nothing about what the client has seen of this
+            // run, so it gets all of it.
+            if epoch.parse::<i64>().ok()? == state.live.epoch {
+                id.parse::<u64>().ok()
+            } else {
+                Some(0)
+            }
+        });
+    let missed = last_event_id.map(|id| state.live.since(id)).unwrap_or_default();
+    let replayed_up_to = missed.last().map(|r| r.id).or(last_event_id).unwrap_or(0);
+
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        next_message(&mut receiver).await.map(|reading| (reading, receiver))
+    })
+    .filter(move |reading| futures::future::ready(reading.id > replayed_up_to));
+    let epoch = state.live.epoch;
+    let events = stream::iter(missed).chain(live).map(move |reading| {
+        Ok(Event::default()
+            .id(format!("{}-{}", epoch, reading.id))
+            .event("reading")
+            .json_data(&reading.record)
+            .unwrap_or_else(|_| Event::default().comment("unserializable reading")))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Starts serving in the background. Fails right away if the address can't be bound.
+pub async fn spawn(address: SocketAddr, stats: Arc<Stats>, live: Arc<LiveFeed>) -> Result<(), Box<dyn Error>> {
+    let app = Router::new()
+        .route("/", get(dashboard))
+        .route("/latest", get(latest))
+        .route("/status", get(status))
+        .route("/metrics", get(metrics))
+        .route("/ws", get(websocket))
+        .route("/ws/pleth", get(waveform))
+        .route("/events", get(events))
+        .with_state(AppState { stats, live });
+    let listener = TcpListener::bind(address).await?;
+    info!("Serving HTTP on {}", address);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("HTTP server failed: {}", e);
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Running as a Windows service, e.g. on a mini PC by the bed that should start reading at boot
+//! without anyone logging in. Messages go to the Event Log, as a service has no console.
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use windows_service::service::{
+    ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::config;
+
+/// The name to create the service with, which is also its Event Log source.
+const NAME: &str = "ble-spo2";
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Hands the process to the service manager, which runs the reader on another thread until the
+/// service is stopped.
+pub fn run() -> Result<(), Box<dyn Error>> {
+    crate::logging::init_event_log(NAME)?;
+    service_dispatcher::start(NAME, ffi_service_main)
+        .map_err(|e| format!("Couldn't start as a service, --service is only for the service manager: {}", e))?;
+    Ok(())
+}
+
+/// Arguments given when starting the service by hand are ignored, the ones it was created with
+/// are read like any other command line.
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("{}", e);
+    }
+}
+
+fn run_service() -> Result<(), Box<dyn Error>> {
+    let (stop_sender, stop) = oneshot::channel();
+    let mut stop_sender = Some(stop_sender);
+    let status = service_control_handler::register(NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            if let Some(sender) = stop_sender.take() {
+                let _ = sender.send(());
+            }
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    set_state(
+        &status,
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        ServiceExitCode::NO_ERROR,
+    )?;
+    info!("Service started");
+
+    let result = config::load_args().and_then(|args| {
+        tokio::runtime::Runtime::new()?.block_on(crate::run(args, async {
+            let _ = stop.await;
+        }))
+    });
+    let exit_code = match &result {
+        Ok(()) => ServiceExitCode::NO_ERROR,
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    set_state(&status, ServiceState::Stopped, ServiceControlAccept::empty(), exit_code)?;
+    result
+}
+
+fn set_state(
+    status: &ServiceStatusHandle,
+    state: ServiceState,
+    controls_accepted: ServiceControlAccept,
+    exit_code: ServiceExitCode,
+) -> Result<(), Box<dyn Error>> {
+    status.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    })?;
+    Ok(())
+}
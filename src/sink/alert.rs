@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{Sink, SinkError};
+use crate::output::{Event, EventRecord, Record};
+
+/// Where alerts are sent.
+#[derive(Debug, Clone)]
+pub enum AlertTarget {
+    /// Gets the event POSTed as JSON.
+    Webhook(String),
+    Pushover { token: String, user: String },
+    Telegram { bot_token: String, chat_id: String },
+}
+
+impl fmt::Display for AlertTarget {
+    /// Leaves out credentials, as this ends up in logs.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertTarget::Webhook(url) => write!(f, "webhook {}", url),
+            AlertTarget::Pushover { .. } => f.write_str("Pushover"),
+            AlertTarget::Telegram { .. } => f.write_str("Telegram"),
+        }
+    }
+}
+
+/// Sends alarms, and devices going away and coming back, to services that reach a phone, for
+/// whoever isn't at the computer.
+pub struct AlertSink {
+    client: reqwest::Client,
+    targets: Vec<AlertTarget>,
+    /// Devices that disconnected, so their next connection is a reconnection worth telling about.
+    lost: HashSet<String>,
+}
+
+impl AlertSink {
+    pub fn new(targets: Vec<AlertTarget>) -> Self {
+        AlertSink {
+            client: reqwest::Client::new(),
+            targets,
+            lost: HashSet::new(),
+        }
+    }
+}
+
+/// Sends `record` to `target`, with `title` for services that show one.
+async fn send(client: &reqwest::Client, target: &AlertTarget, title: &str, urgent: bool, record: &EventRecord) -> reqwest::Result<()> {
+    let message = format!("{} ({})", record.event, record.device);
+    let request = match target {
+        AlertTarget::Webhook(url) => client.post(url).json(record),
+        AlertTarget::Pushover { token, user } => client.post("https://api.pushover.net/1/messages.json").form(&[
+            ("token", token.as_str()),
+            ("user", user.as_str()),
+            ("title", title),
+            ("message", &message),
+            // High priority gets through quiet hours.
+            ("priority", if urgent { "1" } else { "0" }),
+        ]),
+        AlertTarget::Telegram { bot_token, chat_id } => client
+            .post(format!("https://api.telegram.org/bot{}/sendMessage", bot_token))
+            .json(&json!({ "chat_id": chat_id, "text": format!("{}: {}", title, message) })),
+    };
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+#[async_trait]
+impl Sink for AlertSink {
+    fn name(&self) -> String {
+        "alerts".to_string()
+    }
+
+    async fn reading(&mut self, _record: &Record) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        let (title, urgent) = match record.event {
+            Event::Alarm { .. } => ("Oximeter alarm", true),
+            Event::AlarmCleared { .. } => ("Oximeter alarm cleared", false),
+            Event::Disconnected => {
+                self.lost.insert(record.device.clone());
+                ("Oximeter disconnected", true)
+            }
+            Event::Connected if self.lost.remove(&record.device) => ("Oximeter reconnected", false),
+            _ => return Ok(()),
+        };
+        // Sent in the background, so a slow service doesn't hold up the readings.
+        for target in &self.targets {
+            let (client, target, record) = (self.client.clone(), target.clone(), record.clone());
+            tokio::spawn(async move {
+                if let Err(e) = send(&client, &target, title, urgent, &record).await {
+                    // Telegram's URL has the bot token in it.
+                    error!("Couldn't send alert to {}: {}", target, e.without_url());
+                }
+            });
+        }
+        Ok(())
+    }
+}
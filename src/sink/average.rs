@@ -0,0 +1,111 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use super::{Sink, SinkError};
+use crate::output::{Average, Destination, EventRecord, Format, Record, RowWriter, Timestamps};
+
+/// The readings from one device so far in the current interval.
+struct Interval {
+    start: DateTime<Utc>,
+    spo2: Vec<u8>,
+    heartrate: Vec<u8>,
+    readings: usize,
+}
+
+impl Interval {
+    fn average(&self, device: &str) -> Average {
+        Average {
+            time: self.start,
+            spo2: mean(&self.spo2),
+            spo2_min: self.spo2.iter().min().copied(),
+            spo2_max: self.spo2.iter().max().copied(),
+            heartrate: mean(&self.heartrate),
+            heartrate_min: self.heartrate.iter().min().copied(),
+            heartrate_max: self.heartrate.iter().max().copied(),
+            readings: self.readings,
+            device: device.to_string(),
+        }
+    }
+}
+
+/// Rounded to a tenth, which is as precise as the whole-number readings allow for.
+fn mean(values: &[u8]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    let mean = values.iter().map(|&v| f32::from(v)).sum::<f32>() / values.len() as f32;
+    Some((mean * 10.0).round() / 10.0)
+}
+
+/// Writes one row per interval instead of one per reading, with the mean, lowest and highest SpO2
+/// and heart rate, so long recordings take up a fraction of the space but still show the dips.
+///
+/// Intervals are aligned to the clock, e.g. to whole minutes for "1m", and an interval is written
+/// once the first reading after it arrives, or when the program exits.
+pub struct AverageRowSink {
+    writer: RowWriter<Average>,
+    interval: Duration,
+    /// By device, as each one's readings are averaged separately.
+    intervals: BTreeMap<String, Interval>,
+}
+
+impl AverageRowSink {
+    pub fn new(destination: Destination, format: Format, timestamps: Timestamps, interval: Duration) -> Self {
+        AverageRowSink {
+            writer: RowWriter::new(destination, format, timestamps),
+            interval,
+            intervals: BTreeMap::new(),
+        }
+    }
+
+    /// The start of the interval `time` falls in.
+    fn start_of(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let interval = (self.interval.as_millis() as i64).max(1);
+        let millis = time.timestamp_millis();
+        DateTime::from_timestamp_millis(millis - millis.rem_euclid(interval)).unwrap_or(time)
+    }
+}
+
+#[async_trait]
+impl Sink for AverageRowSink {
+    fn name(&self) -> String {
+        "averaged readings output".to_string()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let start = self.start_of(record.time);
+        if let Some(interval) = self.intervals.get(&record.device).filter(|interval| interval.start != start) {
+            self.writer.write(&interval.average(&record.device))?;
+            self.intervals.remove(&record.device);
+        }
+        let interval = self.intervals.entry(record.device.clone()).or_insert_with(|| Interval {
+            start,
+            spo2: Vec::new(),
+            heartrate: Vec::new(),
+            readings: 0,
+        });
+        interval.spo2.extend(record.spo2);
+        interval.heartrate.extend(record.heartrate);
+        interval.readings += 1;
+        Ok(())
+    }
+
+    /// Like with every reading, events go among the averages only as JSON lines.
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        if self.writer.format() == Format::Jsonl {
+            self.writer.write_json(record)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the intervals that were cut short.
+    async fn close(&mut self) -> Result<(), SinkError> {
+        for (device, interval) in &self.intervals {
+            self.writer.write(&interval.average(device))?;
+        }
+        self.intervals.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use std::time::{Duration, Instant};
+
+use super::{Sink, SinkError};
+use crate::output::{Event, EventRecord, Record, WaveformRecord};
+
+/// Gathers readings for a sink and passes them on in batches, once there are `size` of them or
+/// the first is `interval` old, so servers get one request every so often rather than one per
+/// reading. Whatever is left is passed on when a device disconnects, as no more readings are
+/// coming to fill the batch, and when closing.
+pub struct Batched {
+    sink: Box<dyn Sink>,
+    pending: Vec<Record>,
+    size: usize,
+    interval: Duration,
+    /// When the first of the pending readings arrived.
+    started: Option<Instant>,
+}
+
+impl Batched {
+    pub fn new(sink: Box<dyn Sink>, size: usize, interval: Duration) -> Self {
+        Batched {
+            sink,
+            pending: Vec::with_capacity(size),
+            size,
+            interval,
+            started: None,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.started = None;
+        let batch = std::mem::take(&mut self.pending);
+        self.sink.readings(&batch).await
+    }
+}
+
+#[async_trait]
+impl Sink for Batched {
+    fn name(&self) -> String {
+        self.sink.name()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        self.pending.push(record.clone());
+        if self.pending.len() >= self.size || started.elapsed() >= self.interval {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn waveform(&mut self, record: &WaveformRecord) -> Result<(), SinkError> {
+        self.sink.waveform(record).await
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        if record.event == Event::Disconnected && !self.pending.is_empty() {
+            if let Err(e) = self.flush().await {
+                error!("Couldn't write readings to {}: {}", self.sink.name(), e);
+            }
+        }
+        self.sink.event(record).await
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        if !self.pending.is_empty() {
+            if let Err(e) = self.flush().await {
+                error!("Couldn't write the last readings to {}: {}", self.sink.name(), e);
+            }
+        }
+        self.sink.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use pc60fw_protocol::ProbeStatus;
+    use std::sync::{Arc, Mutex};
+
+    use crate::output::{Extra, Quality};
+
+    /// Keeps the size of each batch it's given.
+    #[derive(Clone, Default)]
+    struct Batches(Arc<Mutex<Vec<usize>>>);
+
+    #[async_trait]
+    impl Sink for Batches {
+        fn name(&self) -> String {
+            "batches".to_string()
+        }
+
+        async fn reading(&mut self, _record: &Record) -> Result<(), SinkError> {
+            self.0.lock().unwrap().push(1);
+            Ok(())
+        }
+
+        async fn readings(&mut self, records: &[Record]) -> Result<(), SinkError> {
+            self.0.lock().unwrap().push(records.len());
+            Ok(())
+        }
+    }
+
+    fn record() -> Record {
+        Record {
+            time: Utc::now(),
+            spo2: Some(97),
+            heartrate: Some(60),
+            pi: None,
+            battery: None,
+            status: ProbeStatus::Stable,
+            signal: 6,
+            quality: Quality::Good,
+            device: "test".to_string(),
+            rssi: None,
+            extra: Extra::default(),
+        }
+    }
+
+    fn event(event: Event) -> EventRecord {
+        EventRecord { time: Utc::now(), device: "test".to_string(), event }
+    }
+
+    #[tokio::test]
+    async fn passes_on_a_partial_batch_when_the_device_disconnects() {
+        let batches = Batches::default();
+        let mut batched = Batched::new(Box::new(batches.clone()), 5, Duration::from_secs(3600));
+        for _ in 0..7 {
+            batched.reading(&record()).await.unwrap();
+        }
+        batched.event(&event(Event::Connected)).await.unwrap();
+        assert_eq!(*batches.0.lock().unwrap(), [5]);
+        batched.event(&event(Event::Disconnected)).await.unwrap();
+        assert_eq!(*batches.0.lock().unwrap(), [5, 2]);
+        batched.close().await.unwrap();
+        assert_eq!(*batches.0.lock().unwrap(), [5, 2]);
+    }
+}
@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use super::{Sink, SinkError};
+use crate::output::{Event, EventRecord, Record};
+
+/// Runs a program whenever an alarm goes off or clears, so anything can be wired up to it.
+///
+/// It's run as `program EVENT ALARM THRESHOLD VALUE DEVICE`, e.g.
+/// `program alarm spo2-below 90 87 AA:BB:CC:DD:EE:FF`, and gets the event and the latest reading
+/// as JSON on stdin. The program isn't waited for, so a slow one doesn't hold up the readings.
+pub struct AlarmCommandSink {
+    program: PathBuf,
+    /// The latest reading from each device.
+    latest: HashMap<String, Record>,
+}
+
+impl AlarmCommandSink {
+    pub fn new(program: PathBuf) -> Self {
+        AlarmCommandSink { program, latest: HashMap::new() }
+    }
+}
+
+#[async_trait]
+impl Sink for AlarmCommandSink {
+    fn name(&self) -> String {
+        format!("alarm command {:?}", self.program)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        self.latest.insert(record.device.clone(), record.clone());
+        Ok(())
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        let (Event::Alarm { alarm, threshold, value } | Event::AlarmCleared { alarm, threshold, value }) = record.event
+        else {
+            return Ok(());
+        };
+        let alarm = serde_json::to_value(alarm)?;
+        let mut child = Command::new(&self.program)
+            .arg(record.event.kind())
+            .arg(alarm.as_str().unwrap_or_default())
+            .arg(threshold.to_string())
+            .arg(value.to_string())
+            .arg(&record.device)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let input = serde_json::to_vec(&json!({ "event": record, "reading": self.latest.get(&record.device) }))?;
+        let mut stdin = child.stdin.take().unwrap();
+        let program = self.program.clone();
+        tokio::spawn(async move {
+            // Programs that don't read their input close it early, which is fine.
+            let _ = stdin.write_all(&input).await;
+            drop(stdin);
+            match child.wait().await {
+                Ok(status) if !status.success() => warn!("Alarm command {:?} failed: {}", program, status),
+                Ok(_) => {}
+                Err(e) => warn!("Couldn't wait for alarm command {:?}: {}", program, e),
+            }
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+use super::{Sink, SinkError};
+use crate::output::{EventRecord, Record, WaveformRecord};
+
+/// Passes only every `every`th reading from each device on to a sink, starting with the first,
+/// for recordings kept for months that don't need one reading a second. Waveform samples and
+/// events all go through.
+pub struct Decimated {
+    sink: Box<dyn Sink>,
+    every: u64,
+    /// Readings seen so far, by device.
+    seen: HashMap<String, u64>,
+}
+
+impl Decimated {
+    pub fn new(sink: Box<dyn Sink>, every: u64) -> Self {
+        Decimated {
+            sink,
+            every,
+            seen: HashMap::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for Decimated {
+    fn name(&self) -> String {
+        self.sink.name()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let seen = self.seen.entry(record.device.clone()).or_default();
+        let keep = seen.is_multiple_of(self.every);
+        *seen += 1;
+        if keep {
+            self.sink.reading(record).await?;
+        }
+        Ok(())
+    }
+
+    async fn waveform(&mut self, record: &WaveformRecord) -> Result<(), SinkError> {
+        self.sink.waveform(record).await
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        self.sink.event(record).await
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.sink.close().await
+    }
+}
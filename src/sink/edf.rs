@@ -0,0 +1,246 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Local, Utc};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::{Sink, SinkError};
+use crate::output::{Record, WaveformRecord};
+
+/// Pleth samples per one-second data record. The waveform received during each second is
+/// resampled to this rate.
+const PLETH_SAMPLES_PER_RECORD: usize = 50;
+/// Bytes reserved for the EDF Annotations signal in each data record. Only the time-keeping
+/// annotation is written, which needs far less.
+const ANNOTATION_BYTES_PER_RECORD: usize = 60;
+/// Offset of the "number of data records" header field.
+const RECORD_COUNT_OFFSET: u64 = 236;
+/// A value older than this isn't repeated into the following data records.
+const MAX_VALUE_AGE_SECONDS: i64 = 5;
+
+struct Signal {
+    label: &'static str,
+    dimension: &'static str,
+    physical_range: (i32, i32),
+    digital_range: (i32, i32),
+    samples_per_record: usize,
+}
+
+const SPO2: Signal = Signal {
+    label: "SpO2",
+    dimension: "%",
+    physical_range: (0, 100),
+    digital_range: (0, 100),
+    samples_per_record: 1,
+};
+const HEART_RATE: Signal = Signal {
+    label: "Pulse",
+    dimension: "bpm",
+    physical_range: (0, 255),
+    digital_range: (0, 255),
+    samples_per_record: 1,
+};
+const PLETH: Signal = Signal {
+    label: "Pleth",
+    dimension: "",
+    physical_range: (0, 127),
+    digital_range: (0, 127),
+    samples_per_record: PLETH_SAMPLES_PER_RECORD,
+};
+const ANNOTATIONS: Signal = Signal {
+    label: "EDF Annotations",
+    dimension: "",
+    physical_range: (-1, 1),
+    digital_range: (-32768, 32767),
+    samples_per_record: ANNOTATION_BYTES_PER_RECORD / 2,
+};
+
+/// Writes readings to an EDF+ file, for sleep analysis software like EDFbrowser.
+///
+/// EDF needs evenly sampled signals, so readings are gathered into one-second data records. The
+/// recording starts at the first reading; seconds without data are written as zeroes.
+pub struct EdfSink {
+    path: PathBuf,
+    include_pleth: bool,
+    file: Option<BufWriter<File>>,
+    start: DateTime<Utc>,
+    /// Data records written so far, which is also the index of the one being gathered.
+    records_written: i64,
+    /// Latest SpO2 and heart rate, with the second they were received in.
+    latest: Option<(i64, Option<u8>, Option<u8>)>,
+    pleth: Vec<u8>,
+}
+
+/// Left-aligns `value` in a space-padded ASCII field of `width` bytes.
+fn field(value: impl ToString, width: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = value.to_string().bytes().filter(|b| b.is_ascii()).take(width).collect();
+    bytes.resize(width, b' ');
+    bytes
+}
+
+impl EdfSink {
+    pub fn new(path: &Path, include_pleth: bool) -> Self {
+        EdfSink {
+            path: path.to_path_buf(),
+            include_pleth,
+            file: None,
+            start: Utc::now(),
+            records_written: 0,
+            latest: None,
+            pleth: Vec::new(),
+        }
+    }
+
+    fn signals(&self) -> Vec<&'static Signal> {
+        let mut signals = vec![&SPO2, &HEART_RATE];
+        if self.include_pleth {
+            signals.push(&PLETH);
+        }
+        signals.push(&ANNOTATIONS);
+        signals
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let signals = self.signals();
+        let start = self.start.with_timezone(&Local);
+        let mut header = Vec::new();
+        header.extend(field("0", 8));
+        header.extend(field("X X X X", 80));
+        header.extend(field(
+            format!("Startdate {} X X PC-60FW", start.format("%d-%b-%Y").to_string().to_uppercase()),
+            80,
+        ));
+        header.extend(field(start.format("%d.%m.%y"), 8));
+        header.extend(field(start.format("%H.%M.%S"), 8));
+        header.extend(field(256 * (signals.len() + 1), 8));
+        header.extend(field("EDF+C", 44));
+        header.extend(field(-1, 8));
+        header.extend(field(1, 8));
+        header.extend(field(signals.len(), 4));
+        for signal in &signals {
+            header.extend(field(signal.label, 16));
+        }
+        for signal in &signals {
+            let transducer = if signal.label == ANNOTATIONS.label { "" } else { "Pulse oximeter" };
+            header.extend(field(transducer, 80));
+        }
+        for signal in &signals {
+            header.extend(field(signal.dimension, 8));
+        }
+        for signal in &signals {
+            header.extend(field(signal.physical_range.0, 8));
+        }
+        for signal in &signals {
+            header.extend(field(signal.physical_range.1, 8));
+        }
+        for signal in &signals {
+            header.extend(field(signal.digital_range.0, 8));
+        }
+        for signal in &signals {
+            header.extend(field(signal.digital_range.1, 8));
+        }
+        for _ in &signals {
+            header.extend(field("", 80));
+        }
+        for signal in &signals {
+            header.extend(field(signal.samples_per_record, 8));
+        }
+        for _ in &signals {
+            header.extend(field("", 32));
+        }
+        header
+    }
+
+    fn second_of(&self, time: DateTime<Utc>) -> i64 {
+        (time - self.start).num_milliseconds().div_euclid(1000)
+    }
+
+    /// Writes data records up to, but not including, `second`.
+    fn write_records_until(&mut self, second: i64) -> io::Result<()> {
+        while self.records_written < second {
+            let index = self.records_written;
+            let (spo2, heart_rate) = match self.latest {
+                Some((received, spo2, heart_rate)) if index - received < MAX_VALUE_AGE_SECONDS => {
+                    (spo2.unwrap_or(0), heart_rate.unwrap_or(0))
+                }
+                _ => (0, 0),
+            };
+
+            let mut record = Vec::new();
+            record.extend_from_slice(&i16::from(spo2).to_le_bytes());
+            record.extend_from_slice(&i16::from(heart_rate).to_le_bytes());
+            if self.include_pleth {
+                for i in 0..PLETH_SAMPLES_PER_RECORD {
+                    let sample = if self.pleth.is_empty() {
+                        0
+                    } else {
+                        self.pleth[i * self.pleth.len() / PLETH_SAMPLES_PER_RECORD]
+                    };
+                    record.extend_from_slice(&i16::from(sample).to_le_bytes());
+                }
+                self.pleth.clear();
+            }
+            // Time-keeping annotation: "+<onset>" followed by two 0x14 and a 0.
+            let mut annotation = format!("+{}\x14\x14\0", index).into_bytes();
+            annotation.resize(ANNOTATION_BYTES_PER_RECORD, 0);
+            record.extend(annotation);
+
+            let file = self.file.as_mut().unwrap();
+            file.write_all(&record)?;
+            self.records_written += 1;
+
+            // Keep the record count in the header current, so the file is valid even if the
+            // program is killed.
+            file.seek(SeekFrom::Start(RECORD_COUNT_OFFSET))?;
+            file.write_all(&field(self.records_written, 8))?;
+            file.seek(SeekFrom::End(0))?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Starts the recording at `time` if that hasn't happened yet, and returns which second
+    /// `time` falls into.
+    fn advance_to(&mut self, time: DateTime<Utc>) -> io::Result<i64> {
+        if self.file.is_none() {
+            self.start = time;
+            let mut file = BufWriter::new(File::create(&self.path)?);
+            file.write_all(&self.header())?;
+            self.file = Some(file);
+        }
+        let second = self.second_of(time);
+        self.write_records_until(second)?;
+        Ok(second)
+    }
+}
+
+#[async_trait]
+impl Sink for EdfSink {
+    fn name(&self) -> String {
+        format!("EDF file {:?}", self.path)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let second = self.advance_to(record.time)?;
+        self.latest = Some((second, record.spo2, record.heartrate));
+        Ok(())
+    }
+
+    async fn waveform(&mut self, record: &WaveformRecord) -> Result<(), SinkError> {
+        if !self.include_pleth {
+            return Ok(());
+        }
+        self.advance_to(record.time)?;
+        self.pleth.push(record.pleth);
+        Ok(())
+    }
+
+    /// Writes the second being gathered, which would otherwise wait for the next one to start.
+    async fn close(&mut self) -> Result<(), SinkError> {
+        let gathering = self.latest.is_some_and(|(second, _, _)| second == self.records_written);
+        if self.file.is_some() && (gathering || !self.pleth.is_empty()) {
+            self.write_records_until(self.records_written + 1)?;
+        }
+        Ok(())
+    }
+}
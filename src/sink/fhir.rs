@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use chrono::Local;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::io::Write;
+use std::slice;
+
+use super::{http_client, Sink, SinkError};
+use crate::output::Record;
+use crate::rotating_file::RotatingFile;
+
+const LOINC: &str = "http://loinc.org";
+const UCUM: &str = "http://unitsofmeasure.org";
+
+/// Where Observations get sent.
+enum Target {
+    /// NDJSON files, one Observation per line, as used by FHIR bulk data.
+    File(RotatingFile),
+    /// A FHIR server, which gets a transaction Bundle per reading, or per batch of them.
+    Server { client: reqwest::Client, base_url: String },
+}
+
+/// Writes readings as FHIR R4 vital-signs Observations.
+pub struct FhirSink {
+    target: Target,
+    subject: Option<String>,
+    description: String,
+}
+
+/// A vital-signs Observation of `value` in `unit`, coded with each of the LOINC `codes`.
+fn observation(record: &Record, subject: Option<&str>, codes: &[(&str, &str)], value: u8, unit: &str) -> Value {
+    let coding: Vec<Value> = codes
+        .iter()
+        .map(|(code, display)| json!({ "system": LOINC, "code": code, "display": display }))
+        .collect();
+    let mut observation = json!({
+        "resourceType": "Observation",
+        "status": "final",
+        "category": [{
+            "coding": [{
+                "system": "http://terminology.hl7.org/CodeSystem/observation-category",
+                "code": "vital-signs",
+                "display": "Vital Signs",
+            }],
+        }],
+        "code": { "coding": coding },
+        "effectiveDateTime": record.time.to_rfc3339(),
+        "valueQuantity": { "value": value, "unit": unit, "system": UCUM, "code": unit },
+        "device": { "display": "PC-60FW pulse oximeter" },
+    });
+    if let Some(subject) = subject {
+        observation["subject"] = json!({ "reference": subject });
+    }
+    observation
+}
+
+/// The Observations for a reading, which is none while there's no finger or pulse.
+fn observations(record: &Record, subject: Option<&str>) -> Vec<Value> {
+    let mut observations = Vec::new();
+    if let Some(spo2) = record.spo2 {
+        // The vital signs profile wants 2708-6, the pulse oximetry profile adds 59408-5.
+        let codes = [
+            ("2708-6", "Oxygen saturation in Arterial blood"),
+            ("59408-5", "Oxygen saturation in Arterial blood by Pulse oximetry"),
+        ];
+        observations.push(observation(record, subject, &codes, spo2, "%"));
+    }
+    if let Some(heartrate) = record.heartrate {
+        observations.push(observation(record, subject, &[("8867-4", "Heart rate")], heartrate, "/min"));
+    }
+    observations
+}
+
+impl FhirSink {
+    /// `target` is the base URL of a FHIR server, or a file name that may contain strftime
+    /// patterns. `subject` is a reference like "Patient/123" to attach to every Observation.
+    pub fn new(target: &str, subject: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let sink_target = if target.starts_with("http://") || target.starts_with("https://") {
+            Target::Server {
+                client: http_client()?,
+                base_url: target.trim_end_matches('/').to_string(),
+            }
+        } else {
+            Target::File(RotatingFile::new(target)?)
+        };
+        Ok(FhirSink {
+            target: sink_target,
+            subject: subject.map(str::to_string),
+            description: target.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for FhirSink {
+    fn name(&self) -> String {
+        format!("FHIR {}", self.description)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        self.readings(slice::from_ref(record)).await
+    }
+
+    /// Several readings go to a server in one Bundle.
+    async fn readings(&mut self, records: &[Record]) -> Result<(), SinkError> {
+        let subject = self.subject.as_deref();
+        let observations: Vec<(&Record, Value)> = records
+            .iter()
+            .flat_map(|record| observations(record, subject).into_iter().map(move |observation| (record, observation)))
+            .collect();
+        if observations.is_empty() {
+            return Ok(());
+        }
+        match &mut self.target {
+            Target::File(files) => {
+                for (record, observation) in &observations {
+                    let (file, _) = files.file_for(record.time.with_timezone(&Local))?;
+                    serde_json::to_writer(&mut *file, observation)?;
+                    writeln!(file)?;
+                }
+                files.flush()?;
+            }
+            Target::Server { client, base_url } => {
+                let entries: Vec<Value> = observations
+                    .into_iter()
+                    .map(|(_, resource)| json!({ "resource": resource, "request": { "method": "POST", "url": "Observation" } }))
+                    .collect();
+                let bundle = json!({ "resourceType": "Bundle", "type": "transaction", "entry": entries });
+                client
+                    .post(base_url.as_str())
+                    .header("Content-Type", "application/fhir+json")
+                    .body(bundle.to_string())
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::{http_client, Sink, SinkError};
+use crate::output::Record;
+
+const DEVICE_CODE_URL: &str = "https://oauth2.googleapis.com/device/code";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const API_URL: &str = "https://www.googleapis.com/fitness/v1/users/me";
+const SCOPES: &str = "https://www.googleapis.com/auth/fitness.oxygen_saturation.write \
+                      https://www.googleapis.com/auth/fitness.heart_rate.write";
+const SPO2_TYPE: &str = "com.google.oxygen_saturation";
+const HEART_RATE_TYPE: &str = "com.google.heart_rate.bpm";
+
+/// Readings kept while Google Fit is unreachable. Past this, the oldest ones are dropped.
+const MAX_PENDING: usize = 24 * 60 * 60;
+
+/// The user's own OAuth client, and where to keep the refresh token between runs.
+pub struct OAuthOptions {
+    pub client_id: String,
+    pub client_secret: String,
+    pub token_file: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_url: String,
+    interval: u64,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+    error: String,
+}
+
+/// Uploads SpO2 and heart rate to Google Fit every `upload_interval`.
+pub struct GoogleFitSink {
+    client: reqwest::Client,
+    oauth: OAuthOptions,
+    refresh_token: String,
+    access_token: Option<(String, Instant)>,
+    spo2_source: String,
+    heart_rate_source: String,
+    pending: Vec<Record>,
+    upload_interval: Duration,
+    last_upload: Instant,
+}
+
+/// Asks the user to approve access on another device, and waits until they have.
+async fn authorize(client: &reqwest::Client, oauth: &OAuthOptions) -> Result<Token, Box<dyn Error>> {
+    let code: DeviceCode = client
+        .post(DEVICE_CODE_URL)
+        .form(&[("client_id", oauth.client_id.as_str()), ("scope", SCOPES)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    eprintln!(
+        "To let this program upload to Google Fit, visit {} and enter the code {}",
+        code.verification_url, code.user_code
+    );
+
+    let mut interval = Duration::from_secs(code.interval);
+    loop {
+        tokio::time::sleep(interval).await;
+        let response = client
+            .post(TOKEN_URL)
+            .form(&[
+                ("client_id", oauth.client_id.as_str()),
+                ("client_secret", oauth.client_secret.as_str()),
+                ("device_code", code.device_code.as_str()),
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ])
+            .send()
+            .await?;
+        if response.status().is_success() {
+            return Ok(response.json().await?);
+        }
+        match response.json::<TokenError>().await?.error.as_str() {
+            "authorization_pending" => {}
+            "slow_down" => interval += Duration::from_secs(5),
+            error => return Err(format!("Google Fit authorization failed: {}", error).into()),
+        }
+    }
+}
+
+/// Saves the refresh token where only the user can read it, as it gives access to their Google
+/// Fit data until it's revoked.
+fn save_token(path: &Path, refresh_token: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(refresh_token.as_bytes())
+}
+
+impl GoogleFitSink {
+    /// Uses the refresh token saved in `oauth.token_file`, or goes through the device flow to get
+    /// one if there isn't any.
+    pub async fn new(oauth: OAuthOptions, upload_interval: Duration) -> Result<Self, Box<dyn Error>> {
+        let client = http_client()?;
+        let (refresh_token, access_token) = match fs::read_to_string(&oauth.token_file) {
+            Ok(refresh_token) => (refresh_token.trim().to_string(), None),
+            Err(_) => {
+                let token = authorize(&client, &oauth).await?;
+                let refresh_token = token.refresh_token.ok_or("Google didn't return a refresh token")?;
+                if let Some(parent) = oauth.token_file.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                save_token(&oauth.token_file, &refresh_token)
+                    .map_err(|e| format!("Couldn't save Google Fit token to {:?}: {}", oauth.token_file, e))?;
+                let expiry = Instant::now() + Duration::from_secs(token.expires_in);
+                (refresh_token, Some((token.access_token, expiry)))
+            }
+        };
+
+        let mut sink = GoogleFitSink {
+            client,
+            oauth,
+            refresh_token,
+            access_token,
+            spo2_source: String::new(),
+            heart_rate_source: String::new(),
+            pending: Vec::new(),
+            upload_interval,
+            last_upload: Instant::now(),
+        };
+        sink.spo2_source = sink.data_source(SPO2_TYPE).await.map_err(|e| e as Box<dyn Error>)?;
+        sink.heart_rate_source = sink.data_source(HEART_RATE_TYPE).await.map_err(|e| e as Box<dyn Error>)?;
+        Ok(sink)
+    }
+
+    async fn access_token(&mut self) -> Result<String, SinkError> {
+        if let Some((token, expiry)) = &self.access_token {
+            // Leave some slack so the token doesn't expire mid-request.
+            if *expiry > Instant::now() + Duration::from_secs(60) {
+                return Ok(token.clone());
+            }
+        }
+        let token: Token = self
+            .client
+            .post(TOKEN_URL)
+            .form(&[
+                ("client_id", self.oauth.client_id.as_str()),
+                ("client_secret", self.oauth.client_secret.as_str()),
+                ("refresh_token", self.refresh_token.as_str()),
+                ("grant_type", "refresh_token"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let expiry = Instant::now() + Duration::from_secs(token.expires_in);
+        self.access_token = Some((token.access_token.clone(), expiry));
+        Ok(token.access_token)
+    }
+
+    /// Finds or creates this program's data source for `data_type`, and returns its ID.
+    async fn data_source(&mut self, data_type: &str) -> Result<String, SinkError> {
+        let token = self.access_token().await?;
+        let response = self
+            .client
+            .get(format!("{}/dataSources", API_URL))
+            .query(&[("dataTypeName", data_type)])
+            .bearer_auth(&token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?;
+        let existing = response["dataSource"].as_array().and_then(|sources| {
+            sources
+                .iter()
+                .find(|source| source["dataStreamName"] == "pc60fw")
+                .and_then(|source| source["dataStreamId"].as_str())
+        });
+        if let Some(id) = existing {
+            return Ok(id.to_string());
+        }
+
+        let source = json!({
+            "dataStreamName": "pc60fw",
+            "type": "raw",
+            "application": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+            "dataType": { "name": data_type },
+            "device": {
+                "manufacturer": "Creative Medical",
+                "model": "PC-60FW",
+                "type": "unknown",
+                "uid": "1",
+                "version": "1",
+            },
+        });
+        let created = self
+            .client
+            .post(format!("{}/dataSources", API_URL))
+            .bearer_auth(&token)
+            .json(&source)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?;
+        let id = created["dataStreamId"].as_str().ok_or("Google Fit didn't return a data source ID")?;
+        Ok(id.to_string())
+    }
+
+    async fn patch_dataset(&mut self, source: &str, points: Vec<serde_json::Value>, start: i64, end: i64) -> Result<(), SinkError> {
+        if points.is_empty() {
+            return Ok(());
+        }
+        let token = self.access_token().await?;
+        self.client
+            .patch(format!("{}/dataSources/{}/datasets/{}-{}", API_URL, source, start, end))
+            .bearer_auth(&token)
+            .json(&json!({
+                "dataSourceId": source,
+                "minStartTimeNs": start,
+                "maxEndTimeNs": end,
+                "point": points,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn upload(&mut self) -> Result<(), SinkError> {
+        self.last_upload = Instant::now();
+        let nanos = |record: &Record| record.time.timestamp_nanos_opt().unwrap_or_default();
+        let (Some(first), Some(last)) = (self.pending.first(), self.pending.last()) else {
+            return Ok(());
+        };
+        let (start, end) = (nanos(first), nanos(last));
+
+        let point = |record: &Record, data_type: &str, value: serde_json::Value| {
+            json!({
+                "dataTypeName": data_type,
+                "startTimeNanos": nanos(record),
+                "endTimeNanos": nanos(record),
+                "value": value,
+            })
+        };
+        // Oxygen saturation also has fields for supplemental oxygen and the measurement method,
+        // which are left unset.
+        let spo2 = self
+            .pending
+            .iter()
+            .filter_map(|r| Some(point(r, SPO2_TYPE, json!([{ "fpVal": r.spo2? }, { "fpVal": 0.0 }, {}, {}, {}]))))
+            .collect();
+        let heart_rate = self
+            .pending
+            .iter()
+            .filter_map(|r| Some(point(r, HEART_RATE_TYPE, json!([{ "fpVal": r.heartrate? }]))))
+            .collect();
+
+        let (spo2_source, heart_rate_source) = (self.spo2_source.clone(), self.heart_rate_source.clone());
+        self.patch_dataset(&spo2_source, spo2, start, end).await?;
+        self.patch_dataset(&heart_rate_source, heart_rate, start, end).await?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for GoogleFitSink {
+    fn name(&self) -> String {
+        "Google Fit".to_string()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.remove(0);
+        }
+        self.pending.push(record.clone());
+        if self.last_upload.elapsed() >= self.upload_interval {
+            self.upload().await?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.upload().await
+    }
+}
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use std::error::Error;
+use std::slice;
+
+use super::influxdb::to_line_protocol;
+use super::{http_client, Sink, SinkError};
+use crate::output::Record;
+
+/// Measurement readings are pushed as, which is the last part of the channel panels subscribe to.
+const MEASUREMENT: &str = "readings";
+
+/// Pushes readings to Grafana Live, for streaming panels that update as readings come in.
+pub struct GrafanaLiveSink {
+    client: reqwest::Client,
+    push_url: reqwest::Url,
+    token: String,
+}
+
+impl GrafanaLiveSink {
+    /// `url` is the base URL of Grafana. Readings show up on the `stream/<stream>/readings`
+    /// channel.
+    pub fn new(url: &str, stream: &str, token: String) -> Result<Self, Box<dyn Error>> {
+        let mut push_url = reqwest::Url::parse(url)?;
+        push_url
+            .path_segments_mut()
+            .map_err(|_| format!("Grafana URL {:?} can't have a path", url))?
+            .pop_if_empty()
+            .extend(["api", "live", "push", stream]);
+        Ok(GrafanaLiveSink {
+            client: http_client()?,
+            push_url,
+            token,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for GrafanaLiveSink {
+    fn name(&self) -> String {
+        format!("Grafana Live at {}", self.push_url)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        self.readings(slice::from_ref(record)).await
+    }
+
+    async fn readings(&mut self, records: &[Record]) -> Result<(), SinkError> {
+        let lines: Vec<String> = records.iter().map(|record| to_line_protocol(MEASUREMENT, record)).collect();
+        self.client
+            .post(self.push_url.clone())
+            .bearer_auth(&self.token)
+            .body(lines.join("\n"))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use std::slice;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use super::{connect, metric_segment, Sink, SinkError};
+use crate::output::Record;
+
+/// Sends readings to Graphite/Carbon using the plaintext protocol, reconnecting whenever the
+/// connection is lost.
+pub struct GraphiteSink {
+    address: String,
+    prefix: String,
+    stream: Option<TcpStream>,
+}
+
+impl GraphiteSink {
+    /// Doesn't connect until the first reading is sent.
+    pub fn new(address: &str, prefix: &str) -> Self {
+        GraphiteSink {
+            address: address.to_string(),
+            prefix: prefix.to_string(),
+            stream: None,
+        }
+    }
+
+    /// One `<prefix>.<device>.<name> <value> <timestamp>` line per value the reading has.
+    fn lines(&self, record: &Record) -> String {
+        let values = [
+            ("spo2", record.spo2.map(|v| v.to_string())),
+            ("heartrate", record.heartrate.map(|v| v.to_string())),
+            ("pi", record.pi.map(|v| format!("{:.1}", v))),
+            ("battery", record.battery.map(|v| v.to_string())),
+            ("signal", Some(record.signal.to_string())),
+        ];
+        let device = metric_segment(&record.device);
+        values
+            .into_iter()
+            .filter_map(|(name, value)| {
+                Some(format!("{}.{}.{} {} {}\n", self.prefix, device, name, value?, record.time.timestamp()))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Sink for GraphiteSink {
+    fn name(&self) -> String {
+        format!("Graphite {}", self.address)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        self.readings(slice::from_ref(record)).await
+    }
+
+    async fn readings(&mut self, records: &[Record]) -> Result<(), SinkError> {
+        let lines: String = records.iter().map(|record| self.lines(record)).collect();
+        if self.stream.is_none() {
+            self.stream = Some(connect(&self.address).await?);
+            info!("Connected to Graphite {}", self.address);
+        }
+        if let Err(e) = self.stream.as_mut().unwrap().write_all(lines.as_bytes()).await {
+            // Reconnect on the next attempt.
+            self.stream = None;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
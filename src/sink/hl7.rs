@@ -0,0 +1,179 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Local, Utc};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::{connect, Sink, SinkError};
+use crate::output::Record;
+
+/// MLLP wraps each message in these.
+const START_BLOCK: u8 = 0x0b;
+const END_BLOCK: &[u8] = &[0x1c, 0x0d];
+/// How long to wait for the receiver to acknowledge a message.
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends readings as HL7 v2.5.1 ORU^R01 messages over MLLP, either one per reading or one per
+/// interval with the averages.
+pub struct Hl7Sink {
+    address: String,
+    patient_id: Option<String>,
+    stream: Option<TcpStream>,
+    interval: Option<Duration>,
+    interval_start: Option<(DateTime<Utc>, Instant)>,
+    /// When the last reading in the interval was taken.
+    interval_end: Option<DateTime<Utc>>,
+    spo2: Vec<u8>,
+    heartrate: Vec<u8>,
+    control_id: u64,
+}
+
+fn hl7_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%Y%m%d%H%M%S%z").to_string()
+}
+
+fn mean(values: &[u8]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().map(|&v| f32::from(v)).sum::<f32>() / values.len() as f32)
+}
+
+impl Hl7Sink {
+    /// Doesn't connect until the first message is sent.
+    pub fn new(address: &str, patient_id: Option<&str>, interval: Option<Duration>) -> Self {
+        Hl7Sink {
+            address: address.to_string(),
+            patient_id: patient_id.map(str::to_string),
+            stream: None,
+            interval,
+            interval_start: None,
+            interval_end: None,
+            spo2: Vec::new(),
+            heartrate: Vec::new(),
+            control_id: 0,
+        }
+    }
+
+    /// An ORU^R01 message with SpO2 and heart rate observed between `start` and `end`.
+    fn message(&mut self, start: DateTime<Utc>, end: DateTime<Utc>, spo2: Option<f32>, heartrate: Option<f32>) -> String {
+        self.control_id += 1;
+        let mut segments = vec![
+            format!(
+                "MSH|^~\\&|PC60FW|{}|||{}||ORU^R01^ORU_R01|{}|P|2.5.1",
+                env!("CARGO_PKG_NAME"),
+                hl7_time(Utc::now()),
+                self.control_id
+            ),
+            format!("PID|1||{}", self.patient_id.as_deref().unwrap_or("")),
+            format!("OBR|1|||PULSEOX^Pulse oximetry^L|||{}|{}", hl7_time(start), hl7_time(end)),
+        ];
+        let observations = [
+            (spo2, "59408-5^Oxygen saturation in Arterial blood by Pulse oximetry^LN", "%^percent^UCUM"),
+            (heartrate, "8867-4^Heart rate^LN", "/min^per minute^UCUM"),
+        ];
+        for (value, code, unit) in observations {
+            if let Some(value) = value {
+                segments.push(format!(
+                    "OBX|{}|NM|{}||{:.1}|{}|||||F|||{}",
+                    segments.len() - 2,
+                    code,
+                    value,
+                    unit,
+                    hl7_time(end)
+                ));
+            }
+        }
+        segments.join("\r") + "\r"
+    }
+
+    /// Sends the means of the readings in the current interval, if there were any, and starts a
+    /// new one.
+    async fn send_interval(&mut self) -> Result<(), SinkError> {
+        let (Some((start, _)), Some(end)) = (self.interval_start.take(), self.interval_end.take()) else {
+            return Ok(());
+        };
+        let (spo2, heartrate) = (mean(&self.spo2), mean(&self.heartrate));
+        self.spo2.clear();
+        self.heartrate.clear();
+        if spo2.is_none() && heartrate.is_none() {
+            return Ok(());
+        }
+        let message = self.message(start, end, spo2, heartrate);
+        self.send(message).await
+    }
+
+    async fn send(&mut self, message: String) -> Result<(), SinkError> {
+        if self.stream.is_none() {
+            self.stream = Some(connect(&self.address).await?);
+            info!("Connected to HL7 receiver {}", self.address);
+        }
+        let result = Self::exchange(self.stream.as_mut().unwrap(), &message).await;
+        if result.is_err() {
+            // Reconnect on the next attempt, the connection may be broken.
+            self.stream = None;
+        }
+        result
+    }
+
+    /// Sends a framed message and checks the acknowledgement.
+    async fn exchange(stream: &mut TcpStream, message: &str) -> Result<(), SinkError> {
+        let mut frame = vec![START_BLOCK];
+        frame.extend_from_slice(message.as_bytes());
+        frame.extend_from_slice(END_BLOCK);
+        stream.write_all(&frame).await?;
+
+        let mut ack = Vec::new();
+        tokio::time::timeout(ACK_TIMEOUT, async {
+            while !ack.ends_with(END_BLOCK) {
+                if stream.read_buf(&mut ack).await? == 0 {
+                    return Err(SinkError::from("HL7 receiver closed the connection"));
+                }
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|_| "Timed out waiting for HL7 acknowledgement")??;
+
+        let ack = String::from_utf8_lossy(&ack);
+        let code = ack
+            .split('\r')
+            .find(|segment| segment.starts_with("MSA|"))
+            .and_then(|segment| segment.split('|').nth(1));
+        match code {
+            Some("AA") | Some("CA") => Ok(()),
+            _ => Err(format!("HL7 receiver rejected message: {:?}", ack.trim_matches(|c: char| c.is_control())).into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for Hl7Sink {
+    fn name(&self) -> String {
+        format!("HL7 receiver {}", self.address)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let Some(interval) = self.interval else {
+            if record.spo2.is_none() && record.heartrate.is_none() {
+                return Ok(());
+            }
+            let message = self.message(record.time, record.time, record.spo2.map(f32::from), record.heartrate.map(f32::from));
+            return self.send(message).await;
+        };
+
+        let (_, started) = *self.interval_start.get_or_insert((record.time, Instant::now()));
+        self.interval_end = Some(record.time);
+        self.spo2.extend(record.spo2);
+        self.heartrate.extend(record.heartrate);
+        if started.elapsed() < interval {
+            return Ok(());
+        }
+        self.send_interval().await
+    }
+
+    /// Sends the means of the interval that was cut short.
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.send_interval().await
+    }
+}
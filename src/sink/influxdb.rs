@@ -0,0 +1,145 @@
+use async_trait::async_trait;
+use std::error::Error;
+use std::io::{self, Write};
+use tokio::net::UdpSocket;
+
+use super::{http_client, Sink, SinkError};
+use crate::output::Record;
+
+/// Where line protocol gets sent.
+enum Transport {
+    Stdout,
+    Udp(UdpSocket),
+    /// The InfluxDB v2 `/api/v2/write` endpoint.
+    Http {
+        client: reqwest::Client,
+        write_url: String,
+        token: Option<String>,
+    },
+}
+
+/// Writes readings as InfluxDB line protocol.
+pub struct InfluxDbSink {
+    transport: Transport,
+    measurement: String,
+    description: String,
+}
+
+/// Options for the v2 HTTP write API.
+pub struct HttpOptions {
+    pub token: Option<String>,
+    pub org: String,
+    pub bucket: String,
+}
+
+impl InfluxDbSink {
+    /// `url` is `-` for stdout, `udp://host:port`, or the base URL of an InfluxDB v2 server.
+    pub async fn new(url: &str, measurement: &str, http: HttpOptions) -> Result<Self, Box<dyn Error>> {
+        let transport = if url == "-" {
+            Transport::Stdout
+        } else if let Some(address) = url.strip_prefix("udp://") {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            socket.connect(address).await?;
+            Transport::Udp(socket)
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            let mut write_url = reqwest::Url::parse(url)?.join("api/v2/write")?;
+            write_url
+                .query_pairs_mut()
+                .append_pair("org", &http.org)
+                .append_pair("bucket", &http.bucket)
+                .append_pair("precision", "ns");
+            Transport::Http {
+                client: http_client()?,
+                write_url: write_url.to_string(),
+                token: http.token,
+            }
+        } else {
+            return Err(format!("Unsupported InfluxDB URL {:?}, expected -, udp:// or http(s)://", url).into());
+        };
+        Ok(InfluxDbSink {
+            transport,
+            measurement: measurement.to_string(),
+            description: if url == "-" { "stdout".to_string() } else { url.to_string() },
+        })
+    }
+
+    async fn send(&mut self, line: String) -> Result<(), SinkError> {
+        match &mut self.transport {
+            Transport::Stdout => {
+                let mut stdout = io::stdout();
+                writeln!(stdout, "{}", line)?;
+                stdout.flush()?;
+            }
+            Transport::Udp(socket) => {
+                socket.send(format!("{}\n", line).as_bytes()).await?;
+            }
+            Transport::Http { client, write_url, token } => {
+                let mut request = client.post(write_url.as_str()).body(line);
+                if let Some(token) = token {
+                    request = request.header("Authorization", format!("Token {}", token));
+                }
+                request.send().await?.error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Escapes commas and spaces in a measurement name.
+fn escape_measurement(name: &str) -> String {
+    name.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+/// Escapes commas, equals signs and spaces in a tag value.
+fn escape_tag(value: &str) -> String {
+    value.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// Formats a reading as a line of line protocol. Missing values are left out.
+pub fn to_line_protocol(measurement: &str, record: &Record) -> String {
+    let mut fields = Vec::new();
+    if let Some(spo2) = record.spo2 {
+        fields.push(format!("spo2={}i", spo2));
+    }
+    if let Some(heartrate) = record.heartrate {
+        fields.push(format!("heartrate={}i", heartrate));
+    }
+    if let Some(pi) = record.pi {
+        fields.push(format!("pi={}", pi));
+    }
+    if let Some(battery) = record.battery {
+        fields.push(format!("battery={}i", battery));
+    }
+    fields.push(format!("signal={}i", record.signal));
+    if let Some(rssi) = record.rssi {
+        fields.push(format!("rssi={}i", rssi));
+    }
+    fields.push(format!("status=\"{}\"", record.status));
+    fields.push(format!("quality=\"{}\"", record.quality));
+
+    let timestamp = record.time.timestamp_nanos_opt().unwrap_or_default();
+    format!(
+        "{},device={} {} {}",
+        escape_measurement(measurement),
+        escape_tag(&record.device),
+        fields.join(","),
+        timestamp
+    )
+}
+
+#[async_trait]
+impl Sink for InfluxDbSink {
+    fn name(&self) -> String {
+        format!("InfluxDB at {}", self.description)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let line = to_line_protocol(&self.measurement, record);
+        self.send(line).await
+    }
+
+    async fn readings(&mut self, records: &[Record]) -> Result<(), SinkError> {
+        let lines: Vec<String> = records.iter().map(|record| to_line_protocol(&self.measurement, record)).collect();
+        self.send(lines.join("\n")).await
+    }
+}
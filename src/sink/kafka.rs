@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{Sink, SinkError};
+use crate::output::Record;
+
+/// How long to wait for queued messages to be delivered when closing.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the brokers to answer when checking whether they're back.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Publishes readings as JSON to a Kafka topic, keyed by the device address.
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+    /// Whether the last reading that was done with couldn't be delivered, set from the tasks
+    /// that wait for deliveries.
+    failing: Arc<AtomicBool>,
+}
+
+impl KafkaSink {
+    /// `brokers` is a comma-separated list of `host:port` bootstrap servers.
+    pub fn new(brokers: &str, topic: &str) -> Result<Self, Box<dyn Error>> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("client.id", env!("CARGO_PKG_NAME"))
+            .create()?;
+        Ok(KafkaSink {
+            producer,
+            topic: topic.to_string(),
+            failing: Arc::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for KafkaSink {
+    fn name(&self) -> String {
+        format!("Kafka topic {:?}", self.topic)
+    }
+
+    /// Fails while readings can't be delivered, rather than queueing more that would be lost if
+    /// the program exits before the brokers are back, so `--spool-dir` can keep them instead.
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        if self.failing.load(Ordering::Relaxed) {
+            let producer = self.producer.clone();
+            let topic = self.topic.clone();
+            tokio::task::spawn_blocking(move || producer.client().fetch_metadata(Some(&topic), CHECK_TIMEOUT))
+                .await?
+                .map_err(|e| format!("Kafka brokers still can't be reached: {}", e))?;
+            self.failing.store(false, Ordering::Relaxed);
+        }
+        let payload = serde_json::to_string(record)?;
+        let message = FutureRecord::to(&self.topic)
+            .key(&record.device)
+            .payload(&payload)
+            .timestamp(record.time.timestamp_millis());
+        // Only queue the message, librdkafka retries delivery in the background. Waiting for the
+        // broker would hold up the other sinks whenever it's slow.
+        let delivery = self.producer.send_result(message).map_err(|(e, _)| e)?;
+        let failing = self.failing.clone();
+        tokio::spawn(async move {
+            match delivery.await {
+                Ok(Err((e, _))) => warn!("Couldn't deliver reading to Kafka: {}", e),
+                Err(_) => warn!("Kafka delivery was cancelled"),
+                Ok(Ok(_)) => return,
+            }
+            failing.store(true, Ordering::Relaxed);
+        });
+        Ok(())
+    }
+
+    /// Waits for librdkafka to deliver what it still has queued.
+    async fn close(&mut self) -> Result<(), SinkError> {
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || producer.flush(CLOSE_TIMEOUT)).await??;
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use super::{Sink, SinkError};
+use crate::live::LiveFeed;
+use crate::output::{Record, WaveformRecord};
+
+/// Hands readings to the HTTP server's live streams.
+pub struct LiveSink(Arc<LiveFeed>);
+
+impl LiveSink {
+    pub fn new(feed: Arc<LiveFeed>) -> Self {
+        LiveSink(feed)
+    }
+}
+
+#[async_trait]
+impl Sink for LiveSink {
+    fn name(&self) -> String {
+        "live stream".to_string()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        self.0.publish(record);
+        Ok(())
+    }
+
+    async fn waveform(&mut self, record: &WaveformRecord) -> Result<(), SinkError> {
+        self.0.publish_waveform(record);
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+//! Destinations for readings.
+//!
+//! To add a new destination, implement [`Sink`] for it and construct it in [`from_args`]. The BLE
+//! loop only ever talks to [`Sinks`], so it doesn't need to change.
+
+use async_trait::async_trait;
+use std::error::Error;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::cli::Args;
+use crate::config;
+use crate::output::{Destination, EventRecord, Record, WaveformRecord};
+
+mod alert;
+mod average;
+mod batch;
+mod command;
+mod decimate;
+mod edf;
+mod fhir;
+mod google_fit;
+mod grafana_live;
+mod graphite;
+mod hl7;
+mod influxdb;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod live;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+#[cfg(feature = "nats")]
+mod nats;
+#[cfg(feature = "notifications")]
+mod notifications;
+mod one_shot;
+mod osc;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "redis")]
+mod redis;
+mod rows;
+mod sound;
+mod spool;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod statsd;
+mod udp;
+
+pub use alert::{AlertSink, AlertTarget};
+pub use average::AverageRowSink;
+pub use batch::Batched;
+pub use command::AlarmCommandSink;
+pub use decimate::Decimated;
+pub use edf::EdfSink;
+pub use fhir::FhirSink;
+pub use google_fit::GoogleFitSink;
+pub use grafana_live::GrafanaLiveSink;
+pub use graphite::GraphiteSink;
+pub use hl7::Hl7Sink;
+pub use influxdb::InfluxDbSink;
+#[cfg(feature = "kafka")]
+pub use kafka::KafkaSink;
+pub use live::LiveSink;
+
+#[cfg(feature = "mqtt")]
+pub use mqtt::MqttSink;
+#[cfg(feature = "nats")]
+pub use nats::NatsSink;
+#[cfg(feature = "notifications")]
+pub use notifications::NotificationSink;
+pub use one_shot::OneShotSink;
+pub use osc::OscSink;
+#[cfg(feature = "parquet")]
+pub use self::parquet::ParquetSink;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresSink;
+#[cfg(feature = "redis")]
+pub use self::redis::RedisSink;
+pub use rows::{EventRowSink, RowSink, WaveformRowSink};
+pub use sound::{AlarmSound, SoundSink};
+pub use spool::Spooled;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteSink;
+pub use statsd::StatsdSink;
+pub use udp::UdpSink;
+
+pub type SinkError = Box<dyn Error + Send + Sync>;
+
+/// How long network sinks wait for a server to accept a connection. Every other sink waits along
+/// with them, so an unreachable server can't be given long.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long network sinks wait for a server to answer a request, once connected.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An HTTP client that gives up on servers that can't be reached or are too slow to answer.
+fn http_client() -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+}
+
+/// `device` as part of a Graphite or StatsD metric path, where dots and colons would mean
+/// something else.
+fn metric_segment(device: &str) -> String {
+    device.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+/// Connects to a server that takes readings over plain TCP, giving up after [`CONNECT_TIMEOUT`].
+async fn connect(address: &str) -> Result<TcpStream, SinkError> {
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+        .await
+        .map_err(|_| format!("Timed out connecting to {}", address))??;
+    Ok(stream)
+}
+
+#[async_trait]
+pub trait Sink: Send {
+    /// Short description used in log messages.
+    fn name(&self) -> String;
+
+    /// Called for every reading, about once a second.
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError>;
+
+    /// Called with several readings at once when batching. Sinks that can send them in one go
+    /// should.
+    async fn readings(&mut self, records: &[Record]) -> Result<(), SinkError> {
+        for record in records {
+            self.reading(record).await?;
+        }
+        Ok(())
+    }
+
+    /// Called for every plethysmograph sample. Most sinks aren't interested in the waveform.
+    async fn waveform(&mut self, _record: &WaveformRecord) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Called for every event, like a desaturation. Most sinks only take readings.
+    async fn event(&mut self, _record: &EventRecord) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Called once when the program is shutting down, to write out anything still buffered.
+    async fn close(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// All the configured sinks.
+///
+/// A failing sink is logged and skipped, so one unreachable server doesn't stop the others from
+/// getting data.
+#[derive(Default)]
+pub struct Sinks(Vec<Box<dyn Sink>>);
+
+impl Sinks {
+    pub fn push(&mut self, sink: impl Sink + 'static) {
+        self.0.push(Box::new(sink));
+    }
+
+    /// Adds a sink that sends over the network, batching its readings if asked to, and spooling
+    /// them while it can't be reached.
+    pub fn push_network(&mut self, sink: impl Sink + 'static, args: &Args) -> Result<(), Box<dyn Error>> {
+        let sink: Box<dyn Sink> = match &args.spool_dir {
+            Some(dir) => Box::new(Spooled::new(sink, dir)?),
+            None => Box::new(sink),
+        };
+        match args.batch_size {
+            1 => self.0.push(sink),
+            size => self.push(Batched::new(sink, size.into(), args.batch_interval)),
+        }
+        Ok(())
+    }
+
+    pub async fn reading(&mut self, record: &Record) {
+        for sink in &mut self.0 {
+            if let Err(e) = sink.reading(record).await {
+                error!("Couldn't write reading to {}: {}", sink.name(), e);
+            }
+        }
+    }
+
+    pub async fn waveform(&mut self, record: &WaveformRecord) {
+        for sink in &mut self.0 {
+            if let Err(e) = sink.waveform(record).await {
+                error!("Couldn't write waveform to {}: {}", sink.name(), e);
+            }
+        }
+    }
+
+    pub async fn event(&mut self, record: &EventRecord) {
+        for sink in &mut self.0 {
+            if let Err(e) = sink.event(record).await {
+                error!("Couldn't write event to {}: {}", sink.name(), e);
+            }
+        }
+    }
+
+    pub async fn close(&mut self) {
+        for sink in &mut self.0 {
+            if let Err(e) = sink.close().await {
+                error!("Couldn't finish writing to {}: {}", sink.name(), e);
+            }
+        }
+    }
+}
+
+/// Writes readings to `--output`, or stdout without it.
+pub fn row_sink(args: &Args) -> Result<RowSink, Box<dyn Error>> {
+    Ok(RowSink::new(
+        Destination::new(args.output.as_deref())?,
+        args.format,
+        args.timestamps(),
+        args.format_template.clone(),
+        args.gap_markers,
+        args.extra_columns(),
+    ))
+}
+
+/// Creates the sinks selected on the command line.
+pub async fn from_args(args: &Args) -> Result<Sinks, Box<dyn Error>> {
+    let mut sinks = Sinks::default();
+    // --one-shot writes its reading itself, once it has settled.
+    if (args.output.is_some() || !args.tui()) && !args.one_shot {
+        match args.average {
+            Some(interval) => sinks.push(AverageRowSink::new(
+                Destination::new(args.output.as_deref())?,
+                args.format,
+                args.timestamps(),
+                interval,
+            )),
+            None => sinks.push(row_sink(args)?),
+        }
+    }
+    if let Some(path) = &args.waveform_output {
+        sinks.push(WaveformRowSink::new(
+            Destination::new(Some(path))?,
+            args.format,
+            args.timestamps(),
+            args.flush_interval,
+        ));
+    }
+    if let Some(path) = &args.events_output {
+        sinks.push(EventRowSink::new(Destination::new(Some(path))?, args.format, args.timestamps()));
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(url) = &args.mqtt {
+        let credentials = args.mqtt_username.clone().zip(args.mqtt_password.clone());
+        let sink = MqttSink::new(url, &args.mqtt_topic, credentials)?;
+        if args.mqtt_home_assistant {
+            sink.announce_to_home_assistant(&args.mqtt_discovery_prefix)
+                .map_err(|e| e as Box<dyn Error>)?;
+        }
+        sinks.push_network(sink, args)?;
+    }
+    if let Some(url) = &args.influxdb {
+        let http = influxdb::HttpOptions {
+            token: args.influxdb_token.clone(),
+            org: args.influxdb_org.clone(),
+            bucket: args.influxdb_bucket.clone(),
+        };
+        sinks.push_network(InfluxDbSink::new(url, &args.influxdb_measurement, http).await?, args)?;
+    }
+    if let Some(url) = &args.grafana_live {
+        let token = args.grafana_live_token.clone().ok_or("--grafana-live needs --grafana-live-token")?;
+        sinks.push_network(GrafanaLiveSink::new(url, &args.grafana_live_stream, token)?, args)?;
+    }
+    #[cfg(feature = "kafka")]
+    if let Some(brokers) = &args.kafka {
+        sinks.push_network(KafkaSink::new(brokers, &args.kafka_topic)?, args)?;
+    }
+    #[cfg(feature = "nats")]
+    if let Some(url) = &args.nats {
+        let sink = NatsSink::new(url, &args.nats_subject, args.nats_jetstream.as_deref()).await?;
+        sinks.push_network(sink, args)?;
+    }
+    #[cfg(feature = "redis")]
+    if let Some(url) = &args.redis {
+        if args.redis_channel.is_none() && args.redis_stream.is_none() {
+            return Err("--redis needs --redis-channel or --redis-stream".into());
+        }
+        let stream = args.redis_stream.as_deref().map(|key| (key, args.redis_stream_max_length));
+        sinks.push_network(RedisSink::new(url, args.redis_channel.as_deref(), stream).await?, args)?;
+    }
+    #[cfg(feature = "notifications")]
+    if args.notify {
+        sinks.push(NotificationSink);
+    }
+    if let Some(sound) = args.alarm_sound {
+        sinks.push(SoundSink::new(sound)?);
+    }
+    let alert_targets = args.alert_targets();
+    if !alert_targets.is_empty() {
+        sinks.push(AlertSink::new(alert_targets));
+    }
+    if let Some(program) = &args.alarm_command {
+        sinks.push(AlarmCommandSink::new(program.clone()));
+    }
+    if let Some(address) = args.udp {
+        sinks.push(UdpSink::new(address).await?);
+    }
+    if let Some(address) = args.osc {
+        sinks.push(OscSink::new(address).await?);
+    }
+    if let Some(address) = &args.graphite {
+        sinks.push_network(GraphiteSink::new(address, &args.graphite_prefix), args)?;
+    }
+    if let Some(address) = &args.statsd {
+        sinks.push(StatsdSink::new(address, &args.statsd_prefix).await?);
+    }
+    if let Some(target) = &args.fhir {
+        sinks.push_network(FhirSink::new(target, args.fhir_subject.as_deref())?, args)?;
+    }
+    if let Some(address) = &args.hl7 {
+        sinks.push(Hl7Sink::new(address, args.hl7_patient_id.as_deref(), args.hl7_interval));
+    }
+    if let Some((client_id, client_secret)) = args.google_fit_client_id.clone().zip(args.google_fit_client_secret.clone()) {
+        let token_file = match &args.google_fit_token_file {
+            Some(path) => path.clone(),
+            None => config::config_dir()
+                .ok_or("No config directory for the Google Fit token, pass --google-fit-token-file")?
+                .join("google-fit-token"),
+        };
+        let oauth = google_fit::OAuthOptions { client_id, client_secret, token_file };
+        sinks.push(GoogleFitSink::new(oauth, args.google_fit_upload_interval).await?);
+    }
+    if let Some(path) = &args.edf {
+        sinks.push(EdfSink::new(path, args.edf_pleth));
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        sinks.push(SqliteSink::new(path)?);
+    }
+    #[cfg(feature = "postgres")]
+    if let Some(url) = &args.postgres {
+        sinks.push(PostgresSink::new(
+            url,
+            args.postgres_timescaledb,
+            args.postgres_batch_size,
+            args.postgres_batch_interval,
+        ));
+    }
+    #[cfg(feature = "parquet")]
+    if let Some(pattern) = &args.parquet {
+        sinks.push(ParquetSink::new(pattern, args.parquet_row_group_size)?);
+    }
+    // Not the dashboard and live streams, which are added later, as they show what's going on
+    // right now.
+    if args.every > 1 {
+        sinks.0 = sinks.0.into_iter().map(|sink| Box::new(Decimated::new(sink, args.every.into())) as _).collect();
+    }
+    Ok(sinks)
+}
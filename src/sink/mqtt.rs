@@ -0,0 +1,195 @@
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, Outgoing, QoS};
+use serde_json::json;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time;
+
+use super::{Sink, SinkError};
+use crate::output::Record;
+
+/// Publishes every reading as JSON to an MQTT topic.
+pub struct MqttSink {
+    client: AsyncClient,
+    topic: String,
+    /// Whether the broker has accepted the connection and it hasn't failed since, kept up to date
+    /// by the background task.
+    connected: Arc<AtomicBool>,
+    /// Completes once the background task has sent everything queued before disconnecting.
+    disconnected: oneshot::Receiver<()>,
+}
+
+/// How long to wait for queued messages to go out when closing.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Splits `tcp://host:port` (or `mqtt://`, or just `host`) into host and port.
+fn parse_broker_url(url: &str) -> Result<(String, u16), Box<dyn Error>> {
+    let address = url
+        .strip_prefix("tcp://")
+        .or_else(|| url.strip_prefix("mqtt://"))
+        .unwrap_or(url);
+    match address.rsplit_once(':') {
+        Some((host, port)) => Ok((host.to_string(), port.parse()?)),
+        None => Ok((address.to_string(), 1883)),
+    }
+}
+
+impl MqttSink {
+    /// Connects to the broker. The connection is kept up by a background task, which reconnects
+    /// whenever it drops.
+    pub fn new(url: &str, topic: &str, credentials: Option<(String, String)>) -> Result<Self, Box<dyn Error>> {
+        let (host, port) = parse_broker_url(url)?;
+        let mut options = MqttOptions::new(format!("pc60fw-{}", std::process::id()), host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some((username, password)) = credentials {
+            options.set_credentials(username, password);
+        }
+
+        let (client, mut event_loop) = AsyncClient::new(options, 64);
+        let url = url.to_string();
+        let (disconnected_sender, disconnected) = oneshot::channel();
+        let connected = Arc::new(AtomicBool::new(false));
+        let connection = connected.clone();
+        tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Incoming(Incoming::ConnAck(_))) => connection.store(true, Ordering::Relaxed),
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                        let _ = disconnected_sender.send(());
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        connection.store(false, Ordering::Relaxed);
+                        warn!("MQTT connection to {} failed: {}", url, e);
+                        time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            }
+        });
+
+        Ok(MqttSink {
+            client,
+            topic: topic.to_string(),
+            connected,
+            disconnected,
+        })
+    }
+
+    /// Publishes without waiting, so a dead broker can't hold up the BLE loop. Messages that
+    /// don't fit in the queue are dropped.
+    pub fn publish(&self, topic: &str, payload: Vec<u8>, retain: bool) -> Result<(), SinkError> {
+        self.client.try_publish(topic, QoS::AtMostOnce, retain, payload)?;
+        Ok(())
+    }
+}
+
+struct HomeAssistantSensor {
+    object_id: &'static str,
+    name: &'static str,
+    value_template: &'static str,
+    unit: &'static str,
+    device_class: Option<&'static str>,
+    icon: &'static str,
+}
+
+const HOME_ASSISTANT_SENSORS: [HomeAssistantSensor; 4] = [
+    HomeAssistantSensor {
+        object_id: "spo2",
+        name: "SpO2",
+        value_template: "{{ value_json.spo2 }}",
+        unit: "%",
+        device_class: None,
+        icon: "mdi:water-percent",
+    },
+    HomeAssistantSensor {
+        object_id: "heartrate",
+        name: "Heart rate",
+        value_template: "{{ value_json.heartrate }}",
+        unit: "bpm",
+        device_class: None,
+        icon: "mdi:heart-pulse",
+    },
+    HomeAssistantSensor {
+        object_id: "pi",
+        name: "Perfusion index",
+        value_template: "{{ value_json.pi }}",
+        unit: "%",
+        device_class: None,
+        icon: "mdi:pulse",
+    },
+    HomeAssistantSensor {
+        object_id: "battery",
+        name: "Battery",
+        // The device reports 0-3 bars, Home Assistant wants a percentage.
+        value_template: "{{ (value_json.battery * 100 / 3) | round(0) if value_json.battery is not none }}",
+        unit: "%",
+        device_class: Some("battery"),
+        icon: "mdi:battery",
+    },
+];
+
+impl MqttSink {
+    /// Publishes retained Home Assistant MQTT discovery configs, so the readings show up as
+    /// sensors without any manual configuration.
+    pub fn announce_to_home_assistant(&self, discovery_prefix: &str) -> Result<(), SinkError> {
+        let node_id: String = self
+            .topic
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let device = json!({
+            "identifiers": [format!("pc60fw_{}", node_id)],
+            "name": "PC-60FW Pulse Oximeter",
+            "manufacturer": "Creative Medical",
+            "model": "PC-60FW",
+        });
+        for sensor in HOME_ASSISTANT_SENSORS {
+            let mut config = json!({
+                "name": sensor.name,
+                "unique_id": format!("pc60fw_{}_{}", node_id, sensor.object_id),
+                "state_topic": self.topic,
+                "value_template": sensor.value_template,
+                "unit_of_measurement": sensor.unit,
+                "state_class": "measurement",
+                "icon": sensor.icon,
+                "device": device,
+            });
+            if let Some(device_class) = sensor.device_class {
+                config["device_class"] = json!(device_class);
+            }
+            let config_topic = format!("{}/sensor/{}/{}/config", discovery_prefix, node_id, sensor.object_id);
+            self.publish(&config_topic, serde_json::to_vec(&config)?, true)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for MqttSink {
+    fn name(&self) -> String {
+        format!("MQTT topic {:?}", self.topic)
+    }
+
+    /// Fails while the broker can't be reached, rather than queueing readings that would be lost
+    /// if the program exits before it's back, so `--spool-dir` can keep them instead.
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        if !self.connected.load(Ordering::Relaxed) {
+            return Err("Not connected to the MQTT broker".into());
+        }
+        self.publish(&self.topic, serde_json::to_vec(record)?, false)
+    }
+
+    /// Disconnects once the queued messages have been sent, if the broker can be reached in time.
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.client.disconnect().await?;
+        time::timeout(CLOSE_TIMEOUT, &mut self.disconnected)
+            .await
+            .map_err(|_| "Timed out sending the last messages")?
+            .map_err(|_| "The connection was already gone")?;
+        Ok(())
+    }
+}
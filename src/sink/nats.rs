@@ -0,0 +1,77 @@
+use async_nats::connection::State;
+use async_nats::jetstream::{self, stream};
+use async_trait::async_trait;
+use std::error::Error;
+
+use super::{Sink, SinkError};
+use crate::output::Record;
+
+/// Publishes readings as JSON to a NATS subject, optionally through JetStream so they're
+/// persisted.
+pub struct NatsSink {
+    client: async_nats::Client,
+    jetstream: Option<jetstream::Context>,
+    subject: String,
+}
+
+impl NatsSink {
+    /// With `jetstream_stream`, the stream of that name is created if needed, capturing
+    /// `subject`.
+    pub async fn new(url: &str, subject: &str, jetstream_stream: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let client = async_nats::connect(url).await?;
+        let jetstream = match jetstream_stream {
+            Some(name) => {
+                let context = jetstream::new(client.clone());
+                context
+                    .get_or_create_stream(stream::Config {
+                        name: name.to_string(),
+                        subjects: vec![subject.to_string()],
+                        ..Default::default()
+                    })
+                    .await?;
+                Some(context)
+            }
+            None => None,
+        };
+        Ok(NatsSink {
+            client,
+            jetstream,
+            subject: subject.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for NatsSink {
+    fn name(&self) -> String {
+        format!("NATS subject {:?}", self.subject)
+    }
+
+    /// Fails while the server can't be reached, rather than queueing readings that would be lost
+    /// if the program exits before it's back, so `--spool-dir` can keep them instead.
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        if self.client.connection_state() != State::Connected {
+            return Err("Not connected to the NATS server".into());
+        }
+        let payload = serde_json::to_string(record)?;
+        match &self.jetstream {
+            Some(jetstream) => {
+                let ack = jetstream.publish(self.subject.clone(), payload.into()).await?;
+                // Don't hold up the other sinks waiting for the server to persist it.
+                tokio::spawn(async move {
+                    if let Err(e) = ack.await {
+                        warn!("JetStream didn't acknowledge reading: {}", e);
+                    }
+                });
+            }
+            None => self.client.publish(self.subject.clone(), payload.into()).await?,
+        }
+        Ok(())
+    }
+
+    /// Publishing only queues messages, so this waits for them to be sent.
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.client.flush().await?;
+        Ok(())
+    }
+}
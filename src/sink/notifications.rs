@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+#[cfg(all(unix, not(target_os = "macos")))]
+use notify_rust::Urgency;
+use notify_rust::Notification;
+
+use super::{Sink, SinkError};
+use crate::output::{Event, EventRecord, Record};
+
+/// Shows alarms, and devices connecting and disconnecting, as desktop notifications, so the
+/// reader can run in the background.
+#[derive(Default)]
+pub struct NotificationSink;
+
+#[async_trait]
+impl Sink for NotificationSink {
+    fn name(&self) -> String {
+        "desktop notifications".to_string()
+    }
+
+    async fn reading(&mut self, _record: &Record) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        let (summary, critical) = match record.event {
+            Event::Alarm { .. } => ("Oximeter alarm", true),
+            Event::AlarmCleared { .. } => ("Oximeter alarm cleared", false),
+            Event::Connected => ("Oximeter connected", false),
+            Event::Disconnected => ("Oximeter disconnected", false),
+            Event::BatteryLow { .. } => ("Oximeter battery low", false),
+            _ => return Ok(()),
+        };
+        let mut notification = Notification::new();
+        notification
+            .appname("ble-spo2")
+            .summary(summary)
+            .body(&format!("{} ({})", record.event, record.device));
+        // Critical notifications stay up until they're dismissed.
+        #[cfg(all(unix, not(target_os = "macos")))]
+        notification.urgency(if critical { Urgency::Critical } else { Urgency::Normal });
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        let _ = critical;
+        // Showing one talks to the notification daemon synchronously.
+        tokio::task::spawn_blocking(move || notification.show().map(drop)).await??;
+        Ok(())
+    }
+}
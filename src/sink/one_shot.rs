@@ -0,0 +1,86 @@
+use async_trait::async_trait;
+use pc60fw_protocol::ProbeStatus;
+use std::collections::VecDeque;
+use tokio::sync::watch;
+
+use super::{RowSink, Sink, SinkError};
+use crate::output::{Quality, Record};
+
+/// How far apart the SpO2 of the readings in a steady run may be, in percentage points.
+const SPO2_TOLERANCE: u8 = 1;
+
+/// How far apart the heart rate of the readings in a steady run may be, in bpm. The pulse changes
+/// a little with every breath, so this can't be much tighter.
+const HEART_RATE_TOLERANCE: u8 = 5;
+
+/// For `--one-shot`: writes a single reading once the measurement has settled, i.e. the last
+/// `samples` readings all had a pulse with a good signal and agree with each other, and then
+/// tells the reader it can stop.
+pub struct OneShotSink {
+    rows: RowSink,
+    samples: usize,
+    recent: VecDeque<Record>,
+    measured: watch::Sender<bool>,
+}
+
+impl OneShotSink {
+    /// Also returns what turns true once the reading has been written.
+    pub fn new(rows: RowSink, samples: usize) -> (Self, watch::Receiver<bool>) {
+        let (measured, receiver) = watch::channel(false);
+        let sink = OneShotSink {
+            rows,
+            samples,
+            recent: VecDeque::with_capacity(samples),
+            measured,
+        };
+        (sink, receiver)
+    }
+
+    fn steady(&self) -> bool {
+        let spread = |value: fn(&Record) -> Option<u8>| {
+            let values = self.recent.iter().filter_map(value);
+            let (min, max) = values.fold((u8::MAX, u8::MIN), |(min, max), v| (min.min(v), max.max(v)));
+            max.saturating_sub(min)
+        };
+        self.recent.len() == self.samples
+            && spread(|record| record.spo2) <= SPO2_TOLERANCE
+            && spread(|record| record.heartrate) <= HEART_RATE_TOLERANCE
+    }
+}
+
+/// Whether the device had a pulse and a good signal, so the reading means something.
+fn usable(record: &Record) -> bool {
+    record.status == ProbeStatus::Stable
+        && record.quality == Quality::Good
+        && record.spo2.is_some()
+        && record.heartrate.is_some()
+}
+
+#[async_trait]
+impl Sink for OneShotSink {
+    fn name(&self) -> String {
+        "one-shot reading".to_string()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        if *self.measured.borrow() {
+            return Ok(());
+        }
+        if !usable(record) {
+            // Starts over, so the readings that count are in a row.
+            self.recent.clear();
+            return Ok(());
+        }
+        if self.recent.len() == self.samples {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(record.clone());
+        if !self.steady() {
+            return Ok(());
+        }
+        self.rows.reading(record).await?;
+        // Only fails once the reader has stopped listening, when there's nothing left to tell.
+        let _ = self.measured.send(true);
+        Ok(())
+    }
+}
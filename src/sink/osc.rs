@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+use super::{Sink, SinkError};
+use crate::output::{Record, WaveformRecord};
+
+/// Sends `/spo2` and `/hr` for every reading and `/pleth` for every waveform sample as Open Sound
+/// Control messages, for biofeedback and audiovisual tools like Max/MSP or TouchDesigner.
+pub struct OscSink {
+    socket: UdpSocket,
+    address: SocketAddr,
+}
+
+/// Appends `s` as an OSC string: null-terminated, and padded to a multiple of four bytes.
+fn push_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    packet.push(0);
+    while !packet.len().is_multiple_of(4) {
+        packet.push(0);
+    }
+}
+
+/// An OSC message with a single int32 argument.
+fn message(address: &str, value: i32) -> Vec<u8> {
+    let mut packet = Vec::new();
+    push_string(&mut packet, address);
+    push_string(&mut packet, ",i");
+    packet.extend_from_slice(&value.to_be_bytes());
+    packet
+}
+
+impl OscSink {
+    pub async fn new(address: SocketAddr) -> io::Result<Self> {
+        let bind_address: SocketAddr = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let socket = UdpSocket::bind(bind_address).await?;
+        Ok(OscSink { socket, address })
+    }
+
+    async fn send(&self, address: &str, value: i32) -> io::Result<()> {
+        self.socket.send_to(&message(address, value), self.address).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for OscSink {
+    fn name(&self) -> String {
+        format!("OSC {}", self.address)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        if let Some(spo2) = record.spo2 {
+            self.send("/spo2", spo2.into()).await?;
+        }
+        if let Some(heartrate) = record.heartrate {
+            self.send("/hr", heartrate.into()).await?;
+        }
+        Ok(())
+    }
+
+    async fn waveform(&mut self, record: &WaveformRecord) -> Result<(), SinkError> {
+        self.send("/pleth", record.pleth.into()).await?;
+        Ok(())
+    }
+}
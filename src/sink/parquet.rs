@@ -0,0 +1,156 @@
+use arrow_array::{ArrayRef, Float32Array, RecordBatch, StringArray, TimestampMillisecondArray, UInt8Array};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use async_trait::async_trait;
+use chrono::Local;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{Sink, SinkError};
+use crate::output::Record;
+use crate::rotating_file::check_pattern;
+
+/// Writes readings to Parquet files.
+///
+/// Parquet files can't be appended to and are only readable once closed, so readings are
+/// written in row groups of `row_group_size`, and the file name is a strftime pattern: whenever it
+/// changes, the current file is closed and a new one started. A pattern like
+/// `readings-%Y-%m-%d.parquet` gives one file per day.
+pub struct ParquetSink {
+    pattern: String,
+    schema: Arc<Schema>,
+    current: Option<(String, ArrowWriter<File>)>,
+    pending: Vec<Record>,
+    row_group_size: usize,
+}
+
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("time", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false),
+        Field::new("spo2", DataType::UInt8, true),
+        Field::new("heartrate", DataType::UInt8, true),
+        Field::new("pi", DataType::Float32, true),
+        Field::new("battery", DataType::UInt8, true),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("signal", DataType::UInt8, false),
+        Field::new("quality", DataType::Utf8, false),
+        Field::new("device", DataType::Utf8, false),
+    ])
+}
+
+/// `path`, or `path` with a counter added before the extension if it already exists.
+fn unused_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    let mut candidate = path.to_path_buf();
+    let mut counter = 1;
+    while candidate.exists() {
+        candidate = path.with_file_name(format!("{}-{}{}", stem, counter, extension));
+        counter += 1;
+    }
+    candidate
+}
+
+impl ParquetSink {
+    pub fn new(pattern: &str, row_group_size: usize) -> Result<Self, String> {
+        check_pattern(pattern)?;
+        Ok(ParquetSink {
+            pattern: pattern.to_string(),
+            schema: Arc::new(schema()),
+            current: None,
+            pending: Vec::new(),
+            row_group_size,
+        })
+    }
+
+    fn to_batch(&self) -> Result<RecordBatch, SinkError> {
+        let records = &self.pending;
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(
+                TimestampMillisecondArray::from_iter_values(records.iter().map(|r| r.time.timestamp_millis()))
+                    .with_timezone("UTC"),
+            ),
+            Arc::new(UInt8Array::from_iter(records.iter().map(|r| r.spo2))),
+            Arc::new(UInt8Array::from_iter(records.iter().map(|r| r.heartrate))),
+            Arc::new(Float32Array::from_iter(records.iter().map(|r| r.pi))),
+            Arc::new(UInt8Array::from_iter(records.iter().map(|r| r.battery))),
+            Arc::new(StringArray::from_iter_values(records.iter().map(|r| r.status.to_string()))),
+            Arc::new(UInt8Array::from_iter_values(records.iter().map(|r| r.signal))),
+            Arc::new(StringArray::from_iter_values(records.iter().map(|r| r.quality.to_string()))),
+            Arc::new(StringArray::from_iter_values(records.iter().map(|r| r.device.as_str()))),
+        ];
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+
+    /// Writes the pending readings as a row group of the current file.
+    fn write_pending(&mut self) -> Result<(), SinkError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let batch = self.to_batch()?;
+        if let Some((_, writer)) = &mut self.current {
+            writer.write(&batch)?;
+            writer.flush()?;
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Writes what's left and closes the current file, which is only readable after this.
+    fn finish(&mut self) -> Result<(), SinkError> {
+        self.write_pending()?;
+        if let Some((name, writer)) = self.current.take() {
+            writer.close()?;
+            debug!("Closed Parquet file {}", name);
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, name: String) -> Result<(), SinkError> {
+        let path = unused_path(Path::new(&name));
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        info!("Writing Parquet to {:?}", path);
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let writer = ArrowWriter::try_new(File::create(&path)?, self.schema.clone(), Some(properties))?;
+        self.current = Some((name, writer));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for ParquetSink {
+    fn name(&self) -> String {
+        format!("Parquet files {:?}", self.pattern)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let name = record.time.with_timezone(&Local).format(&self.pattern).to_string();
+        if self.current.as_ref().map(|(current, _)| current) != Some(&name) {
+            self.finish()?;
+            self.open(name)?;
+        }
+        self.pending.push(record.clone());
+        if self.pending.len() >= self.row_group_size {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.finish()
+    }
+}
+
+/// In case the sink goes away without being closed.
+impl Drop for ParquetSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            error!("Couldn't finish Parquet file: {}", e);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio_postgres::{Client, Config, NoTls};
+
+use super::{Sink, SinkError, CONNECT_TIMEOUT};
+use crate::output::Record;
+
+/// Readings kept while the database is unreachable. Past this, the oldest ones are dropped.
+const MAX_PENDING: usize = 24 * 60 * 60;
+
+/// The time column comes first and isn't unique, which is what TimescaleDB hypertables want.
+const CREATE_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS readings (
+        time TIMESTAMPTZ NOT NULL,
+        spo2 SMALLINT,
+        heartrate SMALLINT,
+        pi REAL,
+        battery SMALLINT,
+        status TEXT NOT NULL,
+        signal SMALLINT NOT NULL,
+        quality TEXT NOT NULL,
+        device TEXT
+    );
+    ALTER TABLE readings ADD COLUMN IF NOT EXISTS device TEXT;
+    CREATE INDEX IF NOT EXISTS readings_time ON readings (time DESC);
+";
+
+/// Writes readings to PostgreSQL in batches, reconnecting whenever the connection is lost.
+pub struct PostgresSink {
+    url: String,
+    timescaledb: bool,
+    client: Option<Client>,
+    pending: VecDeque<Record>,
+    batch_size: usize,
+    batch_interval: Duration,
+    last_flush: Instant,
+    /// Whether the last batch couldn't be written, so the next attempt waits for the interval
+    /// rather than for a full batch, which there'd be with every reading.
+    failing: bool,
+}
+
+impl PostgresSink {
+    /// Doesn't connect until the first batch is written, so a database that's down at startup
+    /// doesn't keep readings from being recorded elsewhere.
+    pub fn new(url: &str, timescaledb: bool, batch_size: usize, batch_interval: Duration) -> Self {
+        PostgresSink {
+            url: url.to_string(),
+            timescaledb,
+            client: None,
+            pending: VecDeque::new(),
+            batch_size,
+            batch_interval,
+            last_flush: Instant::now(),
+            failing: false,
+        }
+    }
+
+    async fn connect(&self) -> Result<Client, SinkError> {
+        let mut config: Config = self.url.parse()?;
+        if config.get_connect_timeout().is_none() {
+            config.connect_timeout(CONNECT_TIMEOUT);
+        }
+        let (client, connection) = config.connect(NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("PostgreSQL connection closed: {}", e);
+            }
+        });
+        client.batch_execute(CREATE_TABLE).await?;
+        if self.timescaledb {
+            client
+                .execute("SELECT create_hypertable('readings', 'time', if_not_exists => TRUE)", &[])
+                .await?;
+        }
+        info!("Connected to PostgreSQL");
+        Ok(client)
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.last_flush = Instant::now();
+        let result = self.insert().await;
+        self.failing = result.is_err();
+        result
+    }
+
+    async fn insert(&mut self) -> Result<(), SinkError> {
+        if self.client.as_ref().is_none_or(|client| client.is_closed()) {
+            self.client = Some(self.connect().await?);
+        }
+        let client = self.client.as_ref().unwrap();
+
+        let times: Vec<DateTime<Utc>> = self.pending.iter().map(|r| r.time).collect();
+        let spo2: Vec<Option<i16>> = self.pending.iter().map(|r| r.spo2.map(i16::from)).collect();
+        let heartrate: Vec<Option<i16>> = self.pending.iter().map(|r| r.heartrate.map(i16::from)).collect();
+        let pi: Vec<Option<f32>> = self.pending.iter().map(|r| r.pi).collect();
+        let battery: Vec<Option<i16>> = self.pending.iter().map(|r| r.battery.map(i16::from)).collect();
+        let status: Vec<String> = self.pending.iter().map(|r| r.status.to_string()).collect();
+        let signal: Vec<i16> = self.pending.iter().map(|r| i16::from(r.signal)).collect();
+        let quality: Vec<String> = self.pending.iter().map(|r| r.quality.to_string()).collect();
+        let device: Vec<&str> = self.pending.iter().map(|r| r.device.as_str()).collect();
+        let result = client
+            .execute(
+                "INSERT INTO readings (time, spo2, heartrate, pi, battery, status, signal, quality, device)
+                 SELECT * FROM UNNEST($1::timestamptz[], $2::smallint[], $3::smallint[], $4::real[],
+                                      $5::smallint[], $6::text[], $7::smallint[], $8::text[], $9::text[])",
+                &[&times, &spo2, &heartrate, &pi, &battery, &status, &signal, &quality, &device],
+            )
+            .await;
+        match result {
+            Ok(_) => {
+                self.pending.clear();
+                Ok(())
+            }
+            Err(e) => {
+                // Reconnect on the next attempt, the connection may be broken.
+                self.client = None;
+                Err(e.into())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for PostgresSink {
+    fn name(&self) -> String {
+        "PostgreSQL".to_string()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(record.clone());
+        let full = self.pending.len() >= self.batch_size && !self.failing;
+        if full || self.last_flush.elapsed() >= self.batch_interval {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.flush().await
+    }
+}
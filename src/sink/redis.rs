@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::streams::StreamMaxlen;
+use redis::AsyncCommands;
+use std::error::Error;
+
+use super::{Sink, SinkError};
+use crate::output::Record;
+
+/// Publishes readings as JSON to a Redis channel, and/or appends them to a Redis stream.
+pub struct RedisSink {
+    connection: ConnectionManager,
+    channel: Option<String>,
+    stream: Option<(String, usize)>,
+}
+
+impl RedisSink {
+    /// `stream` is the stream's key and the approximate number of entries to keep in it.
+    pub async fn new(url: &str, channel: Option<&str>, stream: Option<(&str, usize)>) -> Result<Self, Box<dyn Error>> {
+        // The connection manager reconnects by itself whenever the connection is lost.
+        let connection = ConnectionManager::new(redis::Client::open(url)?).await?;
+        Ok(RedisSink {
+            connection,
+            channel: channel.map(str::to_string),
+            stream: stream.map(|(key, max_length)| (key.to_string(), max_length)),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for RedisSink {
+    fn name(&self) -> String {
+        "Redis".to_string()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        if let Some(channel) = &self.channel {
+            let _: () = self.connection.publish(channel, serde_json::to_string(record)?).await?;
+        }
+        if let Some((key, max_length)) = &self.stream {
+            // One stream field per column, leaving out empty ones.
+            let serde_json::Value::Object(columns) = serde_json::to_value(record)? else {
+                unreachable!("records serialize to objects");
+            };
+            let fields: Vec<(String, String)> = columns
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(name, value)| match value {
+                    serde_json::Value::String(s) => (name, s),
+                    value => (name, value.to_string()),
+                })
+                .collect();
+            let _: () = self
+                .connection
+                .xadd_maxlen(key, StreamMaxlen::Approx(*max_length), "*", &fields)
+                .await?;
+        }
+        Ok(())
+    }
+}
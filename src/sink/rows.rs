@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use std::time::Duration;
+
+use super::{Sink, SinkError};
+use crate::output::{Destination, Event, EventRecord, Extra, Format, Gap, Record, RowWriter, Timestamps, WaveformRecord};
+use crate::template::Template;
+
+/// Writes readings to stdout or a file, in one of the built-in formats or a user's template.
+pub struct RowSink {
+    writer: RowWriter<Record>,
+    template: Option<Template>,
+    /// Whether to write a [`Gap`] whenever a device disconnects.
+    gap_markers: bool,
+    /// The extra columns of the readings, for the gaps to have them too.
+    extra: Extra,
+}
+
+impl RowSink {
+    pub fn new(
+        destination: Destination,
+        format: Format,
+        timestamps: Timestamps,
+        template: Option<Template>,
+        gap_markers: bool,
+        extra: Extra,
+    ) -> Self {
+        RowSink {
+            writer: RowWriter::new(destination, format, timestamps),
+            template,
+            gap_markers,
+            extra,
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for RowSink {
+    fn name(&self) -> String {
+        "readings output".to_string()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        match &self.template {
+            Some(template) => {
+                let line = template.render(record, self.writer.timestamps());
+                self.writer.write_line(record.time, &line)?
+            }
+            None => self.writer.write(record)?,
+        }
+        Ok(())
+    }
+
+    /// Events go among the readings only as JSON lines, which can tell them apart. Templates
+    /// only ever get readings.
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        if self.template.is_some() {
+            return Ok(());
+        }
+        if self.writer.format() == Format::Jsonl {
+            self.writer.write_json(record)?;
+        }
+        if self.gap_markers && record.event == Event::Disconnected {
+            self.writer.write_alike(&Gap::new(record.time, &record.device, self.extra.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the plethysmograph waveform to stdout or a file, which is flushed every
+/// `flush_interval` as there are dozens of samples a second.
+pub struct WaveformRowSink(RowWriter<WaveformRecord>);
+
+impl WaveformRowSink {
+    pub fn new(destination: Destination, format: Format, timestamps: Timestamps, flush_interval: Duration) -> Self {
+        WaveformRowSink(RowWriter::new(destination, format, timestamps).buffered(flush_interval))
+    }
+}
+
+#[async_trait]
+impl Sink for WaveformRowSink {
+    fn name(&self) -> String {
+        "waveform output".to_string()
+    }
+
+    async fn reading(&mut self, _record: &Record) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn waveform(&mut self, record: &WaveformRecord) -> Result<(), SinkError> {
+        Ok(self.0.write(record)?)
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        Ok(self.0.flush()?)
+    }
+}
+
+/// Writes events, like desaturations, to stdout or a file.
+pub struct EventRowSink(RowWriter<EventRecord>);
+
+impl EventRowSink {
+    pub fn new(destination: Destination, format: Format, timestamps: Timestamps) -> Self {
+        EventRowSink(RowWriter::new(destination, format, timestamps))
+    }
+}
+
+#[async_trait]
+impl Sink for EventRowSink {
+    fn name(&self) -> String {
+        "events output".to_string()
+    }
+
+    async fn reading(&mut self, _record: &Record) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        Ok(self.0.write(record)?)
+    }
+}
@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{Sink, SinkError};
+use crate::alarm::Alarm;
+use crate::output::{Event, EventRecord, Record};
+
+/// How often the alarm sounds while it's going off.
+const PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AlarmSound {
+    /// The terminal bell, which works anywhere there's a terminal.
+    Bell,
+    /// A tone from the speakers. Needs the `sound` feature.
+    Tone,
+}
+
+/// Sounds for as long as any alarm is going off, rather than once when it starts, so nobody
+/// sleeps through it.
+pub struct SoundSink {
+    active: HashSet<(String, Alarm)>,
+    sounding: Arc<AtomicBool>,
+}
+
+impl SoundSink {
+    pub fn new(sound: AlarmSound) -> Result<Self, Box<dyn Error>> {
+        let sounding = Arc::new(AtomicBool::new(false));
+        match sound {
+            AlarmSound::Bell => {
+                let sounding = sounding.clone();
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(PERIOD);
+                    loop {
+                        interval.tick().await;
+                        if sounding.load(Ordering::Relaxed) {
+                            // stderr, so the bell doesn't end up in readings written to stdout.
+                            let mut stderr = std::io::stderr();
+                            let _ = stderr.write_all(b"\x07").and_then(|()| stderr.flush());
+                        }
+                    }
+                });
+            }
+            #[cfg(feature = "sound")]
+            AlarmSound::Tone => tone::spawn(sounding.clone())?,
+            #[cfg(not(feature = "sound"))]
+            AlarmSound::Tone => return Err("--alarm-sound tone needs the sound feature".into()),
+        }
+        Ok(SoundSink { active: HashSet::new(), sounding })
+    }
+}
+
+#[async_trait]
+impl Sink for SoundSink {
+    fn name(&self) -> String {
+        "alarm sound".to_string()
+    }
+
+    async fn reading(&mut self, _record: &Record) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        match record.event {
+            Event::Alarm { alarm, .. } => {
+                self.active.insert((record.device.clone(), alarm));
+            }
+            Event::AlarmCleared { alarm, .. } => {
+                self.active.remove(&(record.device.clone(), alarm));
+            }
+            // Its alarms can't clear any more.
+            Event::Disconnected => self.active.retain(|(device, _)| *device != record.device),
+            _ => {}
+        }
+        self.sounding.store(!self.active.is_empty(), Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sound")]
+mod tone {
+    use rodio::source::{SineWave, Source};
+    use rodio::OutputStreamBuilder;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    use super::PERIOD;
+
+    const FREQUENCY: f32 = 880.0;
+    const LENGTH: Duration = Duration::from_millis(400);
+
+    /// Beeps from a thread of its own, as the audio output can't be moved between threads.
+    pub fn spawn(sounding: Arc<AtomicBool>) -> Result<(), String> {
+        let (opened, result) = mpsc::channel();
+        std::thread::spawn(move || {
+            let stream = match OutputStreamBuilder::open_default_stream() {
+                Ok(stream) => {
+                    let _ = opened.send(Ok(()));
+                    stream
+                }
+                Err(e) => {
+                    let _ = opened.send(Err(format!("Couldn't open the audio output: {}", e)));
+                    return;
+                }
+            };
+            let sink = rodio::Sink::connect_new(stream.mixer());
+            loop {
+                if sounding.load(Ordering::Relaxed) {
+                    sink.append(SineWave::new(FREQUENCY).take_duration(LENGTH).amplify(0.3));
+                }
+                std::thread::sleep(PERIOD);
+            }
+        });
+        result.recv().map_err(|e| e.to_string())?
+    }
+}
@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::time::{Duration, Instant};
+
+use super::{Sink, SinkError};
+use crate::backoff::Backoff;
+use crate::output::{EventRecord, Record, WaveformRecord};
+
+/// Spooled readings sent per new reading while catching up, so a long outage is caught up on in
+/// minutes without holding up the BLE loop for long.
+const REPLAY_BATCH: usize = 60;
+
+/// Most time between attempts to reach a sink that's down.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Wraps a sink that sends over the network. While it can't be reached, readings are appended to
+/// a JSON lines file instead, and sent on in order once it's back, so a Wi-Fi blip overnight
+/// doesn't leave a hole in the data. Readings left over from the last run are sent too.
+///
+/// Only readings are spooled, waveform samples and events are passed straight through.
+pub struct Spooled<S> {
+    sink: S,
+    path: PathBuf,
+    /// Whether there are readings in the spool that haven't been sent.
+    spooling: bool,
+    /// Where in the spool the readings that haven't been sent start.
+    sent: u64,
+    backoff: Backoff,
+    retry_at: Option<Instant>,
+}
+
+impl<S: Sink> Spooled<S> {
+    /// The spool is a file in `dir` named after the sink.
+    pub fn new(sink: S, dir: &Path) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(dir).map_err(|e| format!("Couldn't create {:?}: {}", dir, e))?;
+        let path = dir.join(format!("{}.jsonl", file_name(&sink.name())));
+        let spooling = fs::metadata(&path).is_ok_and(|metadata| metadata.len() > 0);
+        if spooling {
+            info!("Will send the readings left in {:?} to {}", path, sink.name());
+        }
+        Ok(Spooled {
+            sink,
+            path,
+            spooling,
+            sent: 0,
+            backoff: Backoff::new(Duration::from_secs(1), MAX_RETRY_DELAY, None),
+            retry_at: None,
+        })
+    }
+
+    fn spool(&self, records: &[Record]) -> Result<(), SinkError> {
+        let mut file = BufWriter::new(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        for record in records {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Sends the next batch of spooled readings, and returns whether that was the last of them.
+    async fn replay(&mut self) -> Result<bool, SinkError> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.sent))?;
+        let mut reader = BufReader::new(file);
+        let mut batch = Vec::with_capacity(REPLAY_BATCH);
+        let mut length = 0;
+        let mut line = String::new();
+        let mut done = false;
+        while batch.len() < REPLAY_BATCH {
+            line.clear();
+            let line_length = reader.read_line(&mut line)?;
+            if line_length == 0 {
+                done = true;
+                break;
+            }
+            length += line_length as u64;
+            match serde_json::from_str(&line) {
+                Ok(record) => batch.push(record),
+                // Most likely cut short by a crash.
+                Err(e) => warn!("Skipping unreadable line in {:?}: {}", self.path, e),
+            }
+        }
+        if !batch.is_empty() {
+            self.sink.readings(&batch).await?;
+        }
+        if done {
+            fs::remove_file(&self.path)?;
+            self.sent = 0;
+            return Ok(true);
+        }
+        self.sent += length;
+        Ok(false)
+    }
+
+    fn retry_later(&mut self) {
+        let delay = self.backoff.next_delay().unwrap_or(MAX_RETRY_DELAY);
+        self.retry_at = Some(Instant::now() + delay);
+    }
+
+    /// Drops the readings that have been sent from the start of the spool, so they aren't sent
+    /// again next time.
+    fn compact(&mut self) -> io::Result<()> {
+        if self.sent == 0 {
+            return Ok(());
+        }
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.sent))?;
+        let compacted = self.path.with_extension("jsonl.tmp");
+        io::copy(&mut file, &mut File::create(&compacted)?)?;
+        fs::rename(&compacted, &self.path)?;
+        self.sent = 0;
+        Ok(())
+    }
+}
+
+/// Lowercase letters and digits from `name`, with dashes in between words.
+fn file_name(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[async_trait]
+impl<S: Sink> Sink for Spooled<S> {
+    fn name(&self) -> String {
+        self.sink.name()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        self.readings(slice::from_ref(record)).await
+    }
+
+    async fn readings(&mut self, records: &[Record]) -> Result<(), SinkError> {
+        if !self.spooling {
+            let Err(e) = self.sink.readings(records).await else {
+                return Ok(());
+            };
+            warn!(
+                "Couldn't write reading to {}, keeping readings in {:?} until it's back: {}",
+                self.sink.name(),
+                self.path,
+                e
+            );
+            self.spooling = true;
+            self.retry_later();
+            return self.spool(records);
+        }
+
+        // Behind the ones already spooled, to keep them in order.
+        self.spool(records)?;
+        if self.retry_at.is_some_and(|retry_at| Instant::now() < retry_at) {
+            return Ok(());
+        }
+        match self.replay().await {
+            Ok(done) => {
+                self.backoff.reset();
+                self.retry_at = None;
+                if done {
+                    info!("{} is back, and has been sent every spooled reading", self.sink.name());
+                    self.spooling = false;
+                }
+            }
+            Err(e) => {
+                debug!("{} is still unreachable: {}", self.sink.name(), e);
+                self.retry_later();
+            }
+        }
+        Ok(())
+    }
+
+    async fn waveform(&mut self, record: &WaveformRecord) -> Result<(), SinkError> {
+        self.sink.waveform(record).await
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        self.sink.event(record).await
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        if self.spooling {
+            self.compact()?;
+            info!("Readings that couldn't be sent to {} are kept in {:?}", self.sink.name(), self.path);
+        }
+        self.sink.close().await
+    }
+}
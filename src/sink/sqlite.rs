@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::path::Path;
+
+use super::{Sink, SinkError};
+use crate::output::Record;
+
+/// Schema changes, applied in order. `PRAGMA user_version` holds how many have been applied, so
+/// append new migrations instead of changing old ones.
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE sessions (
+        id INTEGER PRIMARY KEY,
+        started_at TEXT NOT NULL,
+        ended_at TEXT,
+        program_version TEXT NOT NULL
+    );
+    CREATE TABLE readings (
+        session_id INTEGER NOT NULL REFERENCES sessions (id),
+        time TEXT NOT NULL,
+        spo2 INTEGER,
+        heartrate INTEGER,
+        pi REAL,
+        battery INTEGER,
+        status TEXT NOT NULL,
+        signal INTEGER NOT NULL,
+        quality TEXT NOT NULL
+    );
+    CREATE INDEX readings_time ON readings (time);
+", "
+    ALTER TABLE readings ADD COLUMN device TEXT;
+"];
+
+/// Stores readings in a SQLite database. Every run of the program is a row in `sessions`.
+pub struct SqliteSink {
+    connection: Connection,
+    session_id: i64,
+    path: String,
+}
+
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        debug!("Applying SQLite migration {}", i + 1);
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", i + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+impl SqliteSink {
+    pub fn new(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut connection)?;
+        connection.execute(
+            "INSERT INTO sessions (started_at, program_version) VALUES (?1, ?2)",
+            params![Utc::now().to_rfc3339(), env!("CARGO_PKG_VERSION")],
+        )?;
+        let session_id = connection.last_insert_rowid();
+        Ok(SqliteSink {
+            connection,
+            session_id,
+            path: path.display().to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for SqliteSink {
+    fn name(&self) -> String {
+        format!("SQLite database {}", self.path)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO readings (session_id, time, spo2, heartrate, pi, battery, status, signal, quality, device)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?
+            .execute(params![
+                self.session_id,
+                record.time.to_rfc3339(),
+                record.spo2,
+                record.heartrate,
+                record.pi,
+                record.battery,
+                record.status.to_string(),
+                record.signal,
+                record.quality.to_string(),
+                record.device,
+            ])?;
+        Ok(())
+    }
+}
+
+impl Drop for SqliteSink {
+    fn drop(&mut self) {
+        let result = self.connection.execute(
+            "UPDATE sessions SET ended_at = ?1 WHERE id = ?2",
+            params![Utc::now().to_rfc3339(), self.session_id],
+        );
+        if let Err(e) = result {
+            warn!("Couldn't record the end of the session in {}: {}", self.path, e);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use async_trait::async_trait;
+use std::io;
+use tokio::net::UdpSocket;
+
+use super::{metric_segment, Sink, SinkError};
+use crate::output::Record;
+
+/// Sends each reading to a StatsD server as gauges, e.g. `oximeter.AA_BB_CC_DD_EE_FF.spo2:97|g`.
+pub struct StatsdSink {
+    socket: UdpSocket,
+    address: String,
+    prefix: String,
+}
+
+impl StatsdSink {
+    pub async fn new(address: &str, prefix: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(address).await?;
+        Ok(StatsdSink {
+            socket,
+            address: address.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for StatsdSink {
+    fn name(&self) -> String {
+        format!("StatsD {}", self.address)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let values = [
+            ("spo2", record.spo2.map(|v| v.to_string())),
+            ("heartrate", record.heartrate.map(|v| v.to_string())),
+            ("pi", record.pi.map(|v| format!("{:.1}", v))),
+            ("battery", record.battery.map(|v| v.to_string())),
+            ("signal", Some(record.signal.to_string())),
+        ];
+        // All gauges go in one packet, which every common StatsD server accepts.
+        let device = metric_segment(&record.device);
+        let packet: Vec<String> = values
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{}.{}.{}:{}|g", self.prefix, device, name, value?)))
+            .collect();
+        self.socket.send(packet.join("\n").as_bytes()).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+use super::{Sink, SinkError};
+use crate::output::Record;
+
+/// Sends each reading as a JSON datagram. Broadcast addresses work too, so anything on the LAN
+/// can listen in.
+pub struct UdpSink {
+    socket: UdpSocket,
+    address: SocketAddr,
+}
+
+impl UdpSink {
+    pub async fn new(address: SocketAddr) -> io::Result<Self> {
+        let bind_address: SocketAddr = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let socket = UdpSocket::bind(bind_address).await?;
+        socket.set_broadcast(true)?;
+        Ok(UdpSink { socket, address })
+    }
+}
+
+#[async_trait]
+impl Sink for UdpSink {
+    fn name(&self) -> String {
+        format!("UDP {}", self.address)
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        self.socket.send_to(&serde_json::to_vec(record)?, self.address).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+//! Pulling ATT notifications out of Bluetooth captures for the `decode` subcommand, so field
+//! reports can be diagnosed from a capture of the phone or computer the device was used with.
+//!
+//! Understood are Android's and BlueZ's btsnoop HCI logs, and pcap or pcapng files from Wireshark
+//! with HCI packets or, e.g. from an nRF Sniffer, unencrypted link-layer packets.
+
+use btleplug::api::bleuuid::uuid_from_u16;
+use chrono::{DateTime, SecondsFormat, Utc};
+use pc60fw_protocol::ParserStats;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::cli::Args;
+
+const BTSNOOP_MAGIC: &[u8] = b"btsnoop\0";
+/// Microseconds from year 0, which btsnoop timestamps count from, to the Unix epoch.
+const BTSNOOP_EPOCH_OFFSET: i64 = 0x00dc_ddb3_0f2f_8000;
+const BTSNOOP_H4: u32 = 1001;
+const BTSNOOP_HCI: u32 = 1002;
+/// btsnoop record flag bits.
+const BTSNOOP_RECEIVED: u32 = 0x01;
+const BTSNOOP_COMMAND_OR_EVENT: u32 = 0x02;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+
+/// pcap link types.
+const LINKTYPE_BLUETOOTH_HCI_H4: u32 = 187;
+const LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR: u32 = 201;
+const LINKTYPE_BLUETOOTH_LE_LL: u32 = 251;
+const LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR: u32 = 256;
+
+const H4_ACL: u8 = 0x02;
+/// Access address of advertising packets, which carry no ATT.
+const ADVERTISING_ACCESS_ADDRESS: u32 = 0x8e89_bed6;
+const LL_CRC_LEN: usize = 3;
+/// Link layer data PDU LLIDs.
+const LLID_CONTINUATION: u8 = 0x01;
+const LLID_START: u8 = 0x02;
+
+const L2CAP_ATT: u16 = 0x0004;
+const ATT_READ_BY_TYPE_RESPONSE: u8 = 0x09;
+const ATT_HANDLE_VALUE_NOTIFICATION: u8 = 0x1b;
+const ATT_HANDLE_VALUE_INDICATION: u8 = 0x1d;
+
+/// A notification or indication found in a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttNotification {
+    pub time: DateTime<Utc>,
+    /// Attribute handle of the characteristic's value.
+    pub handle: u16,
+    pub value: Vec<u8>,
+}
+
+/// What could be found in a capture.
+#[derive(Debug, Default)]
+pub struct Capture {
+    pub notifications: Vec<AttNotification>,
+    /// The characteristics the capture saw being discovered, by value handle. Empty if the
+    /// capture started after discovery, or the host had the handles cached.
+    pub characteristics: HashMap<u16, Uuid>,
+}
+
+/// Reads a capture in any of the supported formats.
+pub fn read(bytes: &[u8]) -> Result<Capture, String> {
+    let mut capture = Capture::default();
+    let mut reassembler = Reassembler::default();
+    if bytes.starts_with(BTSNOOP_MAGIC) {
+        read_btsnoop(bytes, &mut |time, packet| reassembler.packet(time, packet, &mut capture))?;
+    } else if bytes.len() >= 4 && u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) == PCAPNG_SECTION_HEADER {
+        read_pcapng(bytes, &mut |time, packet| reassembler.packet(time, packet, &mut capture))?;
+    } else {
+        read_pcap(bytes, &mut |time, packet| reassembler.packet(time, packet, &mut capture))?;
+    }
+    Ok(capture)
+}
+
+/// A packet from a capture, before the L2CAP frames in it are put back together.
+enum Packet<'a> {
+    /// An HCI ACL data packet, starting with its handle.
+    Acl { received: bool, data: &'a [u8] },
+    /// A link layer packet, starting with its access address and without its CRC.
+    LinkLayer(&'a [u8]),
+}
+
+/// Bounds-checked reads of the fields of a capture.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err("Capture is truncated".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(if self.big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) })
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        let b = [b[0], b[1], b[2], b[3]];
+        Ok(if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+fn read_btsnoop(bytes: &[u8], on_packet: &mut impl FnMut(DateTime<Utc>, Packet)) -> Result<(), String> {
+    let mut reader = Reader { bytes: &bytes[BTSNOOP_MAGIC.len()..], big_endian: true };
+    let _version = reader.u32()?;
+    let datalink = reader.u32()?;
+    if datalink != BTSNOOP_H4 && datalink != BTSNOOP_HCI {
+        return Err(format!("Unsupported btsnoop datalink type {}", datalink));
+    }
+    while !reader.is_empty() {
+        let _original_len = reader.u32()?;
+        let included_len = reader.u32()? as usize;
+        let flags = reader.u32()?;
+        let _drops = reader.u32()?;
+        let timestamp = i64::from(reader.u32()?) << 32 | i64::from(reader.u32()?);
+        let data = reader.take(included_len)?;
+        let time = DateTime::from_timestamp_micros(timestamp - BTSNOOP_EPOCH_OFFSET).unwrap_or_default();
+        let received = flags & BTSNOOP_RECEIVED != 0;
+        let acl = match datalink {
+            BTSNOOP_H4 => data.split_first().filter(|(&kind, _)| kind == H4_ACL).map(|(_, data)| data),
+            _ => Some(data).filter(|_| flags & BTSNOOP_COMMAND_OR_EVENT == 0),
+        };
+        if let Some(data) = acl {
+            on_packet(time, Packet::Acl { received, data });
+        }
+    }
+    Ok(())
+}
+
+/// Turns a packet of the given pcap link type into a [`Packet`], if it can hold ATT.
+fn pcap_packet(link_type: u32, data: &[u8]) -> Result<Option<Packet<'_>>, String> {
+    let packet = match link_type {
+        LINKTYPE_BLUETOOTH_HCI_H4 => match data.split_first() {
+            Some((&H4_ACL, data)) => Some(Packet::Acl { received: true, data }),
+            _ => None,
+        },
+        LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR => {
+            // A big-endian direction, 1 for received, then the H4 packet.
+            let mut reader = Reader { bytes: data, big_endian: true };
+            let received = reader.u32()? & 1 != 0;
+            match reader.bytes.split_first() {
+                Some((&H4_ACL, data)) => Some(Packet::Acl { received, data }),
+                _ => None,
+            }
+        }
+        LINKTYPE_BLUETOOTH_LE_LL => Some(Packet::LinkLayer(&data[..data.len().saturating_sub(LL_CRC_LEN)])),
+        LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR => {
+            // Channel, signal, noise, access address offenses, reference access address, flags.
+            let data = data.get(10..).ok_or("Capture is truncated")?;
+            Some(Packet::LinkLayer(&data[..data.len().saturating_sub(LL_CRC_LEN)]))
+        }
+        _ => return Err(format!("Unsupported link type {}, only HCI and BLE link layer captures are", link_type)),
+    };
+    Ok(packet)
+}
+
+fn read_pcap(bytes: &[u8], on_packet: &mut impl FnMut(DateTime<Utc>, Packet)) -> Result<(), String> {
+    let magic = bytes.get(..4).ok_or("Not a btsnoop, pcap or pcapng capture")?;
+    let (big_endian, nanoseconds) = match magic {
+        [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+        [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+        _ => return Err("Not a btsnoop, pcap or pcapng capture".to_string()),
+    };
+    let mut reader = Reader { bytes: &bytes[4..], big_endian };
+    reader.take(16)?;
+    let link_type = reader.u32()?;
+    while !reader.is_empty() {
+        let seconds = reader.u32()?;
+        let fraction = reader.u32()?;
+        let included_len = reader.u32()? as usize;
+        let _original_len = reader.u32()?;
+        let data = reader.take(included_len)?;
+        let nanos = if nanoseconds { fraction } else { fraction.saturating_mul(1000) };
+        let time = DateTime::from_timestamp(seconds.into(), nanos).unwrap_or_default();
+        if let Some(packet) = pcap_packet(link_type, data)? {
+            on_packet(time, packet);
+        }
+    }
+    Ok(())
+}
+
+fn read_pcapng(bytes: &[u8], on_packet: &mut impl FnMut(DateTime<Utc>, Packet)) -> Result<(), String> {
+    // Link type and timestamp units per second of each interface in the current section.
+    let mut interfaces: Vec<(u32, u64)> = Vec::new();
+    let mut big_endian = false;
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        if rest.len() >= 12 && rest[..4] == PCAPNG_SECTION_HEADER.to_le_bytes() {
+            let magic = [rest[8], rest[9], rest[10], rest[11]];
+            big_endian = u32::from_be_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC;
+            interfaces.clear();
+        }
+        let mut reader = Reader { bytes: rest, big_endian };
+        let block_type = reader.u32()?;
+        let block_len = reader.u32()? as usize;
+        if block_len < 12 || block_len > rest.len() {
+            return Err("Capture is truncated".to_string());
+        }
+        let mut body = Reader { bytes: &rest[8..block_len - 4], big_endian };
+        rest = &rest[block_len..];
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let link_type = u32::from(body.u16()?);
+                body.take(6)?;
+                let mut units_per_second = 1_000_000;
+                while body.bytes.len() >= 4 {
+                    let code = body.u16()?;
+                    let len = body.u16()? as usize;
+                    let value = body.take(len.next_multiple_of(4).min(body.bytes.len()))?;
+                    match (code, value.first()) {
+                        (0, _) => break,
+                        (PCAPNG_OPTION_TSRESOL, Some(&resolution)) if resolution & 0x80 == 0 => {
+                            units_per_second = 10u64.saturating_pow(resolution.into());
+                        }
+                        (PCAPNG_OPTION_TSRESOL, Some(&resolution)) => {
+                            units_per_second = 1u64.checked_shl((resolution & 0x7f).into()).unwrap_or(u64::MAX);
+                        }
+                        _ => {}
+                    }
+                }
+                interfaces.push((link_type, units_per_second));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let interface = body.u32()? as usize;
+                let timestamp = u64::from(body.u32()?) << 32 | u64::from(body.u32()?);
+                let included_len = body.u32()? as usize;
+                let _original_len = body.u32()?;
+                let data = body.take(included_len)?;
+                let &(link_type, units_per_second) =
+                    interfaces.get(interface).ok_or("Packet from an undescribed interface")?;
+                let seconds = timestamp / units_per_second;
+                let nanos = (timestamp % units_per_second) as u128 * 1_000_000_000 / u128::from(units_per_second);
+                let time = DateTime::from_timestamp(seconds as i64, nanos as u32).unwrap_or_default();
+                if let Some(packet) = pcap_packet(link_type, data)? {
+                    on_packet(time, packet);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Puts L2CAP frames split over several packets back together, and picks the ATT PDUs out of
+/// them.
+#[derive(Default)]
+struct Reassembler {
+    /// Partial frames by connection: the ACL handle and direction, or the access address.
+    partial: HashMap<(u32, bool), Vec<u8>>,
+}
+
+impl Reassembler {
+    fn packet(&mut self, time: DateTime<Utc>, packet: Packet, capture: &mut Capture) {
+        let (connection, start, fragment) = match packet {
+            Packet::Acl { received, data } => {
+                if data.len() < 4 {
+                    return;
+                }
+                let handle_and_flags = u16::from_le_bytes([data[0], data[1]]);
+                let len = usize::from(u16::from_le_bytes([data[2], data[3]]));
+                let fragment = &data[4..data.len().min(4 + len)];
+                let start = (handle_and_flags >> 12) & 0x3 != 0x1;
+                ((u32::from(handle_and_flags & 0x0fff), received), start, fragment)
+            }
+            Packet::LinkLayer(data) => {
+                if data.len() < 6 {
+                    return;
+                }
+                let access_address = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+                let llid = data[4] & 0x03;
+                if access_address == ADVERTISING_ACCESS_ADDRESS || !matches!(llid, LLID_START | LLID_CONTINUATION) {
+                    return;
+                }
+                let len = usize::from(data[5]);
+                let fragment = &data[6..data.len().min(6 + len)];
+                if fragment.is_empty() {
+                    return;
+                }
+                ((access_address, true), llid == LLID_START, fragment)
+            }
+        };
+
+        let buffer = self.partial.entry(connection).or_default();
+        if start {
+            buffer.clear();
+        } else if buffer.is_empty() {
+            // The start of this frame was before the capture began.
+            return;
+        }
+        buffer.extend_from_slice(fragment);
+        if buffer.len() < 4 {
+            return;
+        }
+        let len = usize::from(u16::from_le_bytes([buffer[0], buffer[1]]));
+        let channel = u16::from_le_bytes([buffer[2], buffer[3]]);
+        if buffer.len() < 4 + len {
+            return;
+        }
+        let frame = std::mem::take(buffer);
+        if channel == L2CAP_ATT {
+            att(time, &frame[4..4 + len], capture);
+        }
+    }
+}
+
+/// Picks notifications and discovered characteristics out of an ATT PDU.
+fn att(time: DateTime<Utc>, pdu: &[u8], capture: &mut Capture) {
+    match pdu {
+        [ATT_HANDLE_VALUE_NOTIFICATION | ATT_HANDLE_VALUE_INDICATION, handle_low, handle_high, value @ ..] => {
+            capture.notifications.push(AttNotification {
+                time,
+                handle: u16::from_le_bytes([*handle_low, *handle_high]),
+                value: value.to_vec(),
+            });
+        }
+        // Characteristic declarations: their handle, properties, value handle and UUID.
+        [ATT_READ_BY_TYPE_RESPONSE, len @ (7 | 21), entries @ ..] => {
+            for entry in entries.chunks_exact(usize::from(*len)) {
+                let value_handle = u16::from_le_bytes([entry[3], entry[4]]);
+                let uuid = match &entry[5..] {
+                    [low, high] => uuid_from_u16(u16::from_le_bytes([*low, *high])),
+                    uuid => {
+                        let mut bytes = [0; 16];
+                        bytes.copy_from_slice(uuid);
+                        bytes.reverse();
+                        Uuid::from_bytes(bytes)
+                    }
+                };
+                capture.characteristics.insert(value_handle, uuid);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Runs the notifications of the device's RX characteristic in the capture at `path` through the
+/// decoder of `--protocol`, and prints what's in them. `handle` picks the characteristic by its
+/// attribute handle, for captures that don't show it being discovered.
+pub fn decode(args: &Args, path: &Path, handle: Option<u16>) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("Couldn't read capture {:?}: {}", path, e))?;
+    let capture = read(&bytes).map_err(|e| format!("Couldn't read capture {:?}: {}", path, e))?;
+    let rx = args.protocol.rx_characteristics(args);
+    let handles: Vec<u16> = match handle {
+        Some(handle) => vec![handle],
+        None => capture
+            .characteristics
+            .iter()
+            .filter(|(_, uuid)| rx.contains(uuid))
+            .map(|(&handle, _)| handle)
+            .collect(),
+    };
+    if handles.is_empty() {
+        warn!("The capture doesn't show which handle the characteristic has, decoding every notification; pass --handle to pick one");
+    }
+
+    let mut decoder = args.protocol.decoder(args);
+    let mut notifications = 0;
+    for notification in &capture.notifications {
+        if !handles.is_empty() && !handles.contains(&notification.handle) {
+            continue;
+        }
+        notifications += 1;
+        let characteristic = capture.characteristics.get(&notification.handle).or(rx.first()).copied().unwrap_or_default();
+        for message in decoder.decode(characteristic, &notification.value) {
+            println!("{} {:?}", notification.time.to_rfc3339_opts(SecondsFormat::Micros, true), message);
+        }
+    }
+    let ParserStats { frames, checksum_errors, discarded_bytes } = decoder.stats();
+    println!(
+        "{} notifications, {} frames, {} bad checksums, {} bytes discarded",
+        notifications, frames, checksum_errors, discarded_bytes
+    );
+    Ok(())
+}
@@ -0,0 +1,620 @@
+//! Reading from oximeters over Bluetooth LE, the way this is normally used.
+
+use async_trait::async_trait;
+use btleplug::api::{Central, CentralEvent, CharPropFlags, Characteristic, Manager as _, Peripheral as _, ScanFilter, ValueNotification, WriteType};
+use btleplug::platform::{Adapter, Manager, Peripheral, PeripheralId};
+use chrono::Utc;
+use futures::StreamExt;
+use pc60fw_protocol::{DeviceInfo, Message};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time;
+use tracing::Instrument;
+
+use super::{send, DataSource, Event};
+use crate::backoff::Backoff;
+use crate::capture::Notification;
+use crate::cli::Args;
+use crate::filter;
+use crate::pairing;
+use crate::protocol::{Decoder, Protocol};
+use crate::stats::Stats;
+
+/// Runs a BLE operation, giving up after `--ble-timeout`, since some adapters never complete
+/// them.
+async fn ble<T, E: Into<Box<dyn Error>>>(
+    args: &Args,
+    operation: &str,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, Box<dyn Error>> {
+    match time::timeout(args.ble_timeout, future).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(format!("Timed out {} after {:?}", operation, args.ble_timeout).into()),
+    }
+}
+
+/// How long to listen to a device that could speak several protocols to tell which it does.
+const DETECT_TIME: Duration = Duration::from_secs(5);
+
+/// A connected device, and the characteristics used to talk to it.
+#[derive(Clone)]
+struct Device {
+    adapter: Adapter,
+    peripheral: Peripheral,
+    protocol: Protocol,
+    /// Where readings arrive, by notification or indication.
+    rx: Vec<Characteristic>,
+    /// Where commands are written to. Missing on some devices, which just stream on their own.
+    tx: Option<Characteristic>,
+}
+
+/// Peripherals that already have a connection, so another one doesn't pick them too.
+type Claimed = Mutex<HashSet<PeripheralId>>;
+
+/// Connects to up to `--max-devices` devices, and reconnects to them whenever they're lost.
+pub struct BleSource<'a> {
+    args: &'a Args,
+    manager: Manager,
+    stats: Arc<Stats>,
+    claimed: Claimed,
+    /// Devices with a session running, to disconnect from when stopping.
+    sessions: Mutex<HashMap<PeripheralId, Device>>,
+    /// Only one connection looks for a device at a time, as adapters don't like overlapping
+    /// scans.
+    scanning: tokio::sync::Mutex<()>,
+}
+
+impl<'a> BleSource<'a> {
+    pub fn new(args: &'a Args, manager: Manager, stats: Arc<Stats>) -> Self {
+        BleSource {
+            args,
+            manager,
+            stats,
+            claimed: Claimed::default(),
+            sessions: Mutex::default(),
+            scanning: tokio::sync::Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl DataSource for BleSource<'_> {
+    async fn run(&mut self, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+        // Each device gets its own connection, which reconnects on its own.
+        let this = &*self;
+        let connections = (0..this.args.max_devices)
+            .map(|slot| this.keep_connected(events).instrument(info_span!("connection", slot)));
+        futures::future::try_join_all(connections).await?;
+        Ok(())
+    }
+
+    /// Unsubscribes and disconnects, so the devices are free for something else straight away.
+    async fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        let devices: Vec<Device> = self.sessions.lock().unwrap().drain().map(|(_, device)| device).collect();
+        for device in devices {
+            let address = device.peripheral.address();
+            info!("Disconnecting from {}...", address);
+            if let Err(e) = unsubscribe(self.args, &device).await {
+                debug!("Couldn't unsubscribe from {}: {}", address, e);
+            }
+            if let Err(e) = ble(self.args, "disconnecting", device.peripheral.disconnect()).await {
+                warn!("Couldn't disconnect from {}: {}", address, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scans for a matching device and connects to it. Returns `None` if none turns up within
+/// `--scan-time`.
+async fn find_device(manager: &Manager, args: &Args, claimed: &Claimed) -> Result<Option<Device>, Box<dyn Error>> {
+    let mut adapter_list = ble(args, "listing adapters", manager.adapters()).await?;
+    if adapter_list.is_empty() {
+        error!("No Bluetooth adapters found");
+        return Err("No adapters found".into());
+    }
+    if let Some(selector) = &args.adapter {
+        let mut selected = Vec::new();
+        let mut available = Vec::new();
+        for (index, adapter) in adapter_list.into_iter().enumerate() {
+            let info = ble(args, "getting adapter info", adapter.adapter_info()).await?;
+            if filter::matches_adapter(selector, index, &info) {
+                debug!("Using adapter {}: {}", index, info);
+                selected.push(adapter);
+            } else {
+                available.push(format!("{}: {}", index, info));
+            }
+        }
+        if selected.is_empty() {
+            return Err(format!("No adapter matches {:?}, available adapters are {}", selector, available.join(", ")).into());
+        }
+        adapter_list = selected;
+    }
+    let name_filter = &args.name_filter();
+
+    // Scan on every adapter at once, so a second adapter doesn't add to the wait.
+    info!("Starting scan...");
+    let mut scanning = Vec::new();
+    let mut events = Vec::new();
+    let scan_filter = ScanFilter {
+        services: match &args.scan_services[..] {
+            _ if args.no_scan_filter => Vec::new(),
+            [] => args.protocol.services(),
+            services => services.to_vec(),
+        },
+    };
+    for adapter in adapter_list {
+        // Listen before scanning, so no discoveries are missed.
+        let scan = async {
+            let adapter_events = adapter.events().await?;
+            adapter.start_scan(scan_filter.clone()).await?;
+            Ok::<_, btleplug::Error>(adapter_events)
+        };
+        match ble(args, "starting scan", scan).await {
+            Ok(adapter_events) => {
+                let index = scanning.len();
+                events.push(adapter_events.map(move |event| (index, event)));
+                scanning.push(adapter);
+            }
+            Err(err) => warn!("Couldn't scan with an adapter, skipping it: {}", err),
+        }
+    }
+    if scanning.is_empty() {
+        return Err("Couldn't scan with any adapter".into());
+    }
+    let mut events = futures::stream::select_all(events);
+
+    let found = async {
+        // Connect as soon as a matching device shows up. Updates come in whenever a device
+        // advertises, and its name may only arrive with a later one, so those are checked too.
+        let mut tried = HashSet::new();
+        let mut known = Vec::new();
+        for (index, adapter) in scanning.iter().enumerate() {
+            // Peripherals the adapter already knew about, e.g. ones that are still connected,
+            // won't necessarily be discovered again.
+            for peripheral in ble(args, "listing peripherals", adapter.peripherals()).await? {
+                known.push((index, peripheral.id()));
+            }
+        }
+        let mut known = futures::stream::iter(known);
+        loop {
+            let (index, id) = tokio::select! {
+                Some(candidate) = known.next() => candidate,
+                Some((index, event)) = events.next() => match event {
+                    CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => (index, id),
+                    _ => continue,
+                },
+                else => return Err("Adapters stopped sending events".into()),
+            };
+            if tried.contains(&id) {
+                continue;
+            }
+            let adapter = &scanning[index];
+            let Ok(peripheral) = adapter.peripheral(&id).await else {
+                continue;
+            };
+            let Some(local_name) = matching_name(args, &peripheral, name_filter, claimed).await? else {
+                continue;
+            };
+            // Don't keep trying the same device if it won't connect, that's for the next scan.
+            tried.insert(id);
+            if let Some(device) = try_connect(args, adapter, &peripheral, &local_name, claimed).await? {
+                return Ok::<_, Box<dyn Error>>(device);
+            }
+        }
+    };
+    let result = time::timeout(args.scan_time, found).await;
+    for adapter in &scanning {
+        if let Err(err) = ble(args, "stopping scan", adapter.stop_scan()).await {
+            debug!("Couldn't stop scanning: {}", err);
+        }
+    }
+    match result {
+        Ok(result) => result.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the name of `peripheral` if it's one we're looking for and nobody else has it.
+async fn matching_name(
+    args: &Args,
+    peripheral: &Peripheral,
+    name_filter: &filter::NameFilter,
+    claimed: &Claimed,
+) -> Result<Option<String>, Box<dyn Error>> {
+    if claimed.lock().unwrap().contains(&peripheral.id()) {
+        return Ok(None);
+    }
+    let Some(properties) = ble(args, "getting peripheral properties", peripheral.properties()).await? else {
+        return Ok(None);
+    };
+    let local_name = properties
+        .local_name
+        .unwrap_or_else(|| properties.address.to_string());
+    // Check if it's the peripheral we want.
+    let is_match = match &args.address {
+        Some(address) => filter::matches_address(address, &properties.address, &peripheral.id()),
+        None => name_filter.matches(&local_name),
+    };
+    Ok(is_match.then_some(local_name))
+}
+
+/// Connects to a matching peripheral and sets it up for reading.
+async fn try_connect(
+    args: &Args,
+    adapter: &Adapter,
+    peripheral: &Peripheral,
+    local_name: &str,
+    claimed: &Claimed,
+) -> Result<Option<Device>, Box<dyn Error>> {
+    info!("Found matching peripheral {:?}...", local_name);
+    let is_connected = ble(args, "checking connection", peripheral.is_connected()).await?;
+    if !is_connected {
+        // Connect if we aren't already connected.
+        if let Err(err) = ble(args, "connecting", peripheral.connect()).await {
+            error!("Error connecting to peripheral, skipping: {}", err);
+            return Ok(None);
+        }
+    }
+    let is_connected = ble(args, "checking connection", peripheral.is_connected()).await?;
+    info!("Now connected ({:?}) to peripheral {:?}.", is_connected, &local_name);
+    if !is_connected {
+        error!("Couldn't connect to peripheral, skipping {:?}.", &local_name);
+        return Ok(None);
+    }
+
+    if args.pair {
+        if let Err(err) = pairing::pair(peripheral).await {
+            error!("Error pairing, skipping {:?}: {}", local_name, err);
+            let _ = ble(args, "disconnecting", peripheral.disconnect()).await;
+            return Ok(None);
+        }
+    }
+
+    debug!("Discover peripheral {:?} services...", local_name);
+    if let Err(err) = ble(args, "discovering services", peripheral.discover_services()).await {
+        error!("Error discovering services, skipping {:?}: {}", &local_name, err);
+        // Don't leave a half-set-up connection behind.
+        let _ = ble(args, "disconnecting", peripheral.disconnect()).await;
+        return Ok(None);
+    }
+    let device = match args.protocol {
+        Protocol::Auto => match detect_protocol(args, adapter, peripheral, local_name).await {
+            Ok(device) => device,
+            Err(err) => {
+                error!("Error detecting the protocol, skipping {:?}: {}", local_name, err);
+                let _ = ble(args, "disconnecting", peripheral.disconnect()).await;
+                return Ok(None);
+            }
+        },
+        protocol => device_with(adapter, peripheral, args, protocol),
+    };
+    let Some(device) = device else {
+        error!("Couldn't find characteristic, skipping {:?}.", &local_name);
+        return Ok(None);
+    };
+    claimed.lock().unwrap().insert(peripheral.id());
+    Ok(Some(device))
+}
+
+/// The device, if it has the characteristics to speak `protocol`.
+fn device_with(adapter: &Adapter, peripheral: &Peripheral, args: &Args, protocol: Protocol) -> Option<Device> {
+    let characteristics = peripheral.characteristics();
+    let rx_uuids = protocol.rx_characteristics(args);
+    let rx: Vec<Characteristic> = characteristics
+        .iter()
+        .filter(|c| {
+            rx_uuids.contains(&c.uuid) && c.properties.intersects(CharPropFlags::NOTIFY | CharPropFlags::INDICATE)
+        })
+        .cloned()
+        .collect();
+    if rx.is_empty() {
+        return None;
+    }
+    let tx = characteristics.iter().find(|c| {
+        Some(c.uuid) == protocol.tx_characteristic(args)
+            && c.properties.intersects(CharPropFlags::WRITE | CharPropFlags::WRITE_WITHOUT_RESPONSE)
+    });
+    Some(Device {
+        adapter: adapter.to_owned(),
+        peripheral: peripheral.to_owned(),
+        protocol,
+        rx,
+        tx: tx.cloned(),
+    })
+}
+
+/// Works out which protocol the device speaks for `--protocol auto`: the only one it has the
+/// characteristics for, or if there are several, the first to make sense of what it sends once
+/// asked to start.
+async fn detect_protocol(
+    args: &Args,
+    adapter: &Adapter,
+    peripheral: &Peripheral,
+    local_name: &str,
+) -> Result<Option<Device>, Box<dyn Error>> {
+    let mut candidates: Vec<Device> = Protocol::KNOWN
+        .iter()
+        .filter_map(|&protocol| device_with(adapter, peripheral, args, protocol))
+        .collect();
+    if candidates.len() <= 1 {
+        return Ok(candidates.pop());
+    }
+    let protocols: Vec<Protocol> = candidates.iter().map(|device| device.protocol).collect();
+    info!("{:?} could speak any of {:?}, listening to find out which...", local_name, protocols);
+    let mut notification_stream = ble(args, "getting notifications", peripheral.notifications()).await?;
+    for device in &candidates {
+        subscribe(args, device).await?;
+        send_start_commands(args, device).await?;
+        if let Some((_, command)) = device.protocol.poll(args) {
+            send_commands(args, device, &[command]).await?;
+        }
+    }
+    let mut decoder = Decoder::detect(args, &protocols);
+    let _ = time::timeout(DETECT_TIME, async {
+        while let Some(ValueNotification { uuid, value }) = notification_stream.next().await {
+            decoder.decode(uuid, &value);
+            if decoder.protocol().is_some() {
+                break;
+            }
+        }
+    })
+    .await;
+    for device in &candidates {
+        unsubscribe(args, device).await?;
+    }
+    let Some(protocol) = decoder.protocol() else {
+        warn!("{:?} didn't send anything recognizable within {:?}", local_name, DETECT_TIME);
+        return Ok(None);
+    };
+    Ok(candidates.into_iter().find(|device| device.protocol == protocol))
+}
+
+/// Connects to the device and prints what it says about itself.
+pub async fn print_device_info(manager: &Manager, args: &Args, timeout: Duration) -> Result<(), Box<dyn Error>> {
+    let device = find_device(manager, args, &Claimed::default())
+        .await?
+        .ok_or("No matching peripheral found")?;
+    let peripheral = &device.peripheral;
+    subscribe(args, &device).await?;
+    let mut notification_stream = ble(args, "getting notifications", peripheral.notifications()).await?;
+    let mut decoder = device.protocol.decoder(args);
+    let mut device_info = DeviceInfo::default();
+
+    let received = time::timeout(timeout, async {
+        while let Some(ValueNotification { uuid, value }) = notification_stream.next().await {
+            for message in decoder.decode(uuid, &value) {
+                if let Message::Info(field) = message {
+                    device_info.update(field);
+                }
+            }
+            if device_info.is_complete() {
+                break;
+            }
+        }
+    })
+    .await;
+    if received.is_err() {
+        warn!("Timed out waiting for the device to identify itself");
+    }
+
+    ble(args, "disconnecting", peripheral.disconnect()).await?;
+    println!("{}", device_info);
+    Ok(())
+}
+
+/// Subscribes to every characteristic readings arrive on.
+async fn subscribe(args: &Args, device: &Device) -> Result<(), Box<dyn Error>> {
+    for characteristic in &device.rx {
+        ble(args, "subscribing", device.peripheral.subscribe(characteristic)).await?;
+    }
+    Ok(())
+}
+
+async fn unsubscribe(args: &Args, device: &Device) -> Result<(), Box<dyn Error>> {
+    for characteristic in &device.rx {
+        ble(args, "unsubscribing", device.peripheral.unsubscribe(characteristic)).await?;
+    }
+    Ok(())
+}
+
+/// Writes commands to the device, if it has somewhere to write them to.
+async fn send_commands(args: &Args, device: &Device, commands: &[Vec<u8>]) -> Result<(), Box<dyn Error>> {
+    let Some(tx) = &device.tx else {
+        return Ok(());
+    };
+    let write_type = if tx.properties.contains(CharPropFlags::WRITE_WITHOUT_RESPONSE) {
+        WriteType::WithoutResponse
+    } else {
+        WriteType::WithResponse
+    };
+    for command in commands {
+        debug!("Sending {:02x?}", command);
+        ble(args, "sending command", device.peripheral.write(tx, command, write_type)).await?;
+    }
+    Ok(())
+}
+
+/// Writes the commands that make the device start streaming.
+async fn send_start_commands(args: &Args, device: &Device) -> Result<(), Box<dyn Error>> {
+    send_commands(args, device, &device.protocol.start_commands(args)).await
+}
+
+/// Passes on what a connected device sends until the connection is lost.
+async fn run_session(args: &Args, device: &Device, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+    let Device { adapter, peripheral, .. } = device;
+    subscribe(args, device).await?;
+    send_start_commands(args, device).await?;
+    let mut notification_stream = ble(args, "getting notifications", peripheral.notifications()).await?;
+    let mut disconnect_stream = ble(args, "getting adapter events", adapter.events()).await?;
+    let device_address = peripheral.address().to_string();
+    let decoder = device.protocol.decoder(args);
+    send(events, Event::Connected { device: device_address.clone(), decoder }).await?;
+    // The device sometimes connects fine but never sends anything. If nothing arrives
+    // for a while, subscribe again, and if that doesn't help, reconnect.
+    let mut data_deadline = time::Instant::now() + args.stall_timeout;
+    let mut resubscribed = false;
+    let poll = device.protocol.poll(args).filter(|_| device.tx.is_some());
+    let mut poll_at = time::Instant::now() + poll.as_ref().map_or(Duration::ZERO, |(interval, _)| *interval);
+    let mut rssi_at = time::Instant::now();
+    let mut weak_signal = false;
+
+    // Process while the BLE connection is not broken or stopped.
+    loop {
+        tokio::select! {
+            msg = notification_stream.next() => {
+                match msg {
+                    Some(ValueNotification { uuid, value }) => {
+                        trace!("Got raw data: {:?}", value);
+                        data_deadline = time::Instant::now() + args.stall_timeout;
+                        resubscribed = false;
+                        let notification = Notification {
+                            time: Utc::now(),
+                            device: device_address.clone(),
+                            characteristic: uuid,
+                            value,
+                        };
+                        send(events, Event::Notification(notification)).await?;
+                    },
+                    _ => break
+                }
+            },
+            _ = time::sleep_until(data_deadline), if !args.stall_timeout.is_zero() => {
+                if resubscribed {
+                    warn!("Still no data from the device after subscribing again, reconnecting");
+                    break;
+                }
+                warn!("No data from the device for {:?}, subscribing again", args.stall_timeout);
+                unsubscribe(args, device).await?;
+                subscribe(args, device).await?;
+                send_start_commands(args, device).await?;
+                resubscribed = true;
+                data_deadline = time::Instant::now() + args.stall_timeout;
+            },
+            _ = time::sleep_until(poll_at), if poll.is_some() => {
+                let (interval, command) = poll.as_ref().unwrap();
+                send_commands(args, device, std::slice::from_ref(command)).await?;
+                poll_at = time::Instant::now() + *interval;
+            },
+            _ = time::sleep_until(rssi_at), if !args.rssi_interval.is_zero() => {
+                let properties = ble(args, "getting signal strength", peripheral.properties()).await?;
+                let rssi = properties.and_then(|p| p.rssi);
+                trace!("RSSI of {}: {:?}", device_address, rssi);
+                send(events, Event::Rssi { device: device_address.clone(), rssi }).await?;
+                if let Some(value) = rssi {
+                    if value < args.min_rssi && !weak_signal {
+                        warn!("Weak Bluetooth signal from {} ({} dBm), readings may be lost", device_address, value);
+                        weak_signal = true;
+                    } else if value >= args.min_rssi && weak_signal {
+                        info!("Bluetooth signal from {} recovered ({} dBm)", device_address, value);
+                        weak_signal = false;
+                    }
+                }
+                rssi_at = time::Instant::now() + args.rssi_interval;
+            },
+            msg = disconnect_stream.next() => {
+                match msg {
+                    Some(CentralEvent::DeviceDisconnected(periph_id)) if periph_id == peripheral.id() => {
+                        info!("Disconnected from peripheral {}", device_address);
+                        break;
+                    },
+                    _ => {}
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+impl BleSource<'_> {
+    /// Connects to a device, streams from it, and reconnects whenever the connection is lost, until
+    /// the retries run out.
+    async fn keep_connected(&self, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+        let args = self.args;
+        let mut backoff = Backoff::new(args.reconnect_delay, args.reconnect_max_delay, args.max_retries);
+        // The device reconnecting is about, once there's been one.
+        let mut last_device = None;
+        let mut attempts: u64 = 0;
+        loop {
+            attempts += 1;
+            // Everything from scanning to disconnecting, to tell apart what happened on each try.
+            let attempt = info_span!("attempt", attempt = attempts);
+            let scan_started = time::Instant::now();
+            let found = {
+                let _scanning = self.scanning.lock().await;
+                find_device(&self.manager, args, &self.claimed).instrument(attempt.clone()).await
+            };
+            let result = match found {
+                Ok(Some(device)) => {
+                    self.stats.connections.fetch_add(1, Ordering::Relaxed);
+                    self.stats.connected.fetch_add(1, Ordering::Relaxed);
+                    self.sessions.lock().unwrap().insert(device.peripheral.id(), device.clone());
+                    let address = device.peripheral.address().to_string();
+                    let session = info_span!(parent: &attempt, "session", device = address.as_str());
+                    let result = run_session(args, &device, events).instrument(session.clone()).await;
+                    self.sessions.lock().unwrap().remove(&device.peripheral.id());
+                    self.stats.connected.fetch_sub(1, Ordering::Relaxed);
+                    last_device = Some(address.clone());
+                    send(events, Event::Disconnected { device: address }).await?;
+                    session.in_scope(|| info!("Disconnecting from peripheral..."));
+                    let disconnected = ble(args, "disconnecting", device.peripheral.disconnect());
+                    if let Err(e) = disconnected.instrument(session.clone()).await {
+                        session.in_scope(|| warn!("Couldn't disconnect cleanly: {}", e));
+                    }
+                    self.claimed.lock().unwrap().remove(&device.peripheral.id());
+                    result.map_err(|e| format!("Connection failed: {}", e))
+                }
+                Ok(None) => match args.scan_interval {
+                    // Nothing in range isn't a failure when scanning on a schedule, the device is
+                    // probably just off.
+                    Some(interval) => {
+                        debug!("No matching peripheral found, scanning again in {:?}", interval.saturating_sub(scan_started.elapsed()));
+                        time::sleep_until(scan_started + interval).await;
+                        continue;
+                    }
+                    // With several devices, one that's not around yet isn't a failure either, as
+                    // long as another is being read.
+                    None if !self.sessions.lock().unwrap().is_empty() => {
+                        debug!("No other matching peripheral found, scanning again in {:?}", args.reconnect_delay);
+                        time::sleep(args.reconnect_delay).await;
+                        continue;
+                    }
+                    None => Err("Failed to connect: No matching peripheral found".to_string()),
+                },
+                Err(e) => Err(format!("Failed to connect: {}", e)),
+            };
+            match result {
+                // The connection worked for a while, so try again straight away.
+                Ok(()) => {
+                    backoff.reset();
+                    if let Some(device) = &last_device {
+                        let event = Event::Reconnecting { device: device.clone(), failures: 0, delay: Duration::ZERO };
+                        send(events, event).await?;
+                    }
+                }
+                Err(e) => {
+                    attempt.in_scope(|| error!("{}", e));
+                    let Some(delay) = backoff.next_delay() else {
+                        return Err(format!("Giving up after {} failed attempts to connect", backoff.failures()).into());
+                    };
+                    let attempts = match backoff.max_retries() {
+                        Some(max_retries) => format!("{}/{}", backoff.failures(), max_retries),
+                        None => backoff.failures().to_string(),
+                    };
+                    info!("Retrying in {:.1}s (failed attempts: {})", delay.as_secs_f32(), attempts);
+                    if let Some(device) = &last_device {
+                        let event = Event::Reconnecting { device: device.clone(), failures: backoff.failures(), delay };
+                        send(events, event).await?;
+                    }
+                    time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Where notifications come from. Each [`DataSource`] produces [`Event`]s, and [`run`] turns them
+//! into readings for the sinks the same way whichever source it is.
+
+use async_trait::async_trait;
+use btleplug::platform::Manager;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::capture::{CaptureWriter, Notification};
+use crate::cli::Args;
+use crate::output::{self, EventRecord};
+use crate::protocol::Decoder;
+use crate::receiver::Receiver;
+use crate::sink::Sinks;
+use crate::stats::Stats;
+use crate::systemd::Notifier;
+
+pub mod ble;
+mod replay;
+mod simulator;
+mod tcp;
+
+pub use ble::BleSource;
+pub use replay::ReplaySource;
+pub use simulator::SimulatorSource;
+pub use tcp::TcpSource;
+
+/// How many events can wait for the sinks before sources have to.
+const QUEUE_LENGTH: usize = 256;
+
+/// Something that happened to a device.
+pub enum Event {
+    /// A device is about to send notifications, which `decoder` understands.
+    Connected { device: String, decoder: Decoder },
+    Notification(Notification),
+    Rssi { device: String, rssi: Option<i16> },
+    Disconnected { device: String },
+    /// The source will try to connect to `device` again after `delay`, having failed `failures`
+    /// times in a row.
+    Reconnecting { device: String, failures: u32, delay: Duration },
+}
+
+/// Produces events until there's nothing left, or it fails.
+#[async_trait(?Send)]
+pub trait DataSource {
+    async fn run(&mut self, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>>;
+
+    /// Called after `run` has been cancelled because the program is shutting down. Sources that
+    /// hold on to devices let go of them here.
+    async fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Picks the source the arguments ask for, which is the BLE device unless something else is.
+pub async fn from_args<'a>(args: &'a Args, stats: &Arc<Stats>) -> Result<Box<dyn DataSource + 'a>, Box<dyn Error>> {
+    if let Some(path) = &args.replay {
+        return Ok(Box::new(ReplaySource::new(path.clone(), args.replay_speed)));
+    }
+    if args.simulate {
+        return Ok(Box::new(SimulatorSource::new(
+            args.simulate_desaturation_interval,
+            args.simulate_desaturation_depth,
+        )));
+    }
+    if let Some(address) = &args.tcp {
+        return Ok(Box::new(TcpSource::new(args, address.clone())));
+    }
+    Ok(Box::new(BleSource::new(args, Manager::new().await?, stats.clone())))
+}
+
+/// Sends an event on to [`run`], failing if it has stopped taking them.
+async fn send(events: &mpsc::Sender<Event>, event: Event) -> Result<(), Box<dyn Error>> {
+    events
+        .send(event)
+        .await
+        .map_err(|_| "Readings are no longer being processed".into())
+}
+
+/// Runs `source`, decoding what it produces and passing the readings to `sinks`, and recording
+/// notifications to `capture` if given. Once `shutdown` completes, the source is stopped and
+/// what it already produced is passed on before returning.
+pub async fn run(
+    mut source: Box<dyn DataSource + '_>,
+    args: &Args,
+    sinks: &Mutex<Sinks>,
+    stats: &Stats,
+    mut capture: Option<CaptureWriter>,
+    shutdown: impl Future<Output = Result<(), Box<dyn Error>>>,
+) -> Result<(), Box<dyn Error>> {
+    let (sender, mut events) = mpsc::channel(QUEUE_LENGTH);
+    let produce = async move {
+        // Dropping the sender when done lets the consumer finish too.
+        let sender = sender;
+        let result = tokio::select! {
+            result = source.run(&sender) => return result,
+            result = shutdown => result,
+        };
+        if let Err(e) = source.stop().await {
+            warn!("Couldn't stop cleanly: {}", e);
+        }
+        result
+    };
+    let consume = async {
+        let mut receivers: HashMap<String, Receiver> = HashMap::new();
+        let mut systemd = Notifier::from_env();
+        let mut tick = time::interval(systemd.tick_interval());
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = tick.tick() => {
+                    systemd.tick(stats, !receivers.is_empty());
+                    continue;
+                }
+            };
+            systemd.alive();
+            match event {
+                Event::Connected { device, decoder } => {
+                    receivers.insert(device.clone(), Receiver::new(device.clone(), decoder, args, stats));
+                    systemd.ready();
+                    let record = EventRecord { time: Utc::now(), device, event: output::Event::Connected };
+                    sinks.lock().await.event(&record).await;
+                }
+                Event::Notification(notification) => {
+                    if let Some(capture) = &mut capture {
+                        let Notification { time, device, characteristic, value } = &notification;
+                        if let Err(e) = capture.write(*time, device, *characteristic, value) {
+                            warn!("Couldn't record notification: {}", e);
+                        }
+                    }
+                    let receiver = receivers.entry(notification.device.clone()).or_insert_with(|| {
+                        Receiver::new(notification.device.clone(), args.protocol.decoder(args), args, stats)
+                    });
+                    receiver
+                        .receive(notification.time, notification.characteristic, &notification.value, sinks, stats)
+                        .await;
+                }
+                Event::Rssi { device, rssi } => {
+                    if let Some(receiver) = receivers.get_mut(&device) {
+                        receiver.rssi = rssi;
+                    }
+                }
+                Event::Disconnected { device } => {
+                    stats.disconnected(&device);
+                    systemd.status(&format!("Lost {}", device));
+                    if let Some(mut receiver) = receivers.remove(&device) {
+                        receiver.close(sinks, stats).await;
+                        log_parser_stats(&device, &receiver);
+                        let record = EventRecord { time: Utc::now(), device, event: output::Event::Disconnected };
+                        sinks.lock().await.event(&record).await;
+                    }
+                }
+                Event::Reconnecting { device, failures, delay } => {
+                    let event = output::Event::Reconnecting { failures, delay: delay.as_secs_f32() };
+                    let record = EventRecord { time: Utc::now(), device, event };
+                    sinks.lock().await.event(&record).await;
+                }
+            }
+        }
+        for (device, receiver) in &mut receivers {
+            receiver.close(sinks, stats).await;
+            log_parser_stats(device, receiver);
+        }
+        if let Some(capture) = &mut capture {
+            if let Err(e) = capture.flush() {
+                warn!("Couldn't finish recording notifications: {}", e);
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(produce, consume);
+    result
+}
+
+fn log_parser_stats(device: &str, receiver: &Receiver) {
+    let parser_stats = receiver.parser_stats();
+    if parser_stats.checksum_errors > 0 {
+        warn!(
+            "Dropped {} of {} frames from {} with bad checksums",
+            parser_stats.checksum_errors,
+            parser_stats.frames + parser_stats.checksum_errors,
+            device
+        );
+    }
+    debug!("Parser stats for {}: {:?}", device, parser_stats);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser as _;
+    use pc60fw_protocol::{Frame, Parser, Profile};
+    use uuid::Uuid;
+
+    use crate::output::Record;
+    use crate::sink::{Sink, SinkError};
+
+    const DEVICE: &str = "canned";
+
+    /// Sends the given notifications from one device, then disconnects.
+    struct CannedSource(Vec<Vec<u8>>);
+
+    #[async_trait(?Send)]
+    impl DataSource for CannedSource {
+        async fn run(&mut self, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+            let decoder = Decoder::Pc60fw(Parser::new(), Profile::PC_60FW);
+            send(events, Event::Connected { device: DEVICE.to_string(), decoder }).await?;
+            for value in self.0.drain(..) {
+                let notification = Notification {
+                    time: Utc::now(),
+                    device: DEVICE.to_string(),
+                    characteristic: Uuid::nil(),
+                    value,
+                };
+                send(events, Event::Notification(notification)).await?;
+            }
+            send(events, Event::Disconnected { device: DEVICE.to_string() }).await
+        }
+    }
+
+    /// Keeps everything it's given.
+    #[derive(Clone, Default)]
+    struct Collector {
+        readings: Arc<std::sync::Mutex<Vec<Record>>>,
+        events: Arc<std::sync::Mutex<Vec<EventRecord>>>,
+    }
+
+    #[async_trait]
+    impl Sink for Collector {
+        fn name(&self) -> String {
+            "collector".to_string()
+        }
+
+        async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+            self.readings.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+
+        async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+            self.events.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+    }
+
+    /// A PC-60FW parameters frame with a finger in and a good signal.
+    fn parameters(spo2: u8, heart_rate: u8) -> Vec<u8> {
+        Frame::new(0x0f, vec![0x01, spo2, heart_rate, 0, 30, 6 << 4]).encode()
+    }
+
+    #[tokio::test]
+    async fn passes_readings_and_events_to_the_sinks() {
+        let args = Args::try_parse_from(["ble-spo2"]).unwrap();
+        let collector = Collector::default();
+        let mut sinks = Sinks::default();
+        sinks.push(collector.clone());
+        let sinks = Mutex::new(sinks);
+        let stats = Stats::default();
+        // The second reading is split over two notifications, as happens over BLE.
+        let second = parameters(96, 62);
+        let (start, end) = second.split_at(4);
+        let source = CannedSource(vec![parameters(97, 61), start.to_vec(), end.to_vec()]);
+
+        run(Box::new(source), &args, &sinks, &stats, None, std::future::pending()).await.unwrap();
+
+        let readings = collector.readings.lock().unwrap();
+        let values: Vec<_> = readings.iter().map(|r| (r.spo2, r.heartrate)).collect();
+        assert_eq!(values, [(Some(97), Some(61)), (Some(96), Some(62))]);
+        assert!(readings.iter().all(|r| r.device == DEVICE));
+        let events = collector.events.lock().unwrap();
+        let kinds: Vec<_> = events.iter().map(|e| e.event.kind()).collect();
+        assert_eq!(kinds.first(), Some(&"connected"));
+        assert_eq!(kinds.last(), Some(&"disconnected"));
+        assert_eq!(stats.latest().map(|r| r.spo2), Some(Some(96)));
+    }
+}
@@ -0,0 +1,52 @@
+//! Feeding a capture recorded with `--record-raw` back through the decoder and sinks, so output
+//! formats and analysis can be worked on without the device.
+
+use async_trait::async_trait;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+
+use super::{send, DataSource, Event};
+use crate::capture::Notification;
+
+/// Replays the capture at `path`, `speed` times faster than it was recorded, or as fast as
+/// possible if `speed` is 0. Readings keep the times they were recorded at.
+pub struct ReplaySource {
+    path: PathBuf,
+    speed: f64,
+}
+
+impl ReplaySource {
+    pub fn new(path: PathBuf, speed: f64) -> Self {
+        ReplaySource { path, speed }
+    }
+}
+
+#[async_trait(?Send)]
+impl DataSource for ReplaySource {
+    async fn run(&mut self, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+        let path = &self.path;
+        let file = File::open(path).map_err(|e| format!("Couldn't open capture {:?}: {}", path, e))?;
+        let mut start = None;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let notification: Notification =
+                line.parse().map_err(|e| format!("{}:{}: {}", path.display(), index + 1, e))?;
+
+            let (recorded_start, started) = *start.get_or_insert((notification.time, Instant::now()));
+            if self.speed > 0.0 {
+                let offset = (notification.time - recorded_start).to_std().unwrap_or_default();
+                time::sleep_until(started + offset.div_f64(self.speed)).await;
+            }
+            send(events, Event::Notification(notification)).await?;
+        }
+        info!("Replayed {:?}", path);
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+//! A pretend oximeter for `--simulate`, so outputs, dashboards and alarms can be tried out
+//! without the device. It makes up PC-60FW frames, which go through the same decoder and sinks as
+//! real ones.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use pc60fw_protocol::{Frame, Parser, Profile};
+use rand::Rng;
+use std::error::Error;
+use std::f64::consts::TAU;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time;
+use uuid::Uuid;
+
+use super::{send, DataSource, Event};
+use crate::capture::Notification;
+use crate::protocol::Decoder;
+
+/// Stands in for the device's address.
+const DEVICE: &str = "simulator";
+const TOKEN_DATA: u8 = 0x0f;
+/// Like the real device, the waveform comes in frames of 5 samples, 10 times a second.
+const WAVEFORM_PERIOD: Duration = Duration::from_millis(100);
+const SAMPLES_PER_FRAME: u32 = 5;
+/// How long a desaturation takes to reach its lowest point, stays there, and recovers.
+const DESATURATION_FALL: f64 = 20.0;
+const DESATURATION_HOLD: f64 = 10.0;
+const DESATURATION_RISE: f64 = 15.0;
+/// Breathing makes the waveform's baseline rise and fall, 15 times a minute.
+const BREATHING_PERIOD: f64 = 4.0;
+
+/// Runs the simulated device until interrupted.
+pub struct SimulatorSource {
+    desaturation_interval: Duration,
+    desaturation_depth: u8,
+}
+
+impl SimulatorSource {
+    pub fn new(desaturation_interval: Duration, desaturation_depth: u8) -> Self {
+        SimulatorSource { desaturation_interval, desaturation_depth }
+    }
+}
+
+#[async_trait(?Send)]
+impl DataSource for SimulatorSource {
+    async fn run(&mut self, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+        info!("Simulating a device");
+        let decoder = Decoder::Pc60fw(Parser::new(), Profile::PC_60FW);
+        send(events, Event::Connected { device: DEVICE.to_string(), decoder }).await?;
+        let mut simulator = Simulator::new(self.desaturation_interval, self.desaturation_depth);
+        let mut interval = time::interval(WAVEFORM_PERIOD);
+        loop {
+            interval.tick().await;
+            for frame in simulator.step() {
+                let notification = Notification {
+                    time: Utc::now(),
+                    device: DEVICE.to_string(),
+                    characteristic: Uuid::nil(),
+                    value: frame.encode(),
+                };
+                send(events, Event::Notification(notification)).await?;
+            }
+        }
+    }
+}
+
+struct Simulator {
+    /// Seconds since the simulation started.
+    elapsed: f64,
+    /// How far into the current heart beat the waveform is, from 0 to 1.
+    beat_phase: f64,
+    steps: u32,
+    desaturation_interval: Duration,
+    desaturation_depth: u8,
+}
+
+impl Simulator {
+    fn new(desaturation_interval: Duration, desaturation_depth: u8) -> Self {
+        Simulator {
+            elapsed: 0.0,
+            beat_phase: 0.0,
+            steps: 0,
+            desaturation_interval,
+            desaturation_depth,
+        }
+    }
+
+    /// How far into a desaturation the simulation is, from 0 for none to 1 at its lowest point.
+    /// Every interval ends with one.
+    fn desaturation(&self) -> f64 {
+        let interval = self.desaturation_interval.as_secs_f64();
+        if interval == 0.0 {
+            return 0.0;
+        }
+        let into = self.elapsed % interval - (interval - DESATURATION_FALL - DESATURATION_HOLD - DESATURATION_RISE);
+        if into < 0.0 {
+            0.0
+        } else if into < DESATURATION_FALL {
+            into / DESATURATION_FALL
+        } else if into < DESATURATION_FALL + DESATURATION_HOLD {
+            1.0
+        } else {
+            (DESATURATION_FALL + DESATURATION_HOLD + DESATURATION_RISE - into) / DESATURATION_RISE
+        }
+    }
+
+    fn heart_rate(&self) -> f64 {
+        // Slow breathing-like drift, and the heart speeding up as oxygen drops.
+        64.0 + 3.0 * (TAU * self.elapsed / 120.0).sin() + 10.0 * self.desaturation()
+    }
+
+    /// Advances the simulation by [`WAVEFORM_PERIOD`] and returns the frames the device would
+    /// have sent meanwhile.
+    fn step(&mut self) -> Vec<Frame> {
+        let mut rng = rand::rng();
+        let sample_period = WAVEFORM_PERIOD.as_secs_f64() / f64::from(SAMPLES_PER_FRAME);
+        let mut waveform = vec![0x02];
+        for _ in 0..SAMPLES_PER_FRAME {
+            self.beat_phase += self.heart_rate() / 60.0 * sample_period;
+            let beat = self.beat_phase >= 1.0;
+            self.beat_phase %= 1.0;
+            // A quick rise to the systolic peak, then a slower fall with a dicrotic notch.
+            let phase = self.beat_phase;
+            let shape = if phase < 0.15 {
+                phase / 0.15
+            } else {
+                (-(phase - 0.15) * 4.0).exp() * (1.0 + 0.15 * (TAU * 2.0 * phase).sin())
+            };
+            let breathing = (TAU * self.elapsed / BREATHING_PERIOD).sin();
+            let pleth = (10.0 + 90.0 * shape.clamp(0.0, 1.0) + 8.0 * (1.0 + breathing)) as u8;
+            waveform.push(pleth | if beat { 0x80 } else { 0 });
+            self.elapsed += sample_period;
+        }
+        let mut frames = vec![Frame::new(TOKEN_DATA, waveform)];
+
+        self.steps += 1;
+        let steps_per_second = (1.0 / WAVEFORM_PERIOD.as_secs_f64()) as u32;
+        if self.steps.is_multiple_of(steps_per_second) {
+            let spo2 = 97.0 - f64::from(self.desaturation_depth) * self.desaturation() + rng.random_range(-0.6..0.6);
+            let heart_rate = self.heart_rate() + rng.random_range(-1.0..1.0);
+            let perfusion_index: u8 = rng.random_range(28..34);
+            let signal_strength: u8 = 6;
+            frames.push(Frame::new(
+                TOKEN_DATA,
+                vec![0x01, spo2.round().clamp(0.0, 100.0) as u8, heart_rate.round() as u8, 0, perfusion_index, signal_strength << 4],
+            ));
+        }
+        if self.steps.is_multiple_of(steps_per_second * 10) {
+            frames.push(Frame::new(TOKEN_DATA, vec![0x03, 3]));
+        }
+        frames
+    }
+}
@@ -0,0 +1,109 @@
+//! Reading raw bytes from a TCP connection, for oximeters that aren't on Bluetooth but can be
+//! bridged to the network, e.g. a wired one behind ser2net or an ESP32.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use std::error::Error;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use super::{send, DataSource, Event};
+use crate::backoff::Backoff;
+use crate::capture::Notification;
+use crate::cli::Args;
+
+/// Connects to `address` and reconnects whenever the connection is lost, until the retries run
+/// out.
+pub struct TcpSource<'a> {
+    args: &'a Args,
+    address: String,
+}
+
+impl<'a> TcpSource<'a> {
+    pub fn new(args: &'a Args, address: String) -> Self {
+        TcpSource { args, address }
+    }
+
+    /// Passes on what arrives until the connection is closed. A connection that's closed before
+    /// anything arrives counts as failed, like when the bridge has no device behind it, so it's
+    /// retried with a backoff rather than straight away.
+    async fn run_session(&self, stream: &mut TcpStream, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+        let decoder = self.args.protocol.decoder(self.args);
+        send(events, Event::Connected { device: self.address.clone(), decoder }).await?;
+        // There are no characteristics, so the bytes are passed on as if they arrived on the one
+        // the protocol reads from.
+        let characteristic = self.args.protocol.rx_characteristics(self.args).first().copied().unwrap_or(Uuid::nil());
+        let mut buffer = [0; 1024];
+        let mut received = false;
+        loop {
+            let read = if self.args.stall_timeout.is_zero() {
+                stream.read(&mut buffer).await?
+            } else {
+                time::timeout(self.args.stall_timeout, stream.read(&mut buffer))
+                    .await
+                    .map_err(|_| format!("Nothing arrived for {:?}", self.args.stall_timeout))??
+            };
+            if read == 0 && !received {
+                return Err(format!("{} closed the connection before sending anything", self.address).into());
+            }
+            if read == 0 {
+                info!("{} closed the connection", self.address);
+                return Ok(());
+            }
+            received = true;
+            trace!("Got raw data: {:?}", &buffer[..read]);
+            let notification = Notification {
+                time: Utc::now(),
+                device: self.address.clone(),
+                characteristic,
+                value: buffer[..read].to_vec(),
+            };
+            send(events, Event::Notification(notification)).await?;
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl DataSource for TcpSource<'_> {
+    async fn run(&mut self, events: &mpsc::Sender<Event>) -> Result<(), Box<dyn Error>> {
+        let args = self.args;
+        let mut backoff = Backoff::new(args.reconnect_delay, args.reconnect_max_delay, args.max_retries);
+        let mut attempts: u64 = 0;
+        loop {
+            attempts += 1;
+            let attempt = info_span!("attempt", attempt = attempts);
+            attempt.in_scope(|| info!("Connecting to {}", self.address));
+            let result = match TcpStream::connect(&self.address).instrument(attempt.clone()).await {
+                Ok(mut stream) => {
+                    let session = info_span!(parent: &attempt, "session", device = self.address.as_str());
+                    let result = self.run_session(&mut stream, events).instrument(session).await;
+                    send(events, Event::Disconnected { device: self.address.clone() }).await?;
+                    result.map_err(|e| format!("Connection failed: {}", e))
+                }
+                Err(e) => Err(format!("Failed to connect to {}: {}", self.address, e)),
+            };
+            let (failures, delay) = match result {
+                Ok(()) => {
+                    backoff.reset();
+                    (0, Duration::ZERO)
+                }
+                Err(e) => {
+                    attempt.in_scope(|| error!("{}", e));
+                    let Some(delay) = backoff.next_delay() else {
+                        return Err(format!("Giving up after {} failed attempts to connect", backoff.failures()).into());
+                    };
+                    info!("Retrying in {:.1}s", delay.as_secs_f32());
+                    (backoff.failures(), delay)
+                }
+            };
+            let event = Event::Reconnecting { device: self.address.clone(), failures, delay };
+            send(events, event).await?;
+            time::sleep(delay).await;
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Live state of the reader, shared with the HTTP server.
+
+use pc60fw_protocol::DeviceInfo;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::output::{EventRecord, Record};
+use crate::summary::Summary;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Frames parsed successfully.
+    pub frames: AtomicU64,
+    /// Frames dropped because of a bad checksum.
+    pub checksum_errors: AtomicU64,
+    /// Successful connections to the device, including the first one.
+    pub connections: AtomicU64,
+    /// Devices currently connected.
+    pub connected: AtomicU64,
+    latest: Mutex<Option<Record>>,
+    /// The latest reading from each device, by address.
+    latest_by_device: Mutex<BTreeMap<String, Record>>,
+    device_info: Mutex<DeviceInfo>,
+    /// What each device has recorded so far, by address.
+    summaries: Mutex<BTreeMap<String, Summary>>,
+}
+
+impl Stats {
+    pub fn reading(&self, record: &Record) {
+        *self.latest.lock().unwrap() = Some(record.clone());
+        self.latest_by_device.lock().unwrap().insert(record.device.clone(), record.clone());
+        self.summaries.lock().unwrap().entry(record.device.clone()).or_default().reading(record);
+    }
+
+    pub fn event(&self, record: &EventRecord) {
+        self.summaries.lock().unwrap().entry(record.device.clone()).or_default().event(&record.event);
+    }
+
+    /// Counts a device going away while readings were still wanted.
+    pub fn disconnected(&self, device: &str) {
+        self.summaries.lock().unwrap().entry(device.to_string()).or_default().disconnects += 1;
+    }
+
+    pub fn summaries(&self) -> BTreeMap<String, Summary> {
+        self.summaries.lock().unwrap().clone()
+    }
+
+    pub fn latest(&self) -> Option<Record> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    pub fn latest_by_device(&self) -> BTreeMap<String, Record> {
+        self.latest_by_device.lock().unwrap().clone()
+    }
+
+    pub fn set_device_info(&self, device_info: &DeviceInfo) {
+        *self.device_info.lock().unwrap() = device_info.clone();
+    }
+
+    pub fn device_info(&self) -> DeviceInfo {
+        self.device_info.lock().unwrap().clone()
+    }
+
+    /// Renders the stats in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        // Values with their labels, if they have any.
+        let mut metric = |name: &str, kind: &str, help: &str, values: Vec<(String, f64)>| {
+            writeln!(out, "# HELP {} {}", name, help).unwrap();
+            writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+            for (labels, value) in values {
+                writeln!(out, "{}{} {}", name, labels, value).unwrap();
+            }
+        };
+        // The latest value from each device, labelled with the device.
+        let latest = self.latest_by_device();
+        let per_device = |value: fn(&Record) -> Option<f64>| -> Vec<(String, f64)> {
+            latest
+                .iter()
+                .filter_map(|(device, record)| Some((format!("{{device=\"{}\"}}", label_value(device)), value(record)?)))
+                .collect()
+        };
+        let single = |value: u64| vec![(String::new(), value as f64)];
+
+        metric(
+            "pc60fw_spo2_percent",
+            "gauge",
+            "Latest oxygen saturation.",
+            per_device(|r| r.spo2.map(f64::from)),
+        );
+        metric(
+            "pc60fw_heart_rate_bpm",
+            "gauge",
+            "Latest pulse rate.",
+            per_device(|r| r.heartrate.map(f64::from)),
+        );
+        metric(
+            "pc60fw_perfusion_index_percent",
+            "gauge",
+            "Latest perfusion index.",
+            // Rounded, as the device only has one decimal and f32 -> f64 would add noise.
+            per_device(|r| r.pi.map(|pi| (f64::from(pi) * 10.0).round() / 10.0)),
+        );
+        metric(
+            "pc60fw_battery_bars",
+            "gauge",
+            "Battery charge as shown on the device, 0-3.",
+            per_device(|r| r.battery.map(f64::from)),
+        );
+        metric(
+            "pc60fw_signal_strength",
+            "gauge",
+            "Latest optical signal strength, 0-8.",
+            per_device(|r| Some(f64::from(r.signal))),
+        );
+        metric(
+            "pc60fw_rssi_dbm",
+            "gauge",
+            "Bluetooth signal strength of the device, as last checked.",
+            per_device(|r| r.rssi.map(f64::from)),
+        );
+        metric(
+            "pc60fw_last_reading_timestamp_seconds",
+            "gauge",
+            "Unix time of the latest reading.",
+            per_device(|r| Some(r.time.timestamp_millis() as f64 / 1000.0)),
+        );
+        metric(
+            "pc60fw_connected",
+            "gauge",
+            "Number of devices currently connected.",
+            single(self.connected.load(Ordering::Relaxed)),
+        );
+        metric(
+            "pc60fw_connections_total",
+            "counter",
+            "Successful connections to the device; anything past the first is a reconnect.",
+            single(self.connections.load(Ordering::Relaxed)),
+        );
+        metric(
+            "pc60fw_frames_total",
+            "counter",
+            "Frames received from the device.",
+            single(self.frames.load(Ordering::Relaxed)),
+        );
+        metric(
+            "pc60fw_checksum_errors_total",
+            "counter",
+            "Frames dropped because of a bad checksum.",
+            single(self.checksum_errors.load(Ordering::Relaxed)),
+        );
+        out
+    }
+}
+
+/// Escapes a Prometheus label value.
+fn label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
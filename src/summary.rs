@@ -0,0 +1,125 @@
+//! Totals over a whole recording, printed when the program exits, for a quick look at a night
+//! without loading the readings into anything.
+
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::time::Duration;
+
+use crate::desaturation;
+use crate::output::{Event, Record};
+
+/// Thresholds the time spent below is counted for, in percent SpO2.
+const THRESHOLDS: [u8; 2] = [90, 88];
+/// Gaps between readings longer than this, e.g. while reconnecting, don't count towards the time
+/// below a threshold.
+const MAX_GAP: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default, Clone)]
+pub struct Summary {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    spo2: Series,
+    heart_rate: Series,
+    /// Time spent below each of [`THRESHOLDS`].
+    below: [Duration; THRESHOLDS.len()],
+    /// SpO2 of the previous reading, which lasted until this one.
+    previous_spo2: Option<u8>,
+    /// Desaturations found for each of [`desaturation::THRESHOLDS`].
+    desaturations: [u64; desaturation::THRESHOLDS.len()],
+    /// Times the device was lost, not counting leaving at the end.
+    pub disconnects: u64,
+}
+
+/// Minimum, maximum and average of a value.
+#[derive(Debug, Default, Clone, Copy)]
+struct Series {
+    min: Option<u8>,
+    max: Option<u8>,
+    sum: u64,
+    count: u64,
+}
+
+impl Series {
+    fn add(&mut self, value: u8) {
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        self.sum += u64::from(value);
+        self.count += 1;
+    }
+
+    fn average(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+}
+
+impl Summary {
+    pub fn reading(&mut self, record: &Record) {
+        if let (Some(end), Some(spo2)) = (self.end, self.previous_spo2) {
+            let gap = (record.time - end).to_std().unwrap_or_default();
+            if gap <= MAX_GAP {
+                for (below, threshold) in self.below.iter_mut().zip(THRESHOLDS) {
+                    if spo2 < threshold {
+                        *below += gap;
+                    }
+                }
+            }
+        }
+        self.start.get_or_insert(record.time);
+        self.end = Some(record.time);
+        self.previous_spo2 = record.spo2;
+        if let Some(spo2) = record.spo2 {
+            self.spo2.add(spo2);
+        }
+        if let Some(heart_rate) = record.heartrate {
+            self.heart_rate.add(heart_rate);
+        }
+    }
+
+    pub fn event(&mut self, event: &Event) {
+        if let Event::Desaturation { threshold, .. } = event {
+            if let Some(index) = desaturation::THRESHOLDS.iter().position(|t| t == threshold) {
+                self.desaturations[index] += 1;
+            }
+        }
+    }
+
+    pub fn length(&self) -> Duration {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => (end - start).to_std().unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Whole seconds, as the rest is noise this far out.
+fn seconds(duration: Duration) -> humantime::FormattedDuration {
+    humantime::format_duration(Duration::from_secs(duration.as_secs()))
+}
+
+fn series(f: &mut fmt::Formatter<'_>, name: &str, series: &Series, unit: &str) -> fmt::Result {
+    match (series.min, series.average(), series.max) {
+        (Some(min), Some(average), Some(max)) => {
+            writeln!(f, "{}: min {}{unit}, avg {:.1}{unit}, max {}{unit}", name, min, average, max)
+        }
+        _ => writeln!(f, "{}: no readings", name),
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let length = self.length();
+        writeln!(f, "Recording length: {}", seconds(length))?;
+        series(f, "SpO2", &self.spo2, "%")?;
+        series(f, "Heart rate", &self.heart_rate, " bpm")?;
+        for (below, threshold) in self.below.iter().zip(THRESHOLDS) {
+            let share = if length.is_zero() { 0.0 } else { below.as_secs_f64() / length.as_secs_f64() * 100.0 };
+            writeln!(f, "Time below {}%: {} ({:.1}%)", threshold, seconds(*below), share)?;
+        }
+        let hours = length.as_secs_f64() / 3600.0;
+        for (count, threshold) in self.desaturations.iter().zip(desaturation::THRESHOLDS) {
+            let per_hour = if hours > 0.0 { *count as f64 / hours } else { 0.0 };
+            writeln!(f, "ODI {}%: {:.1}/h ({} desaturations)", threshold, per_hour, count)?;
+        }
+        write!(f, "Disconnects: {}", self.disconnects)
+    }
+}
@@ -0,0 +1,149 @@
+//! Running as a systemd service with `Type=notify`: telling systemd when the reader is ready, a
+//! status line for `systemctl status`, and petting the watchdog so a wedged BLE stack gets the
+//! service restarted. Does nothing unless started by systemd with `NOTIFY_SOCKET` set.
+
+use std::env;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::stats::Stats;
+
+/// How often the status line is refreshed when there's no watchdog to pet more often.
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+#[cfg(target_os = "linux")]
+type Socket = std::os::unix::net::UnixDatagram;
+/// There's no systemd to talk to.
+#[cfg(not(target_os = "linux"))]
+type Socket = std::convert::Infallible;
+
+pub struct Notifier {
+    socket: Option<Socket>,
+    watchdog: Option<Duration>,
+    last_petted: Option<Instant>,
+    ready: bool,
+    status: String,
+}
+
+impl Notifier {
+    pub fn from_env() -> Self {
+        let socket = match env::var("NOTIFY_SOCKET") {
+            Ok(path) => connect(&path)
+                .inspect_err(|e| warn!("Couldn't connect to systemd at {}: {}", path, e))
+                .ok(),
+            Err(_) => None,
+        };
+        // The watchdog is meant for the main process only, not for what it runs.
+        let for_us = env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string());
+        let watchdog = env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .filter(|_| for_us && socket.is_some())
+            .map(Duration::from_micros);
+        if let Some(watchdog) = watchdog {
+            debug!("systemd watchdog expects to hear from us every {:?}", watchdog);
+        }
+        Notifier {
+            socket,
+            watchdog,
+            last_petted: None,
+            ready: false,
+            status: String::new(),
+        }
+    }
+
+    /// How often [`tick`](Self::tick) should be called.
+    pub fn tick_interval(&self) -> Duration {
+        self.watchdog.map_or(STATUS_INTERVAL, |watchdog| (watchdog / 2).min(STATUS_INTERVAL))
+    }
+
+    /// Tells systemd that startup is done, the first time a device connects.
+    pub fn ready(&mut self) {
+        if !self.ready {
+            self.ready = true;
+            self.send("READY=1");
+        }
+    }
+
+    /// Tells systemd that data is still flowing. Called for everything that arrives, so it only
+    /// actually sends a few times per watchdog interval.
+    pub fn alive(&mut self) {
+        let Some(watchdog) = self.watchdog else {
+            return;
+        };
+        if self.last_petted.is_some_and(|last| last.elapsed() < watchdog / 4) {
+            return;
+        }
+        self.last_petted = Some(Instant::now());
+        self.send("WATCHDOG=1");
+    }
+
+    /// Updates the status line from the latest reading. While no device is connected there's
+    /// nothing to wait for, so that counts as being alive too.
+    pub fn tick(&mut self, stats: &Stats, connected: bool) {
+        let status = match stats.latest() {
+            Some(record) if connected => format!(
+                "{}: SpO2 {}%, {} bpm",
+                record.device,
+                field(record.spo2),
+                field(record.heartrate)
+            ),
+            Some(_) => "Waiting for the device to come back".to_string(),
+            None if connected => "Connected, waiting for readings".to_string(),
+            None => "Looking for the device".to_string(),
+        };
+        self.status(&status);
+        if !connected {
+            self.alive();
+        }
+    }
+
+    pub fn status(&mut self, status: &str) {
+        if self.status != status {
+            self.send(&format!("STATUS={}", status));
+            self.status = status.to_string();
+        }
+    }
+
+    fn send(&self, state: &str) {
+        if let Some(socket) = &self.socket {
+            if let Err(e) = send(socket, state) {
+                debug!("Couldn't notify systemd: {}", e);
+            }
+        }
+    }
+}
+
+fn field(value: Option<u8>) -> String {
+    value.map_or("--".to_string(), |value| value.to_string())
+}
+
+/// `path` starts with @ for a socket in the abstract namespace.
+#[cfg(target_os = "linux")]
+fn connect(path: &str) -> io::Result<Socket> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let address = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(path)?,
+    };
+    let socket = Socket::unbound()?;
+    socket.connect_addr(&address)?;
+    Ok(socket)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn connect(_path: &str) -> io::Result<Socket> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "only supported on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn send(socket: &Socket, state: &str) -> io::Result<()> {
+    socket.send(state.as_bytes()).map(|_| ())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send(socket: &Socket, _state: &str) -> io::Result<()> {
+    match *socket {}
+}
@@ -0,0 +1,102 @@
+//! User-defined line formats for readings, e.g. `{time}\t{hr}\t{spo2}`.
+//!
+//! `{name}` is replaced with the named field of the reading, and is empty while the field has no
+//! value. `{{` and `}}` are literal braces, and `\t`, `\n` and `\\` are the usual escapes, so
+//! templates can be written without fighting the shell.
+
+use crate::output::{Record, Timestamps};
+
+/// Field names that can be used in templates, with their aliases.
+const FIELDS: &[&str] = &[
+    "time", "spo2", "heartrate", "hr", "pi", "battery", "status", "signal", "quality", "device", "rssi",
+    "spo2_raw", "heartrate_raw", "hr_raw", "rmssd", "sdnn", "resp_rate",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    other => return Err(format!("Unknown escape \\{} in template", other.map(String::from).unwrap_or_default())),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or("Unclosed { in template")?;
+                    let name = &rest[..end];
+                    if !FIELDS.contains(&name) {
+                        return Err(format!("Unknown field {{{}}} in template, expected one of {}", name, FIELDS.join(", ")));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(name.to_string()));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err("Unmatched } in template, use }} for a literal brace".to_string()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template(parts))
+    }
+
+    pub fn render(&self, record: &Record, timestamps: &Timestamps) -> String {
+        let mut out = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Literal(literal) => out.push_str(literal),
+                Part::Field(name) => out.push_str(&field(record, name, timestamps)),
+            }
+        }
+        out
+    }
+}
+
+fn field(record: &Record, name: &str, timestamps: &Timestamps) -> String {
+    fn optional<T: ToString>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or_default()
+    }
+    match name {
+        "time" => timestamps.format(record.time),
+        "spo2" => optional(record.spo2),
+        "heartrate" | "hr" => optional(record.heartrate),
+        "pi" => optional(record.pi.map(|pi| format!("{:.1}", pi))),
+        "battery" => optional(record.battery),
+        "status" => record.status.to_string(),
+        "signal" => record.signal.to_string(),
+        "quality" => record.quality.to_string(),
+        "device" => record.device.clone(),
+        "rssi" => optional(record.rssi),
+        "spo2_raw" => optional(record.extra.spo2_raw.flatten()),
+        "heartrate_raw" | "hr_raw" => optional(record.extra.heartrate_raw.flatten()),
+        "rmssd" => optional(record.extra.rmssd.flatten()),
+        "sdnn" => optional(record.extra.sdnn.flatten()),
+        "resp_rate" => optional(record.extra.resp_rate.flatten()),
+        _ => unreachable!("field names are checked when parsing"),
+    }
+}
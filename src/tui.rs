@@ -0,0 +1,279 @@
+//! A terminal dashboard for `--tui`, with the latest reading in big digits, the pleth waveform,
+//! a trend chart, and recent alarms, for keeping an eye on things from a terminal. The sinks keep getting readings
+//! as usual.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local, Utc};
+use ratatui::crossterm::event::{self, Event as TerminalEvent, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, List, ListItem, Paragraph, Sparkline};
+use ratatui::Frame;
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::alarm::Alarm;
+use crate::output::{Event, EventRecord, Record, WaveformRecord};
+use crate::sink::{Sink, SinkError};
+
+/// How far back the trend chart goes.
+const HISTORY: Duration = Duration::from_secs(10 * 60);
+/// Pleth samples kept, more than fit on any terminal. The device sends about 50 a second.
+const WAVEFORM_SAMPLES: usize = 500;
+/// How many events are listed.
+const RECENT_EVENTS: usize = 8;
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// What the dashboard shows, kept up to date by [`DashboardSink`].
+#[derive(Debug, Default)]
+pub struct State {
+    latest: Option<Record>,
+    /// Readings for the trend chart, oldest first.
+    history: VecDeque<Record>,
+    /// Pleth samples, oldest first.
+    waveform: VecDeque<u8>,
+    connected: HashSet<String>,
+    /// Alarms going off, by device.
+    alarms: HashSet<(String, Alarm)>,
+    events: VecDeque<EventRecord>,
+}
+
+pub type SharedState = Arc<Mutex<State>>;
+
+/// Feeds readings and events to the dashboard.
+pub struct DashboardSink(SharedState);
+
+impl DashboardSink {
+    pub fn new(state: SharedState) -> Self {
+        DashboardSink(state)
+    }
+}
+
+#[async_trait]
+impl Sink for DashboardSink {
+    fn name(&self) -> String {
+        "terminal dashboard".to_string()
+    }
+
+    async fn reading(&mut self, record: &Record) -> Result<(), SinkError> {
+        let mut state = self.0.lock().unwrap();
+        state.latest = Some(record.clone());
+        state.connected.insert(record.device.clone());
+        state.history.push_back(record.clone());
+        while let Some(oldest) = state.history.front() {
+            if (record.time - oldest.time).to_std().unwrap_or_default() <= HISTORY {
+                break;
+            }
+            state.history.pop_front();
+        }
+        Ok(())
+    }
+
+    async fn waveform(&mut self, record: &WaveformRecord) -> Result<(), SinkError> {
+        let mut state = self.0.lock().unwrap();
+        if state.waveform.len() == WAVEFORM_SAMPLES {
+            state.waveform.pop_front();
+        }
+        state.waveform.push_back(record.pleth);
+        Ok(())
+    }
+
+    async fn event(&mut self, record: &EventRecord) -> Result<(), SinkError> {
+        let mut state = self.0.lock().unwrap();
+        match record.event {
+            Event::Connected => {
+                state.connected.insert(record.device.clone());
+            }
+            Event::Disconnected => {
+                state.connected.remove(&record.device);
+                state.alarms.retain(|(device, _)| *device != record.device);
+            }
+            Event::Alarm { alarm, .. } => {
+                state.alarms.insert((record.device.clone(), alarm));
+            }
+            Event::AlarmCleared { alarm, .. } => {
+                state.alarms.remove(&(record.device.clone(), alarm));
+            }
+            _ => {}
+        }
+        state.events.push_front(record.clone());
+        state.events.truncate(RECENT_EVENTS);
+        Ok(())
+    }
+}
+
+/// Shows the dashboard until the user quits with q, Esc or Ctrl-C.
+pub async fn run(state: SharedState) -> io::Result<()> {
+    tokio::task::spawn_blocking(move || {
+        let mut terminal = ratatui::init();
+        let result = (|| loop {
+            terminal.draw(|frame| draw(frame, &state.lock().unwrap()))?;
+            if event::poll(REFRESH)? {
+                if let TerminalEvent::Key(key) = event::read()? {
+                    let ctrl_c = key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+                    if key.kind == KeyEventKind::Press && (matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c) {
+                        return Ok(());
+                    }
+                }
+            }
+        })();
+        ratatui::restore();
+        result
+    })
+    .await?
+}
+
+/// Digits 5 rows high, for reading from across the room.
+const DIGITS: [[&str; 5]; 10] = [
+    ["███", "█ █", "█ █", "█ █", "███"],
+    ["  █", "  █", "  █", "  █", "  █"],
+    ["███", "  █", "███", "█  ", "███"],
+    ["███", "  █", "███", "  █", "███"],
+    ["█ █", "█ █", "███", "  █", "  █"],
+    ["███", "█  ", "███", "  █", "███"],
+    ["███", "█  ", "███", "█ █", "███"],
+    ["███", "  █", "  █", "  █", "  █"],
+    ["███", "█ █", "███", "█ █", "███"],
+    ["███", "█ █", "███", "  █", "███"],
+];
+const DASH: [&str; 5] = ["   ", "   ", "███", "   ", "   "];
+
+fn big_number(value: Option<u8>) -> Text<'static> {
+    let glyphs: Vec<[&str; 5]> = match value {
+        Some(value) => value.to_string().bytes().map(|digit| DIGITS[usize::from(digit - b'0')]).collect(),
+        None => vec![DASH, DASH],
+    };
+    (0..5)
+        .map(|row| Line::from(glyphs.iter().map(|glyph| glyph[row]).collect::<Vec<_>>().join(" ")))
+        .collect()
+}
+
+fn draw(frame: &mut Frame, state: &State) {
+    let [numbers, waveform, chart, events] = Layout::vertical([
+        Constraint::Length(7),
+        Constraint::Length(7),
+        Constraint::Min(8),
+        Constraint::Length(RECENT_EVENTS as u16 + 2),
+    ])
+    .areas(frame.area());
+    let [spo2, heart_rate, status] =
+        Layout::horizontal([Constraint::Ratio(1, 3), Constraint::Ratio(1, 3), Constraint::Ratio(1, 3)]).areas(numbers);
+
+    let latest = state.latest.as_ref();
+    let alarming = |alarms: &[Alarm]| state.alarms.iter().any(|(_, alarm)| alarms.contains(alarm));
+    let number = |title: &'static str, value: Option<u8>, alarm: bool| {
+        let style = if alarm { Style::new().fg(Color::Red).bold() } else { Style::new().fg(Color::Green) };
+        Paragraph::new(big_number(value)).style(style).centered().block(Block::bordered().title(title))
+    };
+    frame.render_widget(number(" SpO2 % ", latest.and_then(|r| r.spo2), alarming(&[Alarm::Spo2Below])), spo2);
+    frame.render_widget(
+        number(
+            " Pulse bpm ",
+            latest.and_then(|r| r.heartrate),
+            alarming(&[Alarm::HeartRateAbove, Alarm::HeartRateBelow]),
+        ),
+        heart_rate,
+    );
+    draw_status(frame, state, status);
+    draw_waveform(frame, state, waveform);
+    draw_chart(frame, state, chart);
+
+    let items: Vec<ListItem> = state
+        .events
+        .iter()
+        .map(|record| {
+            let line = format!("{}  {}  {}", local_time(record.time), record.device, record.event);
+            let style = match record.event {
+                Event::Alarm { .. } | Event::Disconnected => Style::new().fg(Color::Red),
+                _ => Style::new(),
+            };
+            ListItem::new(line).style(style)
+        })
+        .collect();
+    frame.render_widget(List::new(items).block(Block::bordered().title(" Events ")), events);
+}
+
+fn local_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%H:%M:%S").to_string()
+}
+
+fn draw_status(frame: &mut Frame, state: &State, area: Rect) {
+    let latest = state.latest.as_ref();
+    let field = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let connection = match state.connected.len() {
+        0 => "disconnected".red(),
+        1 => "connected".green(),
+        devices => format!("{} connected", devices).green(),
+    };
+    let lines = vec![
+        Line::from(vec!["Device:  ".into(), connection]),
+        Line::from(format!("Battery: {}", field(latest.and_then(|r| r.battery).map(|bars| format!("{}/3", bars))))),
+        Line::from(format!("PI:      {}", field(latest.and_then(|r| r.pi).map(|pi| format!("{:.1}%", pi))))),
+        Line::from(format!(
+            "Signal:  {}",
+            field(latest.map(|r| format!("{}/8, {} ({})", r.signal, r.quality, r.status)))
+        )),
+        Line::from(format!("Updated: {}", field(latest.map(|r| local_time(r.time))))),
+    ];
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Status ")), area);
+}
+
+/// The most recent samples that fit, scrolling left like on the device's screen.
+fn draw_waveform(frame: &mut Frame, state: &State, area: Rect) {
+    let width = usize::from(area.width.saturating_sub(2));
+    let samples: Vec<u64> = state.waveform.iter().skip(state.waveform.len().saturating_sub(width)).map(|&pleth| u64::from(pleth)).collect();
+    let sparkline = Sparkline::default()
+        .block(Block::bordered().title(" Pleth "))
+        .data(&samples)
+        .max(127)
+        .style(Style::new().fg(Color::Yellow));
+    frame.render_widget(sparkline, area);
+}
+
+fn draw_chart(frame: &mut Frame, state: &State, area: Rect) {
+    let Some(now) = state.history.back().map(|record| record.time) else {
+        frame.render_widget(Block::bordered().title(" Trend "), area);
+        return;
+    };
+    // Seconds before the latest reading, negative so time runs left to right.
+    let points = |value: fn(&Record) -> Option<u8>| -> Vec<(f64, f64)> {
+        state
+            .history
+            .iter()
+            .filter_map(|record| {
+                let ago = (now - record.time).num_milliseconds() as f64 / 1000.0;
+                value(record).map(|value| (-ago, f64::from(value)))
+            })
+            .collect()
+    };
+    let spo2 = points(|record| record.spo2);
+    let heart_rate = points(|record| record.heartrate);
+    let datasets = vec![
+        Dataset::default()
+            .name("SpO2 %")
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::new().fg(Color::Cyan))
+            .data(&spo2),
+        Dataset::default()
+            .name("Pulse bpm")
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::new().fg(Color::Magenta))
+            .data(&heart_rate),
+    ];
+    let history = HISTORY.as_secs_f64();
+    let chart = Chart::new(datasets)
+        .block(Block::bordered().title(" Trend "))
+        .x_axis(
+            Axis::default()
+                .bounds([-history, 0.0])
+                .labels([format!("-{}m", HISTORY.as_secs() / 60), "now".to_string()]),
+        )
+        .y_axis(Axis::default().bounds([40.0, 160.0]).labels(["40", "100", "160"]));
+    frame.render_widget(chart, area);
+}